@@ -0,0 +1,103 @@
+//! 10万要素の文書で、スタイルの計算を逐次の走査と並列の走査で比べる
+//!
+//! 並列の走査はスタイル共有キャッシュを32個の兄弟ごとに分けるので、共有の率も比べる
+//!
+//! cargo bench --features parallel
+
+use std::time::{Duration, Instant};
+
+use toy_browser::{
+    css::{self, media::Device},
+    dom::{self, AttrMap, Node},
+    style::{style_tree_sequential_with_stats, style_tree_with_stats},
+};
+
+const SECTIONS: usize = 1000;
+const ITEMS_PER_SECTION: usize = 99;
+const RUNS: usize = 5;
+
+fn element(tag_name: &str, class: String, children: Vec<Node>) -> Node {
+    let attributes: AttrMap = [("class".to_string(), class)].into();
+    dom::elem(tag_name.to_string(), attributes, children)
+}
+
+/// `body` の下に `SECTIONS` 個の節があり、それぞれに `ITEMS_PER_SECTION` 個の段落がある文書
+fn document() -> Node {
+    let sections = (0..SECTIONS)
+        .map(|i| {
+            let items = (0..ITEMS_PER_SECTION)
+                .map(|j| {
+                    let text = dom::text(format!("item {}", j));
+                    element("p", format!("item c{}", i % 10 * 5 + j % 5), vec![text])
+                })
+                .collect();
+            element("div", format!("section s{}", i % 20), items)
+        })
+        .collect();
+    let body = element("body", String::new(), sections);
+    element("html", String::new(), vec![body])
+}
+
+fn stylesheet() -> String {
+    let mut source = String::from("body { margin: 8px; } .section { padding: 4px; }\n");
+    for i in 0..50 {
+        source.push_str(&format!(
+            ".c{} {{ margin-left: {}px; color: rgb({} 0 0); }}\n",
+            i,
+            i,
+            i * 5
+        ));
+    }
+    for i in 0..20 {
+        source.push_str(&format!(
+            ".s{} .item {{ padding-top: {}px; }} body > .s{} {{ width: calc(100% - {}em); }}\n",
+            i, i, i, i
+        ));
+    }
+    source
+}
+
+/// `RUNS` 回のうち最も速かった時間
+fn measure<T>(mut f: impl FnMut() -> T) -> (T, Duration) {
+    let mut best = Duration::MAX;
+    let mut result = None;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        let value = f();
+        best = best.min(start.elapsed());
+        result = Some(value);
+    }
+    (result.unwrap(), best)
+}
+
+fn main() {
+    let root = document();
+    let stylesheets = [css::parse(stylesheet())];
+    let device = Device::new(1280.0, 800.0);
+
+    let ((sequential, sequential_stats), sequential_time) =
+        measure(|| style_tree_sequential_with_stats(&root, &stylesheets, &device));
+    let ((parallel, stats), parallel_time) =
+        measure(|| style_tree_with_stats(&root, &stylesheets, &device));
+    assert!(
+        sequential == parallel,
+        "parallel styling changed the result"
+    );
+
+    println!(
+        "{} elements, {} threads",
+        1 + 1 + SECTIONS * (1 + ITEMS_PER_SECTION),
+        rayon::current_num_threads()
+    );
+    println!("sequential: {:?}", sequential_time);
+    println!("parallel:   {:?}", parallel_time);
+    println!(
+        "speedup:    {:.2}x",
+        sequential_time.as_secs_f64() / parallel_time.as_secs_f64()
+    );
+    println!(
+        "style sharing hit rate: {:.1}% sequential, {:.1}% parallel",
+        sequential_stats.sharing_hit_rate() * 100.0,
+        stats.sharing_hit_rate() * 100.0
+    );
+}
@@ -1,42 +1,329 @@
+use self::{
+    calc::CalcNode,
+    content::ContentFunction,
+    custom::{TokenStream, UnparsedValue},
+    import::ImportError,
+    media::MediaQueryList,
+    properties::PropertyId,
+    supports::SupportsCondition,
+};
+
+use std::{fmt, path::PathBuf};
+
+use crate::font::Font;
+
+pub mod calc;
+mod color;
+pub mod content;
+pub mod counter_style;
+pub mod custom;
+pub mod import;
+pub mod media;
+pub mod properties;
+pub mod serialize;
+mod shorthand;
+pub mod supports;
+
 #[derive(Debug, PartialEq)]
 pub struct Stylesheet {
-    pub rules: Vec<Rule>,
+    pub rules: Vec<CssRule>,
+    pub origin: Origin,
+    /// ファイルから読み込んだスタイルシートのパス
+    pub path: Option<PathBuf>,
+}
+
+/// スタイルシートの出どころ (`!important` でない宣言では後ろほど強い)
+///
+/// https://www.w3.org/TR/css-cascade-4/#cascading-origins
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Origin {
+    /// ブラウザが提供するデフォルトのスタイル
+    UserAgent,
+    /// ユーザーが指定したスタイル
+    User,
+    /// ページの作者が指定したスタイル
+    Author,
 }
 
 #[derive(Debug, PartialEq)]
+pub enum CssRule {
+    Style(Rule),
+    Media(MediaRule),
+    Import(ImportRule),
+    Layer(LayerRule),
+    /// `@layer a, b.c;` (レイヤーの順序だけを宣言する)
+    LayerStatement(Vec<LayerName>),
+    Supports(SupportsRule),
+}
+
+/// ドット区切りのカスケードレイヤー名 (`a.b` は `["a", "b"]`)
+pub type LayerName = Vec<String>;
+
+/// `@import <url> [layer | layer(<name>)]? <queries>;`
+#[derive(Debug, PartialEq)]
+pub struct ImportRule {
+    pub url: String,
+    pub layer: Option<ImportLayer>,
+    pub media: MediaQueryList,
+    /// 読み込んだスタイルシート。`import::load` で読み込むまでは `None`
+    pub stylesheet: Option<Result<Stylesheet, ImportError>>,
+}
+
+/// `@import` の読み込み先のカスケードレイヤー
+#[derive(Debug, PartialEq)]
+pub enum ImportLayer {
+    /// `layer`
+    Anonymous,
+    /// `layer(a.b)`
+    Named(LayerName),
+}
+
+/// `@layer <name>? { <rules> }`
+#[derive(Debug, PartialEq)]
+pub struct LayerRule {
+    /// 無名レイヤーは `None`
+    pub name: Option<LayerName>,
+    pub rules: Vec<CssRule>,
+}
+
+/// `@supports <condition> { <rules> }`
+#[derive(Debug, PartialEq)]
+pub struct SupportsRule {
+    pub condition: SupportsCondition,
+    pub rules: Vec<CssRule>,
+}
+
+/// `@media <queries> { <rules> }`
+#[derive(Debug, PartialEq)]
+pub struct MediaRule {
+    pub queries: MediaQueryList,
+    pub rules: Vec<CssRule>,
+}
+
+/// スタイル規則
+#[derive(Debug)]
 pub struct Rule {
     pub selectors: Vec<Selector>,
     pub declarations: Vec<Declaration>,
+    /// スタイルシートの中で規則が始まる位置
+    pub location: SourceLocation,
+}
+
+/// 位置は書き出し方で変わるので、比べない
+impl PartialEq for Rule {
+    fn eq(&self, other: &Rule) -> bool {
+        self.selectors == other.selectors && self.declarations == other.declarations
+    }
+}
+
+/// ソース中の位置 (どちらも1始まり)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SourceLocation {
+    pub line: usize,
+    /// 文字単位
+    pub column: usize,
 }
 
 #[derive(Debug, PartialEq)]
 pub enum Selector {
     Simple(SimpleSelector),
+    /// 結合子で複合セレクタを繋いだもの
+    Complex(ComplexSelector),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ComplexSelector {
+    /// 最も右の複合セレクタ (一致する要素そのもの)
+    pub subject: SimpleSelector,
+    /// 主語から左へ順に、結合子とその左側の複合セレクタ
+    pub ancestors: Vec<(Combinator, SimpleSelector)>,
+}
+
+/// https://www.w3.org/TR/selectors-4/#combinators
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Combinator {
+    /// 空白
+    Descendant,
+    /// `>`
+    Child,
 }
 
 #[derive(Debug, PartialEq)]
 pub struct SimpleSelector {
     pub tag_name: Option<String>,
+    pub pseudo_classes: Vec<PseudoClass>,
+    /// 主語の複合セレクタにだけ付く
+    pub pseudo_element: Option<PseudoElement>,
     pub id: Option<String>,
     pub class: Vec<String>,
 }
 
+/// 要素の状態で一致が変わる擬似クラス
+///
+/// https://www.w3.org/TR/selectors-4/#useraction-pseudos
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PseudoClass {
+    Hover,
+    Active,
+    Focus,
+    FocusVisible,
+    Checked,
+    Disabled,
+    Enabled,
+}
+
+impl PseudoClass {
+    /// 擬似クラス名 (大文字小文字は区別しない) から擬似クラスを得る
+    pub fn from_name(name: &str) -> Option<PseudoClass> {
+        Some(match &*name.to_ascii_lowercase() {
+            "hover" => PseudoClass::Hover,
+            "active" => PseudoClass::Active,
+            "focus" => PseudoClass::Focus,
+            "focus-visible" => PseudoClass::FocusVisible,
+            "checked" => PseudoClass::Checked,
+            "disabled" => PseudoClass::Disabled,
+            "enabled" => PseudoClass::Enabled,
+            _ => return None,
+        })
+    }
+
+    /// `:` を除いた擬似クラス名
+    pub fn name(self) -> &'static str {
+        match self {
+            PseudoClass::Hover => "hover",
+            PseudoClass::Active => "active",
+            PseudoClass::Focus => "focus",
+            PseudoClass::FocusVisible => "focus-visible",
+            PseudoClass::Checked => "checked",
+            PseudoClass::Disabled => "disabled",
+            PseudoClass::Enabled => "enabled",
+        }
+    }
+}
+
+/// https://www.w3.org/TR/css-pseudo-4/
+///
+/// `::first-line` と `::first-letter` はボックスを分割できないので扱わず、
+/// それを含むセレクタは未知の擬似要素と同じく無効になる
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PseudoElement {
+    Before,
+    After,
+}
+
+impl PseudoElement {
+    /// 擬似要素名 (大文字小文字は区別しない) から擬似要素を得る
+    pub fn from_name(name: &str) -> Option<PseudoElement> {
+        Some(match &*name.to_ascii_lowercase() {
+            "before" => PseudoElement::Before,
+            "after" => PseudoElement::After,
+            _ => return None,
+        })
+    }
+
+    /// `::` を除いた擬似要素名
+    pub fn name(self) -> &'static str {
+        match self {
+            PseudoElement::Before => "before",
+            PseudoElement::After => "after",
+        }
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub struct Declaration {
     pub name: String,
     pub value: Value,
+    /// `!important` が付いているか
+    pub important: bool,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Value {
     Keyword(String),
     Length(f32, Unit),
+    Number(f32),
+    Percentage(f32),
     ColorValue(Color),
+    /// 空白区切りの成分値の並び
+    List(Vec<Value>),
+    /// カンマ区切りの並び。各要素は1つの成分値か `List`
+    CommaList(Vec<Value>),
+    /// 引用符を除いた文字列
+    String(String),
+    /// `calc()` などの数学関数
+    Calc(CalcNode),
+    /// `attr()` などの `content` プロパティの関数
+    Content(ContentFunction),
+    /// カスタムプロパティの値か、`var()` を含む値
+    Unparsed(UnparsedValue),
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Unit {
+    // 絶対長さ
     Px,
+    Cm,
+    Mm,
+    Q,
+    In,
+    Pt,
+    Pc,
+    // フォント相対長さ
+    Em,
+    Rem,
+    Ex,
+    Ch,
+    // ビューポート相対長さ
+    Vw,
+    Vh,
+    Vmin,
+    Vmax,
+}
+
+/// 相対長さとパーセンテージを解決するための基準値
+///
+/// https://www.w3.org/TR/css-values-4/#lengths
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LengthContext {
+    /// `em` の基準となる要素のフォントサイズ
+    pub font_size: f32,
+    /// `ex` の基準となる、要素のフォントの x-height の em に対する比
+    pub x_height: f32,
+    /// `ch` の基準となる、要素のフォントの "0" の送り幅の em に対する比
+    pub zero_advance: f32,
+    /// `rem` の基準となるルート要素のフォントサイズ
+    pub root_font_size: f32,
+    /// パーセンテージの基準 (通常はコンテナブロックの幅)
+    pub percent_base: f32,
+    pub viewport_width: f32,
+    pub viewport_height: f32,
+}
+
+/// x-height と "0" の送り幅は、フォントから分からないときと同じく 0.5em とする
+impl Default for LengthContext {
+    fn default() -> LengthContext {
+        LengthContext {
+            font_size: 0.0,
+            x_height: 0.5,
+            zero_advance: 0.5,
+            root_font_size: 0.0,
+            percent_base: 0.0,
+            viewport_width: 0.0,
+            viewport_height: 0.0,
+        }
+    }
+}
+
+impl LengthContext {
+    /// `ex` と `ch` の基準を `font` のものにする
+    pub fn with_font(self, font: &Font) -> LengthContext {
+        LengthContext {
+            x_height: font.x_height_ratio(),
+            zero_advance: font.zero_advance_ratio(),
+            ..self
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -53,63 +340,375 @@ pub type Specificity = (usize, usize, usize);
 
 impl Selector {
     pub fn specificity(&self) -> Specificity {
-        let Selector::Simple(ref simple) = *self;
-        let a = simple.id.iter().count();
-        let b = simple.class.len();
-        let c = simple.tag_name.iter().count();
-        (a, b, c)
+        self.compounds().fold((0, 0, 0), |(a, b, c), simple| {
+            (
+                a + simple.id.iter().count(),
+                b + simple.class.len() + simple.pseudo_classes.len(),
+                c + simple.tag_name.iter().count() + simple.pseudo_element.iter().count(),
+            )
+        })
+    }
+
+    /// 一致する要素そのものに対する複合セレクタ
+    pub fn subject(&self) -> &SimpleSelector {
+        match *self {
+            Selector::Simple(ref simple) => simple,
+            Selector::Complex(ref complex) => &complex.subject,
+        }
+    }
+
+    /// 一致するのが擬似要素なら、その種類
+    pub fn pseudo_element(&self) -> Option<PseudoElement> {
+        self.subject().pseudo_element
+    }
+
+    /// 主語から左へ順にすべての複合セレクタ
+    pub fn compounds(&self) -> impl Iterator<Item = &SimpleSelector> {
+        let ancestors = match *self {
+            Selector::Simple(_) => &[][..],
+            Selector::Complex(ref complex) => &complex.ancestors[..],
+        };
+        std::iter::once(self.subject()).chain(ancestors.iter().map(|(_, simple)| simple))
     }
 }
 
 impl Value {
-    pub fn to_px(&self) -> f32 {
+    /// 長さとパーセンテージを `ctx` に基づいてpx単位に変換する
+    ///
+    /// 長さでない値は0になる
+    pub fn to_px(&self, ctx: &LengthContext) -> f32 {
         match *self {
-            Value::Length(f, Unit::Px) => f,
+            Value::Length(f, unit) => f * unit.to_px(ctx),
+            Value::Percentage(f) => f / 100.0 * ctx.percent_base,
+            Value::Calc(ref calc) => calc.to_px(ctx),
             _ => 0.0,
         }
     }
 }
 
+impl Unit {
+    /// 単位名 (大文字小文字は区別しない) から単位を得る
+    pub fn from_name(name: &str) -> Option<Unit> {
+        Some(match &*name.to_ascii_lowercase() {
+            "px" => Unit::Px,
+            "cm" => Unit::Cm,
+            "mm" => Unit::Mm,
+            "q" => Unit::Q,
+            "in" => Unit::In,
+            "pt" => Unit::Pt,
+            "pc" => Unit::Pc,
+            "em" => Unit::Em,
+            "rem" => Unit::Rem,
+            "ex" => Unit::Ex,
+            "ch" => Unit::Ch,
+            "vw" => Unit::Vw,
+            "vh" => Unit::Vh,
+            "vmin" => Unit::Vmin,
+            "vmax" => Unit::Vmax,
+            _ => return None,
+        })
+    }
+
+    /// 単位名 (小文字)
+    pub fn name(self) -> &'static str {
+        match self {
+            Unit::Px => "px",
+            Unit::Cm => "cm",
+            Unit::Mm => "mm",
+            Unit::Q => "q",
+            Unit::In => "in",
+            Unit::Pt => "pt",
+            Unit::Pc => "pc",
+            Unit::Em => "em",
+            Unit::Rem => "rem",
+            Unit::Ex => "ex",
+            Unit::Ch => "ch",
+            Unit::Vw => "vw",
+            Unit::Vh => "vh",
+            Unit::Vmin => "vmin",
+            Unit::Vmax => "vmax",
+        }
+    }
+
+    /// この単位1つ分のpx数
+    /// 文脈によらずにpx数が決まる単位かどうか
+    pub fn is_absolute(self) -> bool {
+        matches!(
+            self,
+            Unit::Px | Unit::Cm | Unit::Mm | Unit::Q | Unit::In | Unit::Pt | Unit::Pc
+        )
+    }
+
+    pub fn to_px(self, ctx: &LengthContext) -> f32 {
+        const PX_PER_IN: f32 = 96.0;
+        const PX_PER_CM: f32 = PX_PER_IN / 2.54;
+        match self {
+            Unit::Px => 1.0,
+            Unit::Cm => PX_PER_CM,
+            Unit::Mm => PX_PER_CM / 10.0,
+            Unit::Q => PX_PER_CM / 40.0,
+            Unit::In => PX_PER_IN,
+            Unit::Pt => PX_PER_IN / 72.0,
+            Unit::Pc => PX_PER_IN / 6.0,
+            Unit::Em => ctx.font_size,
+            Unit::Rem => ctx.root_font_size,
+            Unit::Ex => ctx.x_height * ctx.font_size,
+            Unit::Ch => ctx.zero_advance * ctx.font_size,
+            Unit::Vw => ctx.viewport_width / 100.0,
+            Unit::Vh => ctx.viewport_height / 100.0,
+            Unit::Vmin => ctx.viewport_width.min(ctx.viewport_height) / 100.0,
+            Unit::Vmax => ctx.viewport_width.max(ctx.viewport_height) / 100.0,
+        }
+    }
+}
+
+/// 作者スタイルシートとして解析する
 pub fn parse(source: String) -> Stylesheet {
-    let mut parser = Parser {
-        pos: 0,
-        input: source,
+    parse_with_origin(source, Origin::Author)
+}
+
+pub fn parse_with_origin(source: String, origin: Origin) -> Stylesheet {
+    parse_with_errors(source, origin).0
+}
+
+/// 解析し、無視した宣言をエラーとして返す
+pub fn parse_with_errors(source: String, origin: Origin) -> (Stylesheet, Vec<ParseError>) {
+    let mut parser = Parser::new(source);
+    let stylesheet = Stylesheet {
+        rules: parser.parse_rules(true),
+        origin,
+        path: None,
     };
-    Stylesheet {
-        rules: parser.parse_rules(),
+    (stylesheet, parser.errors)
+}
+
+/// 無視した宣言
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    /// 宣言のある行 (1始まり)
+    pub line: usize,
+    pub kind: ParseErrorKind,
+    pub property: String,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParseErrorKind {
+    /// `properties::PROPERTIES` にもショートハンドにもないプロパティ
+    UnknownProperty,
+    /// プロパティの文法に合わない値
+    InvalidValue,
+    /// 未知の擬似クラスや擬似要素を含むセレクタ。規則全体を無視する
+    InvalidSelector,
+    /// 文法に合わないアットルール。`property` はその名前 (`@import` など) で、規則全体を無視する
+    InvalidRule,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            ParseErrorKind::UnknownProperty => {
+                write!(f, "line {}: unknown property {}", self.line, self.property)
+            }
+            ParseErrorKind::InvalidValue => write!(
+                f,
+                "line {}: invalid value \"{}\" for {}",
+                self.line, self.value, self.property
+            ),
+            ParseErrorKind::InvalidSelector => {
+                write!(f, "line {}: invalid selector \"{}\"", self.line, self.value)
+            }
+            ParseErrorKind::InvalidRule => write!(
+                f,
+                "line {}: invalid {} rule \"{}\"",
+                self.line, self.property, self.value
+            ),
+        }
     }
 }
 
 struct Parser {
     pos: usize,
     input: String,
+    errors: Vec<ParseError>,
+    /// `location` が最後に数えた位置と、その位置の行番号と行頭
+    scanned: usize,
+    line: usize,
+    line_start: usize,
 }
 
 impl Parser {
-    fn parse_rules(&mut self) -> Vec<Rule> {
+    fn new(input: String) -> Parser {
+        Parser {
+            pos: 0,
+            input,
+            errors: Vec::new(),
+            scanned: 0,
+            line: 1,
+            line_start: 0,
+        }
+    }
+
+    /// 現在の位置。前回数えた位置から先の改行だけを数える
+    fn location(&mut self) -> SourceLocation {
+        if self.pos < self.scanned {
+            self.scanned = 0;
+            self.line = 1;
+            self.line_start = 0;
+        }
+        for (i, c) in self.input[self.scanned..self.pos].char_indices() {
+            if c == '\n' {
+                self.line += 1;
+                self.line_start = self.scanned + i + 1;
+            }
+        }
+        self.scanned = self.pos;
+        SourceLocation {
+            line: self.line,
+            column: self.input[self.line_start..self.pos].chars().count() + 1,
+        }
+    }
+
+    /// 入力の終わりか、ブロックを閉じる `}` までの規則を読む
+    ///
+    /// `@import` はトップレベルで、`@layer` 文以外の規則より前にあるときだけ有効
+    fn parse_rules(&mut self, top_level: bool) -> Vec<CssRule> {
         let mut rules = Vec::new();
+        let mut allow_import = top_level;
         loop {
             self.consume_whitespace();
-            if self.eof() {
+            if self.eof() || self.next_char() == '}' {
                 break;
             }
-            rules.push(self.parse_rule());
+            if let Some(rule) = self.parse_rule(allow_import) {
+                allow_import &= matches!(rule, CssRule::Import(_) | CssRule::LayerStatement(_));
+                rules.push(rule);
+            }
         }
         rules
     }
 
-    fn parse_rule(&mut self) -> Rule {
-        Rule {
-            selectors: self.parse_selectors(),
+    fn parse_rule(&mut self, allow_import: bool) -> Option<CssRule> {
+        if self.next_char() == '@' {
+            return self.parse_at_rule(allow_import);
+        }
+        self.parse_style_rule().map(CssRule::Style)
+    }
+
+    /// セレクタが無効なら、エラーを記録して規則のブロックを読み飛ばす
+    fn parse_style_rule(&mut self) -> Option<Rule> {
+        let location = self.location();
+        let start = self.pos;
+        let Some(selectors) = self.parse_selectors() else {
+            let end = self.input[start..]
+                .find('{')
+                .map_or(self.input.len(), |i| start + i);
+            self.errors.push(ParseError {
+                line: location.line,
+                kind: ParseErrorKind::InvalidSelector,
+                property: String::new(),
+                value: self.input[start..end].trim().to_string(),
+            });
+            self.pos = start;
+            self.skip_block();
+            return None;
+        };
+        Some(Rule {
+            selectors,
             declarations: self.parse_declarations(),
+            location,
+        })
+    }
+
+    /// 未知のアットルールは読み飛ばして `None` を返す
+    fn parse_at_rule(&mut self, allow_import: bool) -> Option<CssRule> {
+        assert_eq!(self.consume_char(), '@');
+        let name = self.parse_identifier().to_ascii_lowercase();
+        self.consume_whitespace();
+        match &*name {
+            "media" => Some(CssRule::Media(MediaRule {
+                queries: self.parse_media_query_list(),
+                rules: self.parse_nested_rules(),
+            })),
+            "import" if allow_import => self.parse_import_rule().map(CssRule::Import),
+            "layer" => Some(self.parse_layer_rule()),
+            "supports" => {
+                let condition = self.parse_supports_condition();
+                self.consume_whitespace();
+                Some(CssRule::Supports(SupportsRule {
+                    condition,
+                    rules: self.parse_nested_rules(),
+                }))
+            }
+            _ => {
+                self.skip_at_rule();
+                None
+            }
         }
     }
 
-    fn parse_selectors(&mut self) -> Vec<Selector> {
-        let mut selectors = Vec::new();
+    /// `@layer` に続くブロックか、カンマ区切りのレイヤー名の文を読む
+    fn parse_layer_rule(&mut self) -> CssRule {
+        if self.next_char() == '{' {
+            return CssRule::Layer(LayerRule {
+                name: None,
+                rules: self.parse_nested_rules(),
+            });
+        }
+        let mut names = vec![self.parse_layer_name()];
         loop {
-            selectors.push(Selector::Simple(self.parse_simple_selector()));
             self.consume_whitespace();
+            match self.next_char() {
+                ',' => {
+                    self.consume_char();
+                    self.consume_whitespace();
+                    names.push(self.parse_layer_name());
+                }
+                ';' => {
+                    self.consume_char();
+                    return CssRule::LayerStatement(names);
+                }
+                _ => break,
+            }
+        }
+        assert_eq!(names.len(), 1, "@layer block must have a single name");
+        CssRule::Layer(LayerRule {
+            name: names.pop(),
+            rules: self.parse_nested_rules(),
+        })
+    }
+
+    /// `{ <rules> }` を読む
+    fn parse_nested_rules(&mut self) -> Vec<CssRule> {
+        assert_eq!(self.consume_char(), '{');
+        let rules = self.parse_rules(false);
+        assert_eq!(self.consume_char(), '}');
+        rules
+    }
+
+    /// `;` で終わる文か、対応する `}` で閉じるブロックまでを読み飛ばす
+    fn skip_at_rule(&mut self) {
+        let mut depth = 0;
+        while !self.eof() {
+            match self.consume_char() {
+                ';' if depth == 0 => break,
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// `{` の手前までのセレクタのリスト。1つでも無効なら `None`
+    fn parse_selectors(&mut self) -> Option<Vec<Selector>> {
+        let mut selectors = Vec::new();
+        loop {
+            selectors.push(self.parse_selector()?);
             match self.next_char() {
                 ',' => {
                     self.consume_char();
@@ -120,12 +719,73 @@ impl Parser {
             }
         }
         selectors.sort_by_key(|b| std::cmp::Reverse(b.specificity()));
-        selectors
+        Some(selectors)
+    }
+
+    /// 最初の `{` から対応する `}` までを読み飛ばす
+    fn skip_block(&mut self) {
+        let mut depth = 0;
+        while !self.eof() {
+            match self.consume_char() {
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// 複合セレクタを結合子で繋いだものを、`,` か `{` の手前まで読む
+    fn parse_selector(&mut self) -> Option<Selector> {
+        let mut compounds = vec![self.parse_compound_selector()?];
+        let mut combinators = Vec::new();
+        loop {
+            self.consume_whitespace();
+            // 擬似要素の後には何も続かない
+            if self.eof() || compounds.last().unwrap().pseudo_element.is_some() {
+                break;
+            }
+            let combinator = match self.next_char() {
+                '>' => {
+                    self.consume_char();
+                    self.consume_whitespace();
+                    Combinator::Child
+                }
+                '#' | '.' | '*' | ':' => Combinator::Descendant,
+                c if valid_identifier_char(c) => Combinator::Descendant,
+                _ => break,
+            };
+            combinators.push(combinator);
+            compounds.push(self.parse_compound_selector()?);
+        }
+
+        let subject = compounds.pop().unwrap();
+        if combinators.is_empty() {
+            return Some(Selector::Simple(subject));
+        }
+        Some(Selector::Complex(ComplexSelector {
+            subject,
+            ancestors: combinators.into_iter().zip(compounds).rev().collect(),
+        }))
     }
 
-    fn parse_simple_selector(&mut self) -> SimpleSelector {
+    /// 結合子の前後の複合セレクタ。空なら `None`
+    fn parse_compound_selector(&mut self) -> Option<SimpleSelector> {
+        let start = self.pos;
+        let selector = self.parse_simple_selector()?;
+        (self.pos > start).then_some(selector)
+    }
+
+    /// 未知の擬似クラスや擬似要素を含めば `None`
+    fn parse_simple_selector(&mut self) -> Option<SimpleSelector> {
         let mut selector = SimpleSelector {
             tag_name: None,
+            pseudo_classes: Vec::new(),
+            pseudo_element: None,
             id: None,
             class: Vec::new(),
         };
@@ -142,13 +802,31 @@ impl Parser {
                 '*' => {
                     self.consume_char();
                 }
+                ':' => {
+                    self.consume_char();
+                    let double_colon = !self.eof() && self.next_char() == ':';
+                    if double_colon {
+                        self.consume_char();
+                    }
+                    let name = self.parse_identifier();
+                    let pseudo_class = PseudoClass::from_name(&name).filter(|_| !double_colon);
+                    // `:before` などの古い表記も受け付ける
+                    match (pseudo_class, PseudoElement::from_name(&name)) {
+                        (Some(pseudo_class), _) => selector.pseudo_classes.push(pseudo_class),
+                        (None, Some(pseudo_element)) => {
+                            selector.pseudo_element = Some(pseudo_element);
+                            break;
+                        }
+                        (None, None) => return None,
+                    }
+                }
                 c if valid_identifier_char(c) => {
                     selector.tag_name = Some(self.parse_identifier());
                 }
                 _ => break,
             }
         }
-        selector
+        Some(selector)
     }
 
     fn parse_declarations(&mut self) -> Vec<Declaration> {
@@ -160,73 +838,237 @@ impl Parser {
                 self.consume_char();
                 break;
             }
-            declaration.push(self.parse_declaration());
+            declaration.extend(self.parse_declaration());
         }
         declaration
     }
 
-    fn parse_declaration(&mut self) -> Declaration {
-        let property_type = self.parse_identifier();
+    /// 1つの宣言を読み、ショートハンドならロングハンドに展開して返す
+    ///
+    /// 未知のプロパティや文法に合わない値の宣言は、エラーを記録して無視する
+    fn parse_declaration(&mut self) -> Vec<Declaration> {
+        let location = self.location();
+        let mut property_type = self.parse_identifier();
+        if !custom::is_custom_property(&property_type) {
+            property_type.make_ascii_lowercase();
+        }
         self.consume_whitespace();
         assert_eq!(self.consume_char(), ':');
         self.consume_whitespace();
 
-        let value = self.parse_value();
-        self.consume_whitespace();
+        let text = self.parse_raw_value();
+        let important = self.parse_important();
         assert_eq!(self.consume_char(), ';');
 
-        Declaration {
-            name: property_type,
-            value,
+        match expand_declaration(&property_type, &text) {
+            Ok(mut declarations) => {
+                for declaration in &mut declarations {
+                    declaration.important = important;
+                }
+                declarations
+            }
+            Err(kind) => {
+                self.errors.push(ParseError {
+                    line: location.line,
+                    kind,
+                    property: property_type,
+                    value: text,
+                });
+                Vec::new()
+            }
         }
     }
 
-    fn parse_value(&mut self) -> Value {
+    /// 入力の終わりか `;` か `!important` までの空白区切りの成分値を読む
+    ///
+    /// 値の文法に合わなければ `None` を返す
+    /// カンマを含む値は1つの `Value::CommaList` になる
+    fn parse_values(&mut self) -> Option<Vec<Value>> {
+        let mut items = Vec::new();
+        let mut values = Vec::new();
+        loop {
+            self.consume_whitespace();
+            if self.eof() || matches!(self.next_char(), ';' | '!') {
+                break;
+            }
+            if self.next_char() == ',' {
+                self.consume_char();
+                if values.is_empty() {
+                    return None;
+                }
+                items.push(shorthand::into_value(std::mem::take(&mut values)));
+                continue;
+            }
+            values.push(self.parse_value()?);
+        }
+        if items.is_empty() {
+            return Some(values);
+        }
+        if values.is_empty() {
+            return None;
+        }
+        items.push(shorthand::into_value(values));
+        Some(vec![Value::CommaList(items)])
+    }
+
+    fn parse_important(&mut self) -> bool {
+        if self.next_char() != '!' {
+            return false;
+        }
+        self.consume_char();
+        self.consume_whitespace();
+        let keyword = self.parse_identifier();
+        assert!(
+            keyword.eq_ignore_ascii_case("important"),
+            "Unexpected !{} in declaration",
+            keyword
+        );
+        self.consume_whitespace();
+        true
+    }
+
+    fn parse_value(&mut self) -> Option<Value> {
         match self.next_char() {
-            '0'..='9' => self.parse_length(),
+            '0'..='9' | '.' => self.parse_length(),
+            '+' | '-' if self.starts_number() => self.parse_length(),
             '#' => self.parse_color(),
-            _ => Value::Keyword(self.parse_identifier()),
+            '"' | '\'' => Some(Value::String(self.parse_string())),
+            c if valid_identifier_char(c) => self.parse_keyword_or_function(),
+            _ => None,
         }
     }
 
-    fn parse_length(&mut self) -> Value {
-        Value::Length(self.parse_float(), self.parse_unit())
+    fn parse_keyword_or_function(&mut self) -> Option<Value> {
+        let name = self.parse_identifier();
+        if !self.eof() && self.next_char() == '(' {
+            return self.parse_function(&name);
+        }
+        if name.eq_ignore_ascii_case("currentcolor") {
+            return Some(Value::Keyword(name.to_ascii_lowercase()));
+        }
+        Some(match color::named(&name) {
+            Some(color) => Value::ColorValue(color),
+            None => Value::Keyword(name),
+        })
+    }
+
+    fn parse_function(&mut self, name: &str) -> Option<Value> {
+        let args = self.parse_parenthesized();
+        if calc::is_math_function(name) {
+            return calc::parse(name, &args).map(Value::Calc);
+        }
+        if let Some(function) = content::parse_function(name, &args) {
+            return Some(Value::Content(function));
+        }
+        color::parse_function(name, &args).map(Value::ColorValue)
+    }
+
+    /// 対応する閉じ括弧までの中身を読む
+    fn parse_parenthesized(&mut self) -> String {
+        assert_eq!(self.consume_char(), '(');
+        let mut depth = 0;
+        let mut contents = String::new();
+        loop {
+            let c = self.consume_char();
+            match c {
+                '(' => depth += 1,
+                ')' if depth == 0 => break,
+                ')' => depth -= 1,
+                _ => {}
+            }
+            contents.push(c);
+        }
+        contents
+    }
+
+    fn parse_length(&mut self) -> Option<Value> {
+        let num = self.parse_float();
+        if self.eof() {
+            return Some(Value::Number(num));
+        }
+        Some(match self.next_char() {
+            '%' => {
+                self.consume_char();
+                Value::Percentage(num)
+            }
+            c if valid_identifier_char(c) => Value::Length(num, self.parse_unit()?),
+            _ => Value::Number(num),
+        })
     }
 
     fn parse_float(&mut self) -> f32 {
-        let s = self.consume_while(|c| matches!(c, '0'..='9' | '.'));
+        let mut s = String::new();
+        if matches!(self.next_char(), '+' | '-') {
+            s.push(self.consume_char());
+        }
+        s.push_str(&self.consume_while(|c| matches!(c, '0'..='9' | '.')));
         s.parse().unwrap()
     }
 
-    fn parse_unit(&mut self) -> Unit {
-        match &*self.parse_identifier().to_ascii_lowercase() {
-            "px" => Unit::Px,
-            _ => panic!("unrecognized unit"),
+    /// 符号の直後に数字が続くかどうか
+    fn starts_number(&self) -> bool {
+        let mut chars = self.input[self.pos..].chars().skip(1);
+        match chars.next() {
+            Some('0'..='9') => true,
+            Some('.') => matches!(chars.next(), Some('0'..='9')),
+            _ => false,
         }
     }
 
-    fn parse_color(&mut self) -> Value {
-        assert_eq!(self.consume_char(), '#');
-        Value::ColorValue(Color {
-            r: self.parse_hex_pair(),
-            g: self.parse_hex_pair(),
-            b: self.parse_hex_pair(),
-            a: 255,
-        })
+    fn parse_unit(&mut self) -> Option<Unit> {
+        Unit::from_name(&self.parse_identifier())
     }
 
-    fn parse_hex_pair(&mut self) -> u8 {
-        let s = &self.input[self.pos..self.pos + 2];
-        self.pos += 2;
-        u8::from_str_radix(s, 16).unwrap()
+    fn parse_color(&mut self) -> Option<Value> {
+        assert_eq!(self.consume_char(), '#');
+        color::parse_hex(&self.parse_identifier()).map(Value::ColorValue)
     }
 
     fn parse_identifier(&mut self) -> String {
         self.consume_while(valid_identifier_char)
     }
 
+    /// 引用符で囲まれた文字列。`\` に続く文字はそのまま読む
+    fn parse_string(&mut self) -> String {
+        self.try_parse_string().expect("Unterminated string")
+    }
+
+    /// 引用符で始まらないか、閉じる前に入力が終われば `None`
+    fn try_parse_string(&mut self) -> Option<String> {
+        let quote = self.consume_char();
+        if quote != '"' && quote != '\'' {
+            return None;
+        }
+        let mut result = String::new();
+        loop {
+            if self.eof() {
+                return None;
+            }
+            match self.consume_char() {
+                c if c == quote => break,
+                '\\' if !self.eof() => result.push(self.consume_char()),
+                c => result.push(c),
+            }
+        }
+        Some(result)
+    }
+
+    /// 空白とコメントを読み飛ばす
     fn consume_whitespace(&mut self) {
-        self.consume_while(char::is_whitespace);
+        loop {
+            self.consume_while(char::is_whitespace);
+            if !self.starts_with("/*") {
+                break;
+            }
+            match self.input[self.pos + 2..].find("*/") {
+                Some(end) => self.pos += 2 + end + 2,
+                None => self.pos = self.input.len(),
+            }
+        }
+    }
+
+    fn starts_with(&self, s: &str) -> bool {
+        self.input[self.pos..].starts_with(s)
     }
 
     fn consume_while<F>(&mut self, test: F) -> String
@@ -240,15 +1082,20 @@ impl Parser {
         result
     }
 
+    /// 次の文字が `c` なら読む
+    fn consume_expected(&mut self, c: char) -> Option<()> {
+        (!self.eof() && self.next_char() == c).then(|| {
+            self.consume_char();
+        })
+    }
+
     fn consume_char(&mut self) -> char {
-        let mut iter = self.input[self.pos..].char_indices();
-        let (_, cur_char) = iter.next().unwrap();
-        let (next_pos, _) = iter.next().unwrap_or((1, ' '));
-        self.pos += next_pos;
+        let cur_char = self.next_char();
+        self.pos += cur_char.len_utf8();
         cur_char
     }
 
-    fn next_char(&mut self) -> char {
+    fn next_char(&self) -> char {
         self.input[self.pos..].chars().next().unwrap()
     }
 
@@ -257,6 +1104,44 @@ impl Parser {
     }
 }
 
+/// 宣言の値を解析してロングハンドに展開し、各ロングハンドの文法に合うか検査する
+///
+/// カスタムプロパティと `var()` を含む値は、解析せずにトークン列のまま残す
+fn expand_declaration(name: &str, text: &str) -> Result<Vec<Declaration>, ParseErrorKind> {
+    if !custom::is_custom_property(name)
+        && PropertyId::from_name(name).is_none()
+        && shorthand::longhands(name).is_none()
+    {
+        return Err(ParseErrorKind::UnknownProperty);
+    }
+    let tokens = TokenStream::parse(text).ok_or(ParseErrorKind::InvalidValue)?;
+    if custom::is_custom_property(name) {
+        return Ok(vec![shorthand::longhand(
+            name.to_string(),
+            Value::Unparsed(UnparsedValue {
+                tokens,
+                shorthand: None,
+            }),
+        )]);
+    }
+    if tokens.has_references() {
+        return Ok(shorthand::expand_unparsed(name, tokens));
+    }
+
+    let mut parser = Parser::new(text.to_string());
+    let values = parser.parse_values().ok_or(ParseErrorKind::InvalidValue)?;
+    let declarations = shorthand::expand(name, values).ok_or(ParseErrorKind::InvalidValue)?;
+    let valid = declarations.iter().all(|declaration| {
+        PropertyId::from_name(&declaration.name)
+            .is_some_and(|id| id.def().accepts(&declaration.value))
+    });
+    if valid {
+        Ok(declarations)
+    } else {
+        Err(ParseErrorKind::InvalidValue)
+    }
+}
+
 fn valid_identifier_char(c: char) -> bool {
     matches!(c, 'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_')
 }
@@ -265,6 +1150,44 @@ fn valid_identifier_char(c: char) -> bool {
 mod tests {
     use super::*;
 
+    fn style_rule(rule: &CssRule) -> &Rule {
+        match rule {
+            CssRule::Style(rule) => rule,
+            _ => panic!("not a style rule: {:?}", rule),
+        }
+    }
+
+    fn declaration(name: &str, value: Value) -> Declaration {
+        Declaration {
+            name: name.to_string(),
+            value,
+            important: false,
+        }
+    }
+
+    /// 4辺のショートハンドに1つの値を書いたときのロングハンド
+    fn sides(prefix: &str, suffix: &str, value: Value) -> Vec<Declaration> {
+        ["top", "right", "bottom", "left"]
+            .iter()
+            .map(|side| declaration(&format!("{}{}{}", prefix, side, suffix), value.clone()))
+            .collect()
+    }
+
+    /// `background` に色だけを書いたときのロングハンド
+    fn background(color: Color) -> Vec<Declaration> {
+        let keyword = |k: &str| Value::Keyword(k.to_string());
+        vec![
+            declaration("background-color", Value::ColorValue(color)),
+            declaration("background-image", keyword("none")),
+            declaration("background-repeat", keyword("repeat")),
+            declaration("background-attachment", keyword("scroll")),
+            declaration(
+                "background-position",
+                Value::List(vec![keyword("left"), keyword("top")]),
+            ),
+        ]
+    }
+
     #[test]
     fn test_parse_id() {
         let source = String::from(
@@ -276,17 +1199,23 @@ mod tests {
         );
 
         let expected = Stylesheet {
-            rules: vec![Rule {
+            rules: vec![CssRule::Style(Rule {
                 selectors: vec![Selector::Simple(SimpleSelector {
                     class: vec![],
                     id: Some(String::from("foo")),
                     tag_name: None,
+                    pseudo_classes: vec![],
+                    pseudo_element: None,
                 })],
                 declarations: vec![Declaration {
                     name: String::from("display"),
                     value: Value::Keyword(String::from("inline")),
+                    important: false,
                 }],
-            }],
+                location: SourceLocation { line: 2, column: 9 },
+            })],
+            origin: Origin::Author,
+            path: None,
         };
         assert_eq!(expected, parse(source));
     }
@@ -302,17 +1231,23 @@ mod tests {
         );
 
         let expected = Stylesheet {
-            rules: vec![Rule {
+            rules: vec![CssRule::Style(Rule {
                 selectors: vec![Selector::Simple(SimpleSelector {
                     class: vec![String::from("foo")],
                     id: None,
                     tag_name: None,
+                    pseudo_classes: vec![],
+                    pseudo_element: None,
                 })],
                 declarations: vec![Declaration {
                     name: String::from("display"),
                     value: Value::Keyword(String::from("inline")),
+                    important: false,
                 }],
-            }],
+                location: SourceLocation { line: 2, column: 9 },
+            })],
+            origin: Origin::Author,
+            path: None,
         };
         assert_eq!(expected, parse(source));
     }
@@ -328,28 +1263,131 @@ mod tests {
         );
 
         let expected = Stylesheet {
-            rules: vec![Rule {
+            rules: vec![CssRule::Style(Rule {
                 selectors: vec![
                     Selector::Simple(SimpleSelector {
                         class: vec![],
                         id: None,
                         tag_name: Some(String::from("foo")),
+                        pseudo_classes: vec![],
+                        pseudo_element: None,
                     }),
                     Selector::Simple(SimpleSelector {
                         class: vec![],
                         id: None,
                         tag_name: Some(String::from("bar")),
+                        pseudo_classes: vec![],
+                        pseudo_element: None,
                     }),
                 ],
                 declarations: vec![Declaration {
                     name: String::from("display"),
                     value: Value::Keyword(String::from("inline")),
+                    important: false,
                 }],
-            }],
+                location: SourceLocation { line: 2, column: 9 },
+            })],
+            origin: Origin::Author,
+            path: None,
         };
         assert_eq!(expected, parse(source));
     }
 
+    #[test]
+    fn test_parse_combinators() {
+        let stylesheet = parse("ul  li>.a.b  #c, div>p {}".to_string());
+        let rule = style_rule(&stylesheet.rules[0]);
+        let simple = |tag_name: Option<&str>, id: Option<&str>, class: &[&str]| SimpleSelector {
+            tag_name: tag_name.map(String::from),
+            pseudo_classes: vec![],
+            pseudo_element: None,
+            id: id.map(String::from),
+            class: class.iter().map(|class| class.to_string()).collect(),
+        };
+        assert_eq!(
+            rule.selectors,
+            vec![
+                Selector::Complex(ComplexSelector {
+                    subject: simple(None, Some("c"), &[]),
+                    ancestors: vec![
+                        (Combinator::Descendant, simple(None, None, &["a", "b"])),
+                        (Combinator::Child, simple(Some("li"), None, &[])),
+                        (Combinator::Descendant, simple(Some("ul"), None, &[])),
+                    ],
+                }),
+                Selector::Complex(ComplexSelector {
+                    subject: simple(Some("p"), None, &[]),
+                    ancestors: vec![(Combinator::Child, simple(Some("div"), None, &[]))],
+                }),
+            ]
+        );
+        assert_eq!(rule.selectors[0].specificity(), (1, 2, 2));
+        assert_eq!(rule.selectors[0].subject().id.as_deref(), Some("c"));
+
+        let stylesheet = parse("a:hover, .menu:FOCUS-VISIBLE :checked {}".to_string());
+        let rule = style_rule(&stylesheet.rules[0]);
+        let pseudo_classes: Vec<_> = rule.selectors[0]
+            .compounds()
+            .map(|simple| simple.pseudo_classes.clone())
+            .collect();
+        assert_eq!(
+            pseudo_classes,
+            vec![vec![PseudoClass::Checked], vec![PseudoClass::FocusVisible]]
+        );
+        assert_eq!(rule.selectors[0].specificity(), (0, 3, 0));
+        assert_eq!(
+            rule.selectors[1].subject().pseudo_classes,
+            vec![PseudoClass::Hover]
+        );
+        assert_eq!(rule.selectors[1].specificity(), (0, 1, 1));
+    }
+
+    #[test]
+    fn test_parse_pseudo_elements() {
+        let stylesheet = parse(
+            r#"q::before, .a:hover::AFTER, p:after { content: open-quote "\"" attr(title) counters(x, ".", upper-roman) counter(y); }"#
+                .to_string(),
+        );
+        let rule = style_rule(&stylesheet.rules[0]);
+        let pseudo_elements: Vec<_> = rule
+            .selectors
+            .iter()
+            .map(|selector| (selector.pseudo_element(), selector.specificity()))
+            .collect();
+        assert_eq!(
+            pseudo_elements,
+            vec![
+                (Some(PseudoElement::After), (0, 2, 1)),
+                (Some(PseudoElement::Before), (0, 0, 2)),
+                (Some(PseudoElement::After), (0, 0, 2)),
+            ]
+        );
+        assert_eq!(
+            rule.declarations[0].value,
+            Value::List(vec![
+                Value::Keyword("open-quote".to_string()),
+                Value::String("\"".to_string()),
+                Value::Content(ContentFunction::Attr("title".to_string())),
+                Value::Content(ContentFunction::Counter {
+                    name: "x".to_string(),
+                    separator: Some(".".to_string()),
+                    style: "upper-roman".to_string(),
+                }),
+                Value::Content(ContentFunction::Counter {
+                    name: "y".to_string(),
+                    separator: None,
+                    style: "decimal".to_string(),
+                }),
+            ])
+        );
+
+        let (_, errors) = parse_with_errors(
+            "p { content: attr(); } p { content: counters(x); }".to_string(),
+            Origin::Author,
+        );
+        assert_eq!(errors.len(), 2);
+    }
+
     #[test]
     fn test_parse_multiple_declarations() {
         let source = String::from(
@@ -365,40 +1403,33 @@ mod tests {
         );
 
         let expected = Stylesheet {
-            rules: vec![Rule {
+            rules: vec![CssRule::Style(Rule {
                 selectors: vec![Selector::Simple(SimpleSelector {
                     class: vec![],
                     id: None,
                     tag_name: Some(String::from("html")),
+                    pseudo_classes: vec![],
+                    pseudo_element: None,
                 })],
-                declarations: vec![
-                    Declaration {
-                        name: String::from("width"),
-                        value: Value::Length(600.0, Unit::Px),
-                    },
-                    Declaration {
-                        name: String::from("padding"),
-                        value: Value::Length(10.0, Unit::Px),
-                    },
-                    Declaration {
-                        name: String::from("border-width"),
-                        value: Value::Length(1.0, Unit::Px),
-                    },
-                    Declaration {
-                        name: String::from("margin"),
-                        value: Value::Keyword(String::from("auto")),
-                    },
-                    Declaration {
-                        name: String::from("background"),
-                        value: Value::ColorValue(Color {
-                            r: 170,
-                            g: 187,
-                            b: 204,
-                            a: 255,
-                        }),
-                    },
-                ],
-            }],
+                declarations: [
+                    vec![declaration("width", Value::Length(600.0, Unit::Px))],
+                    sides("padding-", "", Value::Length(10.0, Unit::Px)),
+                    sides("border-", "-width", Value::Length(1.0, Unit::Px)),
+                    sides("margin-", "", Value::Keyword(String::from("auto"))),
+                    background(Color {
+                        r: 170,
+                        g: 187,
+                        b: 204,
+                        a: 255,
+                    }),
+                ]
+                .into_iter()
+                .flatten()
+                .collect(),
+                location: SourceLocation { line: 2, column: 9 },
+            })],
+            origin: Origin::Author,
+            path: None,
         };
         assert_eq!(expected, parse(source));
     }
@@ -420,59 +1451,339 @@ mod tests {
 
         let expected = Stylesheet {
             rules: vec![
-                Rule {
+                CssRule::Style(Rule {
                     selectors: vec![
                         Selector::Simple(SimpleSelector {
                             class: vec![],
                             id: None,
                             tag_name: Some(String::from("h1")),
+                            pseudo_classes: vec![],
+                            pseudo_element: None,
                         }),
                         Selector::Simple(SimpleSelector {
                             class: vec![],
                             id: None,
                             tag_name: Some(String::from("h2")),
+                            pseudo_classes: vec![],
+                            pseudo_element: None,
                         }),
                         Selector::Simple(SimpleSelector {
                             class: vec![],
                             id: None,
                             tag_name: Some(String::from("h3")),
+                            pseudo_classes: vec![],
+                            pseudo_element: None,
                         }),
                     ],
-                    declarations: vec![
-                        Declaration {
-                            name: String::from("margin"),
-                            value: Value::Keyword(String::from("auto")),
-                        },
-                        Declaration {
-                            name: String::from("background"),
-                            value: Value::ColorValue(Color {
-                                r: 204,
-                                g: 0,
-                                b: 0,
-                                a: 255,
-                            }),
-                        },
-                    ],
-                },
-                Rule {
+                    declarations: [
+                        sides("margin-", "", Value::Keyword(String::from("auto"))),
+                        background(Color {
+                            r: 204,
+                            g: 0,
+                            b: 0,
+                            a: 255,
+                        }),
+                    ]
+                    .into_iter()
+                    .flatten()
+                    .collect(),
+                    location: SourceLocation { line: 2, column: 9 },
+                }),
+                CssRule::Style(Rule {
                     selectors: vec![Selector::Simple(SimpleSelector {
                         class: vec![String::from("note")],
                         id: None,
                         tag_name: Some(String::from("div")),
+                        pseudo_classes: vec![],
+                        pseudo_element: None,
                     })],
-                    declarations: vec![
-                        Declaration {
-                            name: String::from("margin-bottom"),
-                            value: Value::Length(20.0, Unit::Px),
-                        },
-                        Declaration {
-                            name: String::from("padding"),
-                            value: Value::Length(10.0, Unit::Px),
-                        },
-                    ],
-                },
+                    declarations: [
+                        vec![declaration("margin-bottom", Value::Length(20.0, Unit::Px))],
+                        sides("padding-", "", Value::Length(10.0, Unit::Px)),
+                    ]
+                    .into_iter()
+                    .flatten()
+                    .collect(),
+                    location: SourceLocation { line: 6, column: 9 },
+                }),
             ],
+            origin: Origin::Author,
+            path: None,
         };
         assert_eq!(expected, parse(source));
     }
+
+    #[test]
+    fn test_parse_shorthand() {
+        let source = String::from(
+            r#"
+        p {
+            margin: 0 auto;
+            border: 1px solid;
+        }
+        "#,
+        );
+
+        let declarations: Vec<(String, Value)> = style_rule(&parse(source).rules[0])
+            .declarations
+            .iter()
+            .map(|d| (d.name.clone(), d.value.clone()))
+            .collect();
+        let auto = Value::Keyword(String::from("auto"));
+        assert_eq!(
+            declarations[..4],
+            [
+                (String::from("margin-top"), Value::Number(0.0)),
+                (String::from("margin-right"), auto.clone()),
+                (String::from("margin-bottom"), Value::Number(0.0)),
+                (String::from("margin-left"), auto),
+            ]
+        );
+        assert_eq!(declarations.len(), 4 + 12);
+        assert_eq!(
+            declarations[4],
+            (
+                String::from("border-top-width"),
+                Value::Length(1.0, Unit::Px)
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_value_list() {
+        let source = String::from("div { background-position: -1.5px .5px; }");
+
+        let stylesheet = parse(source);
+        assert_eq!(
+            style_rule(&stylesheet.rules[0]).declarations[0].value,
+            Value::List(vec![
+                Value::Length(-1.5, Unit::Px),
+                Value::Length(0.5, Unit::Px),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let source = String::from(
+            "p {\n  colour: red;\n  width: red;\n  border: 1px wavy;\n  COLOR: blue;\n  margin: inherit;\n}",
+        );
+
+        let (stylesheet, errors) = parse_with_errors(source, Origin::Author);
+        let names: Vec<&str> = style_rule(&stylesheet.rules[0])
+            .declarations
+            .iter()
+            .map(|d| &*d.name)
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "color",
+                "margin-top",
+                "margin-right",
+                "margin-bottom",
+                "margin-left"
+            ]
+        );
+        assert_eq!(
+            errors
+                .iter()
+                .map(|e| (e.line, e.kind, &*e.property))
+                .collect::<Vec<_>>(),
+            vec![
+                (2, ParseErrorKind::UnknownProperty, "colour"),
+                (3, ParseErrorKind::InvalidValue, "width"),
+                (4, ParseErrorKind::InvalidValue, "border"),
+            ]
+        );
+        assert_eq!(
+            errors[1].to_string(),
+            "line 3: invalid value \"red\" for width"
+        );
+
+        let ua = include_str!("ua.css").to_string();
+        assert_eq!(parse_with_errors(ua, Origin::UserAgent).1, vec![]);
+    }
+
+    #[test]
+    fn test_parse_invalid_selectors() {
+        let source = String::from(
+            "a:visited {}\n:first-child { color: red; }\np::selection { x: { y } }\nb, i:foo { color: red; }\np::first-line, p:first-letter {}\np { color: blue; }",
+        );
+
+        let (stylesheet, errors) = parse_with_errors(source, Origin::Author);
+        // 無効なセレクタの規則はブロックごと読み飛ばし、続く規則は読む
+        assert_eq!(stylesheet.rules.len(), 1);
+        assert_eq!(
+            style_rule(&stylesheet.rules[0]).selectors,
+            vec![Selector::Simple(SimpleSelector {
+                tag_name: Some("p".to_string()),
+                pseudo_classes: Vec::new(),
+                pseudo_element: None,
+                id: None,
+                class: Vec::new(),
+            })]
+        );
+        assert_eq!(
+            errors
+                .iter()
+                .map(|e| (e.line, e.kind, &*e.value))
+                .collect::<Vec<_>>(),
+            vec![
+                (1, ParseErrorKind::InvalidSelector, "a:visited"),
+                (2, ParseErrorKind::InvalidSelector, ":first-child"),
+                (3, ParseErrorKind::InvalidSelector, "p::selection"),
+                (4, ParseErrorKind::InvalidSelector, "b, i:foo"),
+                (
+                    5,
+                    ParseErrorKind::InvalidSelector,
+                    "p::first-line, p:first-letter"
+                ),
+            ]
+        );
+        assert_eq!(
+            errors[0].to_string(),
+            "line 1: invalid selector \"a:visited\""
+        );
+    }
+
+    #[test]
+    fn test_parse_units() {
+        let source = String::from("p { margin: 1.5em 2rem 50% 10vmin; width: 1in; }");
+
+        let stylesheet = parse(source);
+        let values: Vec<&Value> = style_rule(&stylesheet.rules[0])
+            .declarations
+            .iter()
+            .map(|d| &d.value)
+            .collect();
+        assert_eq!(
+            values,
+            vec![
+                &Value::Length(1.5, Unit::Em),
+                &Value::Length(2.0, Unit::Rem),
+                &Value::Percentage(50.0),
+                &Value::Length(10.0, Unit::Vmin),
+                &Value::Length(1.0, Unit::In),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_to_px() {
+        let ctx = LengthContext {
+            font_size: 20.0,
+            x_height: 0.5,
+            zero_advance: 0.5,
+            root_font_size: 16.0,
+            percent_base: 400.0,
+            viewport_width: 800.0,
+            viewport_height: 600.0,
+        };
+        assert_eq!(Value::Length(10.0, Unit::Px).to_px(&ctx), 10.0);
+        assert_eq!(Value::Length(1.5, Unit::Em).to_px(&ctx), 30.0);
+        assert_eq!(Value::Length(2.0, Unit::Rem).to_px(&ctx), 32.0);
+        assert_eq!(Value::Length(2.0, Unit::Ch).to_px(&ctx), 20.0);
+        assert_eq!(Value::Percentage(25.0).to_px(&ctx), 100.0);
+        assert_eq!(Value::Length(10.0, Unit::Vw).to_px(&ctx), 80.0);
+        assert_eq!(Value::Length(10.0, Unit::Vh).to_px(&ctx), 60.0);
+        assert_eq!(Value::Length(10.0, Unit::Vmin).to_px(&ctx), 60.0);
+        assert_eq!(Value::Length(10.0, Unit::Vmax).to_px(&ctx), 80.0);
+        assert_eq!(Value::Length(1.0, Unit::In).to_px(&ctx), 96.0);
+        assert_eq!(Value::Length(72.0, Unit::Pt).to_px(&ctx), 96.0);
+        assert_eq!(Value::Length(6.0, Unit::Pc).to_px(&ctx), 96.0);
+        assert!((Value::Length(2.54, Unit::Cm).to_px(&ctx) - 96.0).abs() < 1e-3);
+        assert!((Value::Length(40.0, Unit::Q).to_px(&ctx) - 37.795).abs() < 1e-3);
+        assert_eq!(Value::Keyword(String::from("auto")).to_px(&ctx), 0.0);
+    }
+
+    #[test]
+    fn test_parse_custom_properties() {
+        let stylesheet = parse(String::from(
+            "p { --Brand: { a: b } !important; padding: var(--gap) 1px; color: red; }",
+        ));
+        let declarations = &style_rule(&stylesheet.rules[0]).declarations;
+
+        let unparsed = |tokens, shorthand: Option<&str>| {
+            Value::Unparsed(UnparsedValue {
+                tokens: TokenStream::parse(tokens).unwrap(),
+                shorthand: shorthand.map(String::from),
+            })
+        };
+        assert_eq!(declarations[0].name, "--Brand");
+        assert_eq!(declarations[0].value, unparsed("{ a: b }", None));
+        assert!(declarations[0].important);
+        assert_eq!(declarations[1].name, "padding-top");
+        assert_eq!(
+            declarations[1].value,
+            unparsed("var(--gap) 1px", Some("padding"))
+        );
+        assert_eq!(declarations.len(), 6);
+    }
+
+    #[test]
+    fn test_parse_layer_and_supports_rules() {
+        let stylesheet = parse(String::from(
+            r#"
+            @layer reset, base.components;
+            @import "a.css";
+            @layer base { p { color: red; } }
+            @layer { p { color: blue; } }
+            @supports (display: block) { @layer base { p { color: green; } } }
+            "#,
+        ));
+        let name = |parts: &[&str]| parts.iter().map(|s| s.to_string()).collect::<LayerName>();
+
+        assert_eq!(stylesheet.rules.len(), 5);
+        assert_eq!(
+            stylesheet.rules[0],
+            CssRule::LayerStatement(vec![name(&["reset"]), name(&["base", "components"])])
+        );
+        assert!(matches!(stylesheet.rules[1], CssRule::Import(_)));
+        match stylesheet.rules[2] {
+            CssRule::Layer(ref layer) => {
+                assert_eq!(layer.name, Some(name(&["base"])));
+                assert_eq!(layer.rules.len(), 1);
+            }
+            ref rule => panic!("Unexpected rule {:?}", rule),
+        }
+        assert!(matches!(
+            stylesheet.rules[3],
+            CssRule::Layer(LayerRule { name: None, .. })
+        ));
+        match stylesheet.rules[4] {
+            CssRule::Supports(ref supports) => {
+                assert_eq!(
+                    supports.condition,
+                    SupportsCondition::Declaration("display".to_string(), "block".to_string())
+                );
+                assert!(matches!(supports.rules[0], CssRule::Layer(_)));
+            }
+            ref rule => panic!("Unexpected rule {:?}", rule),
+        }
+    }
+
+    #[test]
+    fn test_parse_media_rule() {
+        let source = String::from(
+            r#"
+        @charset "utf-8";
+        @font-face { font-family: foo; }
+        @media screen and (max-width: 600px) {
+            p { display: none; }
+        }
+        "#,
+        );
+
+        let stylesheet = parse(source);
+        assert_eq!(stylesheet.rules.len(), 1);
+        let CssRule::Media(ref media) = stylesheet.rules[0] else {
+            panic!("not a media rule: {:?}", stylesheet.rules[0]);
+        };
+        assert_eq!(media.queries.0.len(), 1);
+        assert_eq!(
+            style_rule(&media.rules[0]).declarations[0].value,
+            Value::Keyword(String::from("none"))
+        );
+    }
 }
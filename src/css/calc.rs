@@ -0,0 +1,404 @@
+//! 数学関数 `calc()`、`min()`、`max()`、`clamp()`
+//!
+//! https://www.w3.org/TR/css-values-4/#math
+
+use super::{valid_identifier_char, LengthContext, Parser, Unit};
+
+/// 数学関数の式木
+///
+/// パーセンテージと相対長さはレイアウト時に `LengthContext` を使って解決する
+#[derive(Debug, Clone, PartialEq)]
+pub enum CalcNode {
+    Number(f32),
+    Length(f32, Unit),
+    Percentage(f32),
+    Add(Box<CalcNode>, Box<CalcNode>),
+    Sub(Box<CalcNode>, Box<CalcNode>),
+    Mul(Box<CalcNode>, Box<CalcNode>),
+    Div(Box<CalcNode>, Box<CalcNode>),
+    Min(Vec<CalcNode>),
+    Max(Vec<CalcNode>),
+    /// `clamp(min, val, max)`
+    Clamp(Box<CalcNode>, Box<CalcNode>, Box<CalcNode>),
+}
+
+/// 式の型。パーセンテージは長さとして扱う
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CalcType {
+    Number,
+    Length,
+}
+
+/// 数学関数かどうか (大文字小文字は区別しない)
+pub fn is_math_function(name: &str) -> bool {
+    ["calc", "min", "max", "clamp"]
+        .iter()
+        .any(|f| f.eq_ignore_ascii_case(name))
+}
+
+/// 関数 `name(args)` を式木に変換する
+///
+/// 構文や型が正しくなければ `None` を返す
+pub fn parse(name: &str, args: &str) -> Option<CalcNode> {
+    let mut parser = Parser::new(args.to_string());
+    let node = parser.parse_math_function_args(&name.to_ascii_lowercase())?;
+    parser.consume_whitespace();
+    if !parser.eof() {
+        return None;
+    }
+    node.calc_type()?;
+    Some(node)
+}
+
+impl CalcNode {
+    /// 式を評価してpx数 (数値の式ならその数値) を返す
+    pub fn to_px(&self, ctx: &LengthContext) -> f32 {
+        match *self {
+            CalcNode::Number(n) => n,
+            CalcNode::Length(f, unit) => f * unit.to_px(ctx),
+            CalcNode::Percentage(f) => f / 100.0 * ctx.percent_base,
+            CalcNode::Add(ref a, ref b) => a.to_px(ctx) + b.to_px(ctx),
+            CalcNode::Sub(ref a, ref b) => a.to_px(ctx) - b.to_px(ctx),
+            CalcNode::Mul(ref a, ref b) => a.to_px(ctx) * b.to_px(ctx),
+            CalcNode::Div(ref a, ref b) => a.to_px(ctx) / b.to_px(ctx),
+            CalcNode::Min(ref args) => args
+                .iter()
+                .map(|arg| arg.to_px(ctx))
+                .fold(f32::INFINITY, f32::min),
+            CalcNode::Max(ref args) => args
+                .iter()
+                .map(|arg| arg.to_px(ctx))
+                .fold(f32::NEG_INFINITY, f32::max),
+            // min が max より大きい場合は min が勝つ
+            CalcNode::Clamp(ref min, ref val, ref max) => {
+                val.to_px(ctx).min(max.to_px(ctx)).max(min.to_px(ctx))
+            }
+        }
+    }
+
+    /// 相対長さをpxに変換した式を返す。パーセンテージはそのまま残す
+    pub fn absolutize(&self, ctx: &LengthContext) -> CalcNode {
+        let binary =
+            |a: &CalcNode, b: &CalcNode| (Box::new(a.absolutize(ctx)), Box::new(b.absolutize(ctx)));
+        match *self {
+            CalcNode::Length(f, unit) => CalcNode::Length(f * unit.to_px(ctx), Unit::Px),
+            CalcNode::Number(_) | CalcNode::Percentage(_) => self.clone(),
+            CalcNode::Add(ref a, ref b) => {
+                let (a, b) = binary(a, b);
+                CalcNode::Add(a, b)
+            }
+            CalcNode::Sub(ref a, ref b) => {
+                let (a, b) = binary(a, b);
+                CalcNode::Sub(a, b)
+            }
+            CalcNode::Mul(ref a, ref b) => {
+                let (a, b) = binary(a, b);
+                CalcNode::Mul(a, b)
+            }
+            CalcNode::Div(ref a, ref b) => {
+                let (a, b) = binary(a, b);
+                CalcNode::Div(a, b)
+            }
+            CalcNode::Min(ref args) => {
+                CalcNode::Min(args.iter().map(|arg| arg.absolutize(ctx)).collect())
+            }
+            CalcNode::Max(ref args) => {
+                CalcNode::Max(args.iter().map(|arg| arg.absolutize(ctx)).collect())
+            }
+            CalcNode::Clamp(ref min, ref val, ref max) => CalcNode::Clamp(
+                Box::new(min.absolutize(ctx)),
+                Box::new(val.absolutize(ctx)),
+                Box::new(max.absolutize(ctx)),
+            ),
+        }
+    }
+
+    /// パーセンテージを含むかどうか
+    pub fn has_percentage(&self) -> bool {
+        self.any_leaf(&|leaf| matches!(leaf, CalcNode::Percentage(_)))
+    }
+
+    /// 長さを含むかどうか
+    pub fn has_length(&self) -> bool {
+        self.any_leaf(&|leaf| matches!(leaf, CalcNode::Length(..)))
+    }
+
+    /// 数値に解決される式かどうか
+    pub fn is_number(&self) -> bool {
+        self.calc_type() == Some(CalcType::Number)
+    }
+
+    /// 相対長さもパーセンテージも含まず、文脈によらずに値が決まるなら、その値
+    pub fn static_value(&self) -> Option<f32> {
+        let relative = self.any_leaf(&|leaf| match *leaf {
+            CalcNode::Length(_, unit) => !unit.is_absolute(),
+            _ => matches!(leaf, CalcNode::Percentage(_)),
+        });
+        (!relative).then(|| self.to_px(&LengthContext::default()))
+    }
+
+    /// `max(0, <式>)` で包み、負の値を0に切り詰めた式を返す
+    pub fn clamp_non_negative(self) -> CalcNode {
+        let zero = if self.is_number() {
+            CalcNode::Number(0.0)
+        } else {
+            CalcNode::Length(0.0, Unit::Px)
+        };
+        CalcNode::Max(vec![zero, self])
+    }
+
+    /// 数値、長さ、パーセンテージのいずれかが `f` を満たすかどうか
+    fn any_leaf(&self, f: &impl Fn(&CalcNode) -> bool) -> bool {
+        match *self {
+            CalcNode::Number(_) | CalcNode::Length(..) | CalcNode::Percentage(_) => f(self),
+            CalcNode::Add(ref a, ref b)
+            | CalcNode::Sub(ref a, ref b)
+            | CalcNode::Mul(ref a, ref b)
+            | CalcNode::Div(ref a, ref b) => a.any_leaf(f) || b.any_leaf(f),
+            CalcNode::Min(ref args) | CalcNode::Max(ref args) => {
+                args.iter().any(|arg| arg.any_leaf(f))
+            }
+            CalcNode::Clamp(ref min, ref val, ref max) => {
+                min.any_leaf(f) || val.any_leaf(f) || max.any_leaf(f)
+            }
+        }
+    }
+
+    /// https://www.w3.org/TR/css-values-4/#calc-type-checking
+    fn calc_type(&self) -> Option<CalcType> {
+        match *self {
+            CalcNode::Number(_) => Some(CalcType::Number),
+            CalcNode::Length(..) | CalcNode::Percentage(_) => Some(CalcType::Length),
+            CalcNode::Add(ref a, ref b) | CalcNode::Sub(ref a, ref b) => {
+                same_type([&**a, &**b].into_iter())
+            }
+            CalcNode::Mul(ref a, ref b) => match (a.calc_type()?, b.calc_type()?) {
+                (CalcType::Number, t) | (t, CalcType::Number) => Some(t),
+                _ => None,
+            },
+            CalcNode::Div(ref a, ref b) => match b.calc_type()? {
+                CalcType::Number => a.calc_type(),
+                CalcType::Length => None,
+            },
+            CalcNode::Min(ref args) | CalcNode::Max(ref args) => same_type(args.iter()),
+            CalcNode::Clamp(ref min, ref val, ref max) => {
+                same_type([&**min, &**val, &**max].into_iter())
+            }
+        }
+    }
+}
+
+/// すべての式が同じ型ならその型
+fn same_type<'a>(mut nodes: impl Iterator<Item = &'a CalcNode>) -> Option<CalcType> {
+    let first = nodes.next()?.calc_type()?;
+    for node in nodes {
+        if node.calc_type()? != first {
+            return None;
+        }
+    }
+    Some(first)
+}
+
+impl Parser {
+    /// 関数 `name` の括弧の中身を読む
+    fn parse_math_function_args(&mut self, name: &str) -> Option<CalcNode> {
+        let mut args = vec![self.parse_calc_sum()?];
+        loop {
+            self.consume_whitespace();
+            if self.eof() || self.next_char() != ',' {
+                break;
+            }
+            self.consume_char();
+            args.push(self.parse_calc_sum()?);
+        }
+
+        match (name, args.len()) {
+            ("calc", 1) => args.pop(),
+            ("min", _) => Some(CalcNode::Min(args)),
+            ("max", _) => Some(CalcNode::Max(args)),
+            ("clamp", 3) => {
+                let max = args.pop()?;
+                let val = args.pop()?;
+                let min = args.pop()?;
+                Some(CalcNode::Clamp(Box::new(min), Box::new(val), Box::new(max)))
+            }
+            _ => None,
+        }
+    }
+
+    /// `<calc-product> [ ['+' | '-'] <calc-product> ]*`
+    ///
+    /// `+` と `-` の前後には空白が必要
+    fn parse_calc_sum(&mut self) -> Option<CalcNode> {
+        let mut node = self.parse_calc_product()?;
+        loop {
+            let before = self.pos;
+            self.consume_whitespace();
+            if self.pos == before || self.eof() {
+                break;
+            }
+            let op = self.next_char();
+            if op != '+' && op != '-' {
+                break;
+            }
+            self.consume_char();
+            if self.eof() || !self.next_char().is_whitespace() {
+                return None;
+            }
+            let rhs = Box::new(self.parse_calc_product()?);
+            node = match op {
+                '+' => CalcNode::Add(Box::new(node), rhs),
+                _ => CalcNode::Sub(Box::new(node), rhs),
+            };
+        }
+        Some(node)
+    }
+
+    /// `<calc-value> [ ['*' | '/'] <calc-value> ]*`
+    fn parse_calc_product(&mut self) -> Option<CalcNode> {
+        let mut node = self.parse_calc_value()?;
+        loop {
+            let before = self.pos;
+            self.consume_whitespace();
+            if self.eof() || !matches!(self.next_char(), '*' | '/') {
+                self.pos = before;
+                break;
+            }
+            let op = self.consume_char();
+            let rhs = Box::new(self.parse_calc_value()?);
+            node = match op {
+                '*' => CalcNode::Mul(Box::new(node), rhs),
+                _ => CalcNode::Div(Box::new(node), rhs),
+            };
+        }
+        Some(node)
+    }
+
+    /// 数値、長さ、パーセンテージ、括弧で囲まれた式、入れ子の数学関数のいずれか
+    fn parse_calc_value(&mut self) -> Option<CalcNode> {
+        self.consume_whitespace();
+        if self.eof() {
+            return None;
+        }
+        match self.next_char() {
+            '0'..='9' | '.' => self.parse_calc_dimension(),
+            '+' | '-' if self.starts_number() => self.parse_calc_dimension(),
+            '(' => {
+                self.consume_char();
+                let node = self.parse_calc_sum()?;
+                self.consume_whitespace();
+                (!self.eof() && self.consume_char() == ')').then_some(node)
+            }
+            c if valid_identifier_char(c) => {
+                let name = self.parse_identifier().to_ascii_lowercase();
+                if !is_math_function(&name) || self.eof() || self.next_char() != '(' {
+                    return None;
+                }
+                let args = self.parse_parenthesized();
+                parse(&name, &args)
+            }
+            _ => None,
+        }
+    }
+
+    fn parse_calc_dimension(&mut self) -> Option<CalcNode> {
+        let num = self.parse_float();
+        if self.eof() {
+            return Some(CalcNode::Number(num));
+        }
+        Some(match self.next_char() {
+            '%' => {
+                self.consume_char();
+                CalcNode::Percentage(num)
+            }
+            c if valid_identifier_char(c) => CalcNode::Length(num, self.parse_unit()?),
+            _ => CalcNode::Number(num),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CTX: LengthContext = LengthContext {
+        font_size: 10.0,
+        x_height: 0.5,
+        zero_advance: 0.5,
+        root_font_size: 16.0,
+        percent_base: 200.0,
+        viewport_width: 800.0,
+        viewport_height: 600.0,
+    };
+
+    fn eval(name: &str, args: &str) -> Option<f32> {
+        parse(name, args).map(|node| node.to_px(&CTX))
+    }
+
+    #[test]
+    fn test_parse_calc() {
+        assert_eq!(
+            parse("calc", "100% - 20px"),
+            Some(CalcNode::Sub(
+                Box::new(CalcNode::Percentage(100.0)),
+                Box::new(CalcNode::Length(20.0, Unit::Px))
+            ))
+        );
+        assert_eq!(eval("calc", "100% - 2em * 2"), Some(160.0));
+        assert_eq!(eval("CALC", "(1vw + 2px) / 2"), Some(5.0));
+        assert_eq!(eval("calc", "-10px + calc(50% * -1)"), Some(-110.0));
+        assert_eq!(eval("calc", " 3 * 2 "), Some(6.0));
+        assert_eq!(
+            parse("calc", "100% - 2em").map(|node| node.absolutize(&CTX)),
+            Some(CalcNode::Sub(
+                Box::new(CalcNode::Percentage(100.0)),
+                Box::new(CalcNode::Length(20.0, Unit::Px))
+            ))
+        );
+    }
+
+    #[test]
+    fn test_calc_properties() {
+        let node = parse("calc", "2 * 3").unwrap();
+        assert!(node.is_number() && !node.has_length());
+        assert_eq!(node.static_value(), Some(6.0));
+        let node = parse("calc", "1in - 100px").unwrap();
+        assert!(!node.is_number() && node.has_length() && !node.has_percentage());
+        assert_eq!(node.static_value(), Some(-4.0));
+        assert_eq!(parse("calc", "1em - 100px").unwrap().static_value(), None);
+        assert_eq!(parse("calc", "50% - 1px").unwrap().static_value(), None);
+        assert_eq!(
+            parse("calc", "1em - 100px")
+                .unwrap()
+                .clamp_non_negative()
+                .to_px(&CTX),
+            0.0
+        );
+        assert_eq!(
+            parse("calc", "-2").unwrap().clamp_non_negative(),
+            CalcNode::Max(vec![CalcNode::Number(0.0), CalcNode::Number(-2.0)])
+        );
+    }
+
+    #[test]
+    fn test_parse_comparison_functions() {
+        assert_eq!(eval("min", "100%, 150px, 20em"), Some(150.0));
+        assert_eq!(eval("max", "10px, 1rem"), Some(16.0));
+        assert_eq!(eval("clamp", "10px, 50%, 80px"), Some(80.0));
+        assert_eq!(eval("clamp", "100px, 50%, 80px"), Some(100.0));
+        assert_eq!(eval("calc", "min(10px, 5%) + 1px"), Some(11.0));
+    }
+
+    #[test]
+    fn test_invalid_calc() {
+        // `-` の前後に空白がない
+        assert_eq!(parse("calc", "100%-20px"), None);
+        // 型が合わない
+        assert_eq!(parse("calc", "10px + 1"), None);
+        assert_eq!(parse("calc", "10px * 1px"), None);
+        assert_eq!(parse("calc", "10 / 1px"), None);
+        assert_eq!(parse("clamp", "1px, 2px"), None);
+        assert_eq!(parse("calc", "1px 2px"), None);
+        assert_eq!(parse("calc", "foo(1px)"), None);
+    }
+}
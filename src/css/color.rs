@@ -0,0 +1,441 @@
+//! `<color>` 値の構文解析
+//!
+//! https://www.w3.org/TR/css-color-4/
+
+use super::Color;
+
+/// 名前付きの色を返す (大文字小文字は区別しない)
+///
+/// `transparent` も含む。`currentcolor` は使用値の計算時に解決するので含まない
+pub fn named(name: &str) -> Option<Color> {
+    let name = name.to_ascii_lowercase();
+    if name == "transparent" {
+        return Some(Color {
+            r: 0,
+            g: 0,
+            b: 0,
+            a: 0,
+        });
+    }
+    NAMED_COLORS
+        .binary_search_by_key(&&*name, |&(name, _)| name)
+        .ok()
+        .map(|i| {
+            let rgb = NAMED_COLORS[i].1;
+            Color {
+                r: (rgb >> 16) as u8,
+                g: (rgb >> 8) as u8,
+                b: rgb as u8,
+                a: 255,
+            }
+        })
+}
+
+/// `#` に続く16進数表記を解釈する
+///
+/// `#rgb`, `#rgba`, `#rrggbb`, `#rrggbbaa` の4形式
+pub fn parse_hex(digits: &str) -> Option<Color> {
+    if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let digit = |i: usize| u8::from_str_radix(&digits[i..i + 1], 16).unwrap();
+    let pair = |i: usize| u8::from_str_radix(&digits[i..i + 2], 16).unwrap();
+    match digits.len() {
+        3 | 4 => Some(Color {
+            r: digit(0) * 0x11,
+            g: digit(1) * 0x11,
+            b: digit(2) * 0x11,
+            a: if digits.len() == 4 {
+                digit(3) * 0x11
+            } else {
+                255
+            },
+        }),
+        6 | 8 => Some(Color {
+            r: pair(0),
+            g: pair(2),
+            b: pair(4),
+            a: if digits.len() == 8 { pair(6) } else { 255 },
+        }),
+        _ => None,
+    }
+}
+
+/// `rgb()`, `rgba()`, `hsl()`, `hsla()`, `hwb()` を解釈する
+///
+/// `args` は括弧の中身。カンマ区切りの旧構文と空白区切りの新構文の両方を受け付ける
+pub fn parse_function(name: &str, args: &str) -> Option<Color> {
+    let (components, alpha) = split_arguments(args)?;
+    let alpha = match alpha {
+        Some(alpha) => match alpha {
+            Component::Number(a) => a,
+            Component::Percentage(p) => p / 100.0,
+            Component::Angle(_) => return None,
+        },
+        None => 1.0,
+    };
+
+    let rgb = match &*name.to_ascii_lowercase() {
+        "rgb" | "rgba" => {
+            let mut rgb = [0.0; 3];
+            for (channel, component) in rgb.iter_mut().zip(components) {
+                *channel = match component {
+                    Component::Number(n) => n / 255.0,
+                    Component::Percentage(p) => p / 100.0,
+                    Component::Angle(_) => return None,
+                };
+            }
+            rgb
+        }
+        "hsl" | "hsla" => {
+            let [h, s, l] = components;
+            hsl_to_rgb(h.hue()?, s.fraction()?, l.fraction()?)
+        }
+        "hwb" => {
+            let [h, w, b] = components;
+            hwb_to_rgb(h.hue()?, w.fraction()?, b.fraction()?)
+        }
+        _ => return None,
+    };
+
+    let channel = |f: f32| (f.clamp(0.0, 1.0) * 255.0).round() as u8;
+    Some(Color {
+        r: channel(rgb[0]),
+        g: channel(rgb[1]),
+        b: channel(rgb[2]),
+        a: channel(alpha),
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Component {
+    Number(f32),
+    Percentage(f32),
+    /// 度数法に変換した角度
+    Angle(f32),
+}
+
+impl Component {
+    fn parse(s: &str) -> Option<Component> {
+        let s = s.to_ascii_lowercase();
+        if s == "none" {
+            return Some(Component::Number(0.0));
+        }
+        if let Some(p) = s.strip_suffix('%') {
+            return p.parse().ok().map(Component::Percentage);
+        }
+        for (unit, degrees) in [
+            ("deg", 1.0),
+            ("grad", 0.9),
+            ("rad", 180.0 / std::f32::consts::PI),
+            ("turn", 360.0),
+        ] {
+            if let Some(n) = s.strip_suffix(unit) {
+                return n.parse::<f32>().ok().map(|n| Component::Angle(n * degrees));
+            }
+        }
+        s.parse().ok().map(Component::Number)
+    }
+
+    /// 色相 (単位なしの数値は度として扱う)
+    fn hue(self) -> Option<f32> {
+        match self {
+            Component::Number(n) | Component::Angle(n) => Some(n),
+            Component::Percentage(_) => None,
+        }
+    }
+
+    /// 0.0〜1.0 の割合 (単位なしの数値は0〜100の範囲)
+    fn fraction(self) -> Option<f32> {
+        match self {
+            Component::Number(n) | Component::Percentage(n) => Some(n / 100.0),
+            Component::Angle(_) => None,
+        }
+    }
+}
+
+/// 3つの成分と省略可能なアルファ値に分ける
+fn split_arguments(args: &str) -> Option<([Component; 3], Option<Component>)> {
+    let (parts, alpha): (Vec<&str>, Option<&str>) = if args.contains(',') {
+        // 旧構文: rgb(255, 0, 0, 0.5)
+        let mut parts: Vec<&str> = args.split(',').map(str::trim).collect();
+        let alpha = if parts.len() == 4 { parts.pop() } else { None };
+        (parts, alpha)
+    } else {
+        // 新構文: rgb(255 0 0 / 50%)
+        let mut split = args.splitn(2, '/');
+        let parts = split.next().unwrap().split_whitespace().collect();
+        (parts, split.next().map(str::trim))
+    };
+
+    if parts.len() != 3 {
+        return None;
+    }
+    let components = [
+        Component::parse(parts[0])?,
+        Component::parse(parts[1])?,
+        Component::parse(parts[2])?,
+    ];
+    let alpha = match alpha {
+        Some(alpha) => Some(Component::parse(alpha)?),
+        None => None,
+    };
+    Some((components, alpha))
+}
+
+/// https://www.w3.org/TR/css-color-4/#hsl-to-rgb
+fn hsl_to_rgb(hue: f32, saturation: f32, lightness: f32) -> [f32; 3] {
+    let hue = hue.rem_euclid(360.0);
+    let saturation = saturation.clamp(0.0, 1.0);
+    let lightness = lightness.clamp(0.0, 1.0);
+    let f = |n: f32| {
+        let k = (n + hue / 30.0) % 12.0;
+        let a = saturation * lightness.min(1.0 - lightness);
+        lightness - a * (k - 3.0).min(9.0 - k).clamp(-1.0, 1.0)
+    };
+    [f(0.0), f(8.0), f(4.0)]
+}
+
+/// https://www.w3.org/TR/css-color-4/#hwb-to-rgb
+fn hwb_to_rgb(hue: f32, whiteness: f32, blackness: f32) -> [f32; 3] {
+    let whiteness = whiteness.clamp(0.0, 1.0);
+    let blackness = blackness.clamp(0.0, 1.0);
+    if whiteness + blackness >= 1.0 {
+        let gray = whiteness / (whiteness + blackness);
+        return [gray; 3];
+    }
+    hsl_to_rgb(hue, 1.0, 0.5).map(|c| c * (1.0 - whiteness - blackness) + whiteness)
+}
+
+/// https://www.w3.org/TR/css-color-4/#named-colors
+///
+/// 二分探索のため名前順に並べる
+const NAMED_COLORS: [(&str, u32); 148] = [
+    ("aliceblue", 0xf0f8ff),
+    ("antiquewhite", 0xfaebd7),
+    ("aqua", 0x00ffff),
+    ("aquamarine", 0x7fffd4),
+    ("azure", 0xf0ffff),
+    ("beige", 0xf5f5dc),
+    ("bisque", 0xffe4c4),
+    ("black", 0x000000),
+    ("blanchedalmond", 0xffebcd),
+    ("blue", 0x0000ff),
+    ("blueviolet", 0x8a2be2),
+    ("brown", 0xa52a2a),
+    ("burlywood", 0xdeb887),
+    ("cadetblue", 0x5f9ea0),
+    ("chartreuse", 0x7fff00),
+    ("chocolate", 0xd2691e),
+    ("coral", 0xff7f50),
+    ("cornflowerblue", 0x6495ed),
+    ("cornsilk", 0xfff8dc),
+    ("crimson", 0xdc143c),
+    ("cyan", 0x00ffff),
+    ("darkblue", 0x00008b),
+    ("darkcyan", 0x008b8b),
+    ("darkgoldenrod", 0xb8860b),
+    ("darkgray", 0xa9a9a9),
+    ("darkgreen", 0x006400),
+    ("darkgrey", 0xa9a9a9),
+    ("darkkhaki", 0xbdb76b),
+    ("darkmagenta", 0x8b008b),
+    ("darkolivegreen", 0x556b2f),
+    ("darkorange", 0xff8c00),
+    ("darkorchid", 0x9932cc),
+    ("darkred", 0x8b0000),
+    ("darksalmon", 0xe9967a),
+    ("darkseagreen", 0x8fbc8f),
+    ("darkslateblue", 0x483d8b),
+    ("darkslategray", 0x2f4f4f),
+    ("darkslategrey", 0x2f4f4f),
+    ("darkturquoise", 0x00ced1),
+    ("darkviolet", 0x9400d3),
+    ("deeppink", 0xff1493),
+    ("deepskyblue", 0x00bfff),
+    ("dimgray", 0x696969),
+    ("dimgrey", 0x696969),
+    ("dodgerblue", 0x1e90ff),
+    ("firebrick", 0xb22222),
+    ("floralwhite", 0xfffaf0),
+    ("forestgreen", 0x228b22),
+    ("fuchsia", 0xff00ff),
+    ("gainsboro", 0xdcdcdc),
+    ("ghostwhite", 0xf8f8ff),
+    ("gold", 0xffd700),
+    ("goldenrod", 0xdaa520),
+    ("gray", 0x808080),
+    ("green", 0x008000),
+    ("greenyellow", 0xadff2f),
+    ("grey", 0x808080),
+    ("honeydew", 0xf0fff0),
+    ("hotpink", 0xff69b4),
+    ("indianred", 0xcd5c5c),
+    ("indigo", 0x4b0082),
+    ("ivory", 0xfffff0),
+    ("khaki", 0xf0e68c),
+    ("lavender", 0xe6e6fa),
+    ("lavenderblush", 0xfff0f5),
+    ("lawngreen", 0x7cfc00),
+    ("lemonchiffon", 0xfffacd),
+    ("lightblue", 0xadd8e6),
+    ("lightcoral", 0xf08080),
+    ("lightcyan", 0xe0ffff),
+    ("lightgoldenrodyellow", 0xfafad2),
+    ("lightgray", 0xd3d3d3),
+    ("lightgreen", 0x90ee90),
+    ("lightgrey", 0xd3d3d3),
+    ("lightpink", 0xffb6c1),
+    ("lightsalmon", 0xffa07a),
+    ("lightseagreen", 0x20b2aa),
+    ("lightskyblue", 0x87cefa),
+    ("lightslategray", 0x778899),
+    ("lightslategrey", 0x778899),
+    ("lightsteelblue", 0xb0c4de),
+    ("lightyellow", 0xffffe0),
+    ("lime", 0x00ff00),
+    ("limegreen", 0x32cd32),
+    ("linen", 0xfaf0e6),
+    ("magenta", 0xff00ff),
+    ("maroon", 0x800000),
+    ("mediumaquamarine", 0x66cdaa),
+    ("mediumblue", 0x0000cd),
+    ("mediumorchid", 0xba55d3),
+    ("mediumpurple", 0x9370db),
+    ("mediumseagreen", 0x3cb371),
+    ("mediumslateblue", 0x7b68ee),
+    ("mediumspringgreen", 0x00fa9a),
+    ("mediumturquoise", 0x48d1cc),
+    ("mediumvioletred", 0xc71585),
+    ("midnightblue", 0x191970),
+    ("mintcream", 0xf5fffa),
+    ("mistyrose", 0xffe4e1),
+    ("moccasin", 0xffe4b5),
+    ("navajowhite", 0xffdead),
+    ("navy", 0x000080),
+    ("oldlace", 0xfdf5e6),
+    ("olive", 0x808000),
+    ("olivedrab", 0x6b8e23),
+    ("orange", 0xffa500),
+    ("orangered", 0xff4500),
+    ("orchid", 0xda70d6),
+    ("palegoldenrod", 0xeee8aa),
+    ("palegreen", 0x98fb98),
+    ("paleturquoise", 0xafeeee),
+    ("palevioletred", 0xdb7093),
+    ("papayawhip", 0xffefd5),
+    ("peachpuff", 0xffdab9),
+    ("peru", 0xcd853f),
+    ("pink", 0xffc0cb),
+    ("plum", 0xdda0dd),
+    ("powderblue", 0xb0e0e6),
+    ("purple", 0x800080),
+    ("rebeccapurple", 0x663399),
+    ("red", 0xff0000),
+    ("rosybrown", 0xbc8f8f),
+    ("royalblue", 0x4169e1),
+    ("saddlebrown", 0x8b4513),
+    ("salmon", 0xfa8072),
+    ("sandybrown", 0xf4a460),
+    ("seagreen", 0x2e8b57),
+    ("seashell", 0xfff5ee),
+    ("sienna", 0xa0522d),
+    ("silver", 0xc0c0c0),
+    ("skyblue", 0x87ceeb),
+    ("slateblue", 0x6a5acd),
+    ("slategray", 0x708090),
+    ("slategrey", 0x708090),
+    ("snow", 0xfffafa),
+    ("springgreen", 0x00ff7f),
+    ("steelblue", 0x4682b4),
+    ("tan", 0xd2b48c),
+    ("teal", 0x008080),
+    ("thistle", 0xd8bfd8),
+    ("tomato", 0xff6347),
+    ("turquoise", 0x40e0d0),
+    ("violet", 0xee82ee),
+    ("wheat", 0xf5deb3),
+    ("white", 0xffffff),
+    ("whitesmoke", 0xf5f5f5),
+    ("yellow", 0xffff00),
+    ("yellowgreen", 0x9acd32),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rgba(r: u8, g: u8, b: u8, a: u8) -> Color {
+        Color { r, g, b, a }
+    }
+
+    #[test]
+    fn test_named_colors_are_sorted() {
+        assert!(NAMED_COLORS.windows(2).all(|w| w[0].0 < w[1].0));
+    }
+
+    #[test]
+    fn test_named() {
+        assert_eq!(named("red"), Some(rgba(255, 0, 0, 255)));
+        assert_eq!(named("RebeccaPurple"), Some(rgba(102, 51, 153, 255)));
+        assert_eq!(named("transparent"), Some(rgba(0, 0, 0, 0)));
+        assert_eq!(named("currentcolor"), None);
+        assert_eq!(named("auto"), None);
+    }
+
+    #[test]
+    fn test_parse_hex() {
+        assert_eq!(parse_hex("f00"), Some(rgba(255, 0, 0, 255)));
+        assert_eq!(parse_hex("f008"), Some(rgba(255, 0, 0, 136)));
+        assert_eq!(parse_hex("aabbcc"), Some(rgba(170, 187, 204, 255)));
+        assert_eq!(parse_hex("aabbcc80"), Some(rgba(170, 187, 204, 128)));
+        assert_eq!(parse_hex("abcde"), None);
+        assert_eq!(parse_hex("ggg"), None);
+    }
+
+    #[test]
+    fn test_parse_rgb() {
+        assert_eq!(
+            parse_function("rgb", "255, 128, 0"),
+            Some(rgba(255, 128, 0, 255))
+        );
+        assert_eq!(
+            parse_function("rgba", "100%, 0%, 0%, 0.5"),
+            Some(rgba(255, 0, 0, 128))
+        );
+        assert_eq!(
+            parse_function("rgb", "255 128 0 / 25%"),
+            Some(rgba(255, 128, 0, 64))
+        );
+        assert_eq!(
+            parse_function("rgb", "300 -5 0"),
+            Some(rgba(255, 0, 0, 255))
+        );
+        assert_eq!(parse_function("rgb", "1 2"), None);
+    }
+
+    #[test]
+    fn test_parse_hsl() {
+        assert_eq!(
+            parse_function("hsl", "120, 100%, 50%"),
+            Some(rgba(0, 255, 0, 255))
+        );
+        assert_eq!(
+            parse_function("hsla", "0.5turn 100% 25% / 0.5"),
+            Some(rgba(0, 128, 128, 128))
+        );
+        assert_eq!(
+            parse_function("hsl", "-120deg 100% 50%"),
+            Some(rgba(0, 0, 255, 255))
+        );
+    }
+
+    #[test]
+    fn test_parse_hwb() {
+        assert_eq!(parse_function("hwb", "0 0% 0%"), Some(rgba(255, 0, 0, 255)));
+        assert_eq!(
+            parse_function("hwb", "90deg 60% 60%"),
+            Some(rgba(128, 128, 128, 255))
+        );
+    }
+}
@@ -0,0 +1,72 @@
+//! `content` プロパティで使う関数
+//!
+//! https://www.w3.org/TR/css-content-3/#content-property
+
+use super::{valid_identifier_char, Parser};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ContentFunction {
+    /// `attr(name)`: 擬似要素を生成した要素の属性の値
+    Attr(String),
+    /// `counter(name, style)` と `counters(name, separator, style)`
+    ///
+    /// `counter()` では `separator` が `None` で、最も内側のカウンタだけを表す
+    Counter {
+        name: String,
+        separator: Option<String>,
+        /// 省略されたら `decimal`
+        style: String,
+    },
+}
+
+enum Argument {
+    Ident(String),
+    String(String),
+}
+
+/// `args` は括弧の中身。文法に合わなければ `None`
+pub fn parse_function(name: &str, args: &str) -> Option<ContentFunction> {
+    let mut parser = Parser::new(args.to_string());
+    let mut arguments = Vec::new();
+    loop {
+        parser.consume_whitespace();
+        if parser.eof() {
+            return None;
+        }
+        arguments.push(match parser.next_char() {
+            '"' | '\'' => Argument::String(parser.parse_string()),
+            c if valid_identifier_char(c) => Argument::Ident(parser.parse_identifier()),
+            _ => return None,
+        });
+        parser.consume_whitespace();
+        if parser.eof() {
+            break;
+        }
+        if parser.consume_char() != ',' {
+            return None;
+        }
+    }
+
+    let counter = |name: &str, separator: Option<&str>, style: Option<&str>| {
+        Some(ContentFunction::Counter {
+            name: name.to_string(),
+            separator: separator.map(str::to_string),
+            style: style.unwrap_or("decimal").to_string(),
+        })
+    };
+    match (&*name.to_ascii_lowercase(), &arguments[..]) {
+        ("attr", [Argument::Ident(name)]) => Some(ContentFunction::Attr(name.clone())),
+        ("counter", [Argument::Ident(name)]) => counter(name, None, None),
+        ("counter", [Argument::Ident(name), Argument::Ident(style)]) => {
+            counter(name, None, Some(style))
+        }
+        ("counters", [Argument::Ident(name), Argument::String(separator)]) => {
+            counter(name, Some(separator), None)
+        }
+        (
+            "counters",
+            [Argument::Ident(name), Argument::String(separator), Argument::Ident(style)],
+        ) => counter(name, Some(separator), Some(style)),
+        _ => None,
+    }
+}
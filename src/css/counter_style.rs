@@ -0,0 +1,294 @@
+//! 定義済みのカウンタスタイル
+//!
+//! https://www.w3.org/TR/css-counter-styles-3/#predefined-counters
+
+/// カウンタの値を表記する方式
+///
+/// https://www.w3.org/TR/css-counter-styles-3/#counter-style-system
+enum System {
+    /// 値によらず同じ記号
+    Cyclic(&'static str),
+    /// 記号を桁として使う位取り記数法
+    Numeric(&'static [&'static str]),
+    /// 1 から始まり、`a, b, ..., z, aa, ab, ...` と数える
+    Alphabetic(&'static [&'static str]),
+    /// 値の大きい順に記号を足していく (ローマ数字)
+    Additive(&'static [(u32, &'static str)]),
+    /// 4桁ごとに区切る漢数字
+    ///
+    /// https://www.w3.org/TR/css-counter-styles-3/#limited-japanese
+    Japanese { formal: bool },
+}
+
+struct CounterStyle {
+    system: System,
+    suffix: &'static str,
+    /// 表記できる値の範囲。範囲外の値は `decimal` で表す
+    range: (i32, i32),
+}
+
+const DECIMAL: &[&str] = &["0", "1", "2", "3", "4", "5", "6", "7", "8", "9"];
+
+const CJK_DECIMAL: &[&str] = &["〇", "一", "二", "三", "四", "五", "六", "七", "八", "九"];
+
+const LOWER_LATIN: &[&str] = &[
+    "a", "b", "c", "d", "e", "f", "g", "h", "i", "j", "k", "l", "m", "n", "o", "p", "q", "r", "s",
+    "t", "u", "v", "w", "x", "y", "z",
+];
+
+const UPPER_LATIN: &[&str] = &[
+    "A", "B", "C", "D", "E", "F", "G", "H", "I", "J", "K", "L", "M", "N", "O", "P", "Q", "R", "S",
+    "T", "U", "V", "W", "X", "Y", "Z",
+];
+
+const LOWER_GREEK: &[&str] = &[
+    "α", "β", "γ", "δ", "ε", "ζ", "η", "θ", "ι", "κ", "λ", "μ", "ν", "ξ", "ο", "π", "ρ", "σ", "τ",
+    "υ", "φ", "χ", "ψ", "ω",
+];
+
+const HIRAGANA: &[&str] = &[
+    "あ", "い", "う", "え", "お", "か", "き", "く", "け", "こ", "さ", "し", "す", "せ", "そ", "た",
+    "ち", "つ", "て", "と", "な", "に", "ぬ", "ね", "の", "は", "ひ", "ふ", "へ", "ほ", "ま", "み",
+    "む", "め", "も", "や", "ゆ", "よ", "ら", "り", "る", "れ", "ろ", "わ", "ゐ", "ゑ", "を", "ん",
+];
+
+const KATAKANA: &[&str] = &[
+    "ア", "イ", "ウ", "エ", "オ", "カ", "キ", "ク", "ケ", "コ", "サ", "シ", "ス", "セ", "ソ", "タ",
+    "チ", "ツ", "テ", "ト", "ナ", "ニ", "ヌ", "ネ", "ノ", "ハ", "ヒ", "フ", "ヘ", "ホ", "マ", "ミ",
+    "ム", "メ", "モ", "ヤ", "ユ", "ヨ", "ラ", "リ", "ル", "レ", "ロ", "ワ", "ヰ", "ヱ", "ヲ", "ン",
+];
+
+const HIRAGANA_IROHA: &[&str] = &[
+    "い", "ろ", "は", "に", "ほ", "へ", "と", "ち", "り", "ぬ", "る", "を", "わ", "か", "よ", "た",
+    "れ", "そ", "つ", "ね", "な", "ら", "む", "う", "ゐ", "の", "お", "く", "や", "ま", "け", "ふ",
+    "こ", "え", "て", "あ", "さ", "き", "ゆ", "め", "み", "し", "ゑ", "ひ", "も", "せ", "す",
+];
+
+const KATAKANA_IROHA: &[&str] = &[
+    "イ", "ロ", "ハ", "ニ", "ホ", "ヘ", "ト", "チ", "リ", "ヌ", "ル", "ヲ", "ワ", "カ", "ヨ", "タ",
+    "レ", "ソ", "ツ", "ネ", "ナ", "ラ", "ム", "ウ", "ヰ", "ノ", "オ", "ク", "ヤ", "マ", "ケ", "フ",
+    "コ", "エ", "テ", "ア", "サ", "キ", "ユ", "メ", "ミ", "シ", "ヱ", "ヒ", "モ", "セ", "ス",
+];
+
+const LOWER_ROMAN: &[(u32, &str)] = &[
+    (1000, "m"),
+    (900, "cm"),
+    (500, "d"),
+    (400, "cd"),
+    (100, "c"),
+    (90, "xc"),
+    (50, "l"),
+    (40, "xl"),
+    (10, "x"),
+    (9, "ix"),
+    (5, "v"),
+    (4, "iv"),
+    (1, "i"),
+];
+
+const UPPER_ROMAN: &[(u32, &str)] = &[
+    (1000, "M"),
+    (900, "CM"),
+    (500, "D"),
+    (400, "CD"),
+    (100, "C"),
+    (90, "XC"),
+    (50, "L"),
+    (40, "XL"),
+    (10, "X"),
+    (9, "IX"),
+    (5, "V"),
+    (4, "IV"),
+    (1, "I"),
+];
+
+const ALL: (i32, i32) = (i32::MIN, i32::MAX);
+
+/// 未知の名前は `decimal` として扱う
+fn counter_style(name: &str) -> CounterStyle {
+    let style = |system, suffix, range| CounterStyle {
+        system,
+        suffix,
+        range,
+    };
+    match name {
+        "disc" => style(System::Cyclic("\u{2022}"), " ", ALL),
+        "circle" => style(System::Cyclic("\u{25e6}"), " ", ALL),
+        "square" => style(System::Cyclic("\u{25aa}"), " ", ALL),
+        "disclosure-open" => style(System::Cyclic("\u{25be}"), " ", ALL),
+        "disclosure-closed" => style(System::Cyclic("\u{25b8}"), " ", ALL),
+        "cjk-decimal" => style(System::Numeric(CJK_DECIMAL), "、", ALL),
+        "lower-alpha" | "lower-latin" => {
+            style(System::Alphabetic(LOWER_LATIN), ". ", (1, i32::MAX))
+        }
+        "upper-alpha" | "upper-latin" => {
+            style(System::Alphabetic(UPPER_LATIN), ". ", (1, i32::MAX))
+        }
+        "lower-greek" => style(System::Alphabetic(LOWER_GREEK), ". ", (1, i32::MAX)),
+        "hiragana" => style(System::Alphabetic(HIRAGANA), "、", (1, i32::MAX)),
+        "katakana" => style(System::Alphabetic(KATAKANA), "、", (1, i32::MAX)),
+        "hiragana-iroha" => style(System::Alphabetic(HIRAGANA_IROHA), "、", (1, i32::MAX)),
+        "katakana-iroha" => style(System::Alphabetic(KATAKANA_IROHA), "、", (1, i32::MAX)),
+        "lower-roman" => style(System::Additive(LOWER_ROMAN), ". ", (1, 3999)),
+        "upper-roman" => style(System::Additive(UPPER_ROMAN), ". ", (1, 3999)),
+        "japanese-informal" => style(System::Japanese { formal: false }, "、", ALL),
+        "japanese-formal" => style(System::Japanese { formal: true }, "、", ALL),
+        _ => style(System::Numeric(DECIMAL), ". ", ALL),
+    }
+}
+
+/// `value` を `style` の表記にする (`counter()` の値)。`none` なら空
+pub fn represent(value: i32, style: &str) -> String {
+    if style == "none" {
+        return String::new();
+    }
+    let counter_style = counter_style(style);
+    let (min, max) = counter_style.range;
+    if !(min..=max).contains(&value) {
+        return represent(value, "decimal");
+    }
+    let magnitude = value.unsigned_abs();
+    let representation = match counter_style.system {
+        System::Cyclic(symbol) => return symbol.to_string(),
+        System::Numeric(digits) => numeric(magnitude, digits),
+        System::Alphabetic(letters) => alphabetic(magnitude, letters),
+        System::Additive(symbols) => additive(magnitude, symbols),
+        System::Japanese { formal } => japanese(magnitude, formal),
+    };
+    if value >= 0 {
+        representation
+    } else if let System::Japanese { .. } = counter_style.system {
+        format!("マイナス{}", representation)
+    } else {
+        format!("-{}", representation)
+    }
+}
+
+/// リスト項目のマーカーの文字列。表記に接尾辞 (`". "` など) を付けたもの
+pub fn marker(value: i32, style: &str) -> String {
+    if style == "none" {
+        return String::new();
+    }
+    represent(value, style) + counter_style(style).suffix
+}
+
+fn numeric(mut value: u32, digits: &[&str]) -> String {
+    let base = digits.len() as u32;
+    let mut result = Vec::new();
+    loop {
+        result.push(digits[(value % base) as usize]);
+        value /= base;
+        if value == 0 {
+            break;
+        }
+    }
+    result.iter().rev().copied().collect()
+}
+
+/// `value` は1以上
+fn alphabetic(mut value: u32, letters: &[&str]) -> String {
+    let base = letters.len() as u32;
+    let mut result = Vec::new();
+    while value > 0 {
+        value -= 1;
+        result.push(letters[(value % base) as usize]);
+        value /= base;
+    }
+    result.iter().rev().copied().collect()
+}
+
+fn additive(mut value: u32, symbols: &[(u32, &str)]) -> String {
+    let mut result = String::new();
+    for &(weight, symbol) in symbols {
+        while value >= weight {
+            result.push_str(symbol);
+            value -= weight;
+        }
+    }
+    result
+}
+
+/// 例えば 12345 は、略式で「一万二千三百四十五」、正式で「壱萬弐阡参百四拾伍」
+fn japanese(value: u32, formal: bool) -> String {
+    let (digits, digit_markers, group_markers) = if formal {
+        (
+            ["零", "壱", "弐", "参", "四", "伍", "六", "七", "八", "九"],
+            ["", "拾", "百", "阡"],
+            ["", "萬", "億", "兆"],
+        )
+    } else {
+        (
+            ["〇", "一", "二", "三", "四", "五", "六", "七", "八", "九"],
+            ["", "十", "百", "千"],
+            ["", "万", "億", "兆"],
+        )
+    };
+    if value == 0 {
+        return digits[0].to_string();
+    }
+
+    let mut groups = Vec::new();
+    let mut rest = value;
+    while rest > 0 {
+        groups.push(rest % 10000);
+        rest /= 10000;
+    }
+    let mut result = String::new();
+    for (i, &group) in groups.iter().enumerate().rev() {
+        // 0 の桁と、すべての桁が 0 の区切りは書かない
+        if group == 0 {
+            continue;
+        }
+        for position in (0..4).rev() {
+            let digit = (group / 10u32.pow(position)) % 10;
+            if digit == 0 {
+                continue;
+            }
+            // 略式では、十、百、千の前の一を省く
+            if formal || digit != 1 || position == 0 {
+                result.push_str(digits[digit as usize]);
+            }
+            result.push_str(digit_markers[position as usize]);
+        }
+        result.push_str(group_markers[i]);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_represent() {
+        let cases = [
+            (7, "decimal", "7"),
+            (-12, "decimal", "-12"),
+            (3, "unknown-style", "3"),
+            (5, "disc", "\u{2022}"),
+            (28, "lower-alpha", "ab"),
+            (0, "upper-alpha", "0"),
+            (3, "lower-greek", "γ"),
+            (1994, "upper-roman", "MCMXCIV"),
+            (4000, "lower-roman", "4000"),
+            (45, "hiragana", "ゐ"),
+            (49, "hiragana", "ああ"),
+            (2, "katakana-iroha", "ロ"),
+            (105, "cjk-decimal", "一〇五"),
+            (0, "japanese-informal", "〇"),
+            (12345, "japanese-informal", "一万二千三百四十五"),
+            (10010, "japanese-informal", "一万十"),
+            (12345, "japanese-formal", "壱萬弐阡参百四拾伍"),
+            (-3, "japanese-formal", "マイナス参"),
+            (3, "none", ""),
+        ];
+        for (value, style, expected) in cases {
+            assert_eq!(represent(value, style), expected, "{} {}", value, style);
+        }
+
+        assert_eq!(marker(3, "decimal"), "3. ");
+        assert_eq!(marker(3, "square"), "\u{25aa} ");
+        assert_eq!(marker(3, "japanese-formal"), "参、");
+        assert_eq!(marker(3, "none"), "");
+    }
+}
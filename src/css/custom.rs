@@ -0,0 +1,272 @@
+//! カスタムプロパティと `var()` 参照
+//!
+//! https://www.w3.org/TR/css-variables-1/
+
+use std::fmt;
+
+use super::{shorthand, Parser, Value};
+
+/// 構文解析を計算値の時点まで遅らせる宣言値
+///
+/// カスタムプロパティの値と、`var()` を含む宣言の値がこれになる
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnparsedValue {
+    pub tokens: TokenStream,
+    /// ショートハンドの値に `var()` が含まれていた場合の、そのショートハンドの名前
+    ///
+    /// 置換後にショートハンドを展開し、このロングハンドの値を取り出す
+    pub shorthand: Option<String>,
+}
+
+/// `var()` 参照とそれ以外の部分に分けた宣言値
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TokenStream(pub Vec<Token>);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    /// `var()` を含まない、ソースそのままの文字列
+    Text(String),
+    /// `var(<name>, <fallback>?)`
+    Var {
+        name: String,
+        fallback: Option<TokenStream>,
+    },
+}
+
+/// カスタムプロパティの名前かどうか
+pub fn is_custom_property(name: &str) -> bool {
+    name.starts_with("--")
+}
+
+impl TokenStream {
+    /// 宣言値の文字列を分解する。不正な `var()` や対応の取れていない `)` があれば `None`
+    pub fn parse(text: &str) -> Option<TokenStream> {
+        let mut parser = Parser::new(text.to_string());
+        let tokens = parser.parse_token_stream()?;
+        parser.eof().then_some(tokens)
+    }
+
+    /// `var()` を1つ以上含むかどうか
+    pub fn has_references(&self) -> bool {
+        self.0
+            .iter()
+            .any(|token| matches!(token, Token::Var { .. }))
+    }
+
+    /// `var()` を `lookup` で得た値で置き換えた文字列を返す
+    ///
+    /// 参照先が無効でフォールバックもない場合は `None` (計算値の時点で無効) になる
+    pub fn substitute<F>(&self, lookup: &mut F) -> Option<String>
+    where
+        F: FnMut(&str) -> Option<String>,
+    {
+        let mut result = String::new();
+        for token in &self.0 {
+            match *token {
+                Token::Text(ref text) => result.push_str(text),
+                Token::Var {
+                    ref name,
+                    ref fallback,
+                } => match lookup(name) {
+                    Some(value) => result.push_str(&value),
+                    None => result.push_str(&fallback.as_ref()?.substitute(lookup)?),
+                },
+            }
+        }
+        Some(result)
+    }
+}
+
+impl fmt::Display for TokenStream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for token in &self.0 {
+            match *token {
+                Token::Text(ref text) => f.write_str(text)?,
+                Token::Var {
+                    ref name,
+                    fallback: None,
+                } => write!(f, "var({})", name)?,
+                Token::Var {
+                    ref name,
+                    fallback: Some(ref fallback),
+                } => write!(f, "var({},{})", name, fallback)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl UnparsedValue {
+    /// `var()` を置換した後の文字列 `text` を、ロングハンド `name` の値として解析する
+    ///
+    /// 文法に合わなければ `None` (計算値の時点で無効) を返す
+    pub fn parse_substituted(&self, name: &str, text: &str) -> Option<Value> {
+        let mut parser = Parser::new(text.to_string());
+        let values = parser.parse_values()?;
+        parser.consume_whitespace();
+        if values.is_empty() || !parser.eof() {
+            return None;
+        }
+        match self.shorthand {
+            Some(ref shorthand) => shorthand::expand(shorthand, values)?
+                .into_iter()
+                .find(|declaration| declaration.name == name)
+                .map(|declaration| declaration.value),
+            None => Some(shorthand::into_value(values)),
+        }
+    }
+}
+
+impl Parser {
+    /// 入力の終わりか、対応の取れていない `)` までを読む
+    fn parse_token_stream(&mut self) -> Option<TokenStream> {
+        let mut tokens = Vec::new();
+        let mut text = String::new();
+        let mut depth = 0;
+        while !self.eof() {
+            match self.next_char() {
+                ')' if depth == 0 => break,
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                '"' | '\'' => {
+                    let start = self.pos;
+                    self.parse_string();
+                    text.push_str(&self.input[start..self.pos]);
+                    continue;
+                }
+                _ if self.starts_var_function() => {
+                    if !text.is_empty() {
+                        tokens.push(Token::Text(std::mem::take(&mut text)));
+                    }
+                    tokens.push(self.parse_var_function()?);
+                    continue;
+                }
+                _ => {}
+            }
+            text.push(self.consume_char());
+        }
+        if !text.is_empty() {
+            tokens.push(Token::Text(text));
+        }
+        Some(TokenStream(tokens))
+    }
+
+    /// 大文字小文字を区別せず `var(` で始まり、直前が識別子の途中でないかどうか
+    fn starts_var_function(&self) -> bool {
+        self.input[self.pos..]
+            .get(..4)
+            .is_some_and(|prefix| prefix.eq_ignore_ascii_case("var("))
+            && !self.input[..self.pos]
+                .chars()
+                .next_back()
+                .is_some_and(super::valid_identifier_char)
+    }
+
+    /// 名前が `--` で始まらないか、括弧が閉じていなければ `None`
+    fn parse_var_function(&mut self) -> Option<Token> {
+        self.parse_identifier();
+        self.consume_char();
+        self.consume_whitespace();
+        let name = self.parse_identifier();
+        if !is_custom_property(&name) {
+            return None;
+        }
+        self.consume_whitespace();
+        let fallback = match (!self.eof()).then(|| self.consume_char()) {
+            Some(')') => {
+                return Some(Token::Var {
+                    name,
+                    fallback: None,
+                })
+            }
+            Some(',') => Some(self.parse_token_stream()?),
+            _ => return None,
+        };
+        if self.eof() || self.consume_char() != ')' {
+            return None;
+        }
+        Some(Token::Var { name, fallback })
+    }
+
+    /// 宣言値を `;`、`!`、`}` のいずれか (括弧と文字列の外側にあるもの) の手前まで読む
+    pub(super) fn parse_raw_value(&mut self) -> String {
+        let start = self.pos;
+        let mut depth = 0;
+        while !self.eof() {
+            match self.next_char() {
+                ';' | '!' | '}' if depth == 0 => break,
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' | '}' => depth -= 1,
+                '"' | '\'' => {
+                    self.parse_string();
+                    continue;
+                }
+                _ => {}
+            }
+            self.consume_char();
+        }
+        self.input[start..self.pos].trim().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(s: &str) -> Token {
+        Token::Text(s.to_string())
+    }
+
+    #[test]
+    fn test_parse_token_stream() {
+        let tokens = TokenStream::parse("1px solid var(--color, var(--fallback, red)) ").unwrap();
+        assert_eq!(
+            tokens,
+            TokenStream(vec![
+                text("1px solid "),
+                Token::Var {
+                    name: "--color".to_string(),
+                    fallback: Some(TokenStream(vec![
+                        text(" "),
+                        Token::Var {
+                            name: "--fallback".to_string(),
+                            fallback: Some(TokenStream(vec![text(" red")])),
+                        },
+                    ])),
+                },
+                text(" "),
+            ])
+        );
+        assert!(tokens.has_references());
+        assert_eq!(
+            tokens.to_string(),
+            "1px solid var(--color, var(--fallback, red)) "
+        );
+
+        let tokens = TokenStream::parse("rgb(0 0 0) 'var(--x)' navar(1)").unwrap();
+        assert!(!tokens.has_references());
+
+        // 不正な `var()` は宣言ごと無効になる
+        for text in ["var(;", "var(--x }", "var(x)", "var(--x, 1px", "1px)"] {
+            assert_eq!(TokenStream::parse(text), None, "{}", text);
+        }
+        assert!(TokenStream::parse("ab\u{65e5}").is_some());
+        assert_eq!(
+            super::super::expand_declaration("color", "var(;").err(),
+            Some(super::super::ParseErrorKind::InvalidValue)
+        );
+    }
+
+    #[test]
+    fn test_substitute() {
+        let tokens = TokenStream::parse("var(--a) var(--b, 2px) var(--c,)").unwrap();
+        let mut lookup = |name: &str| (name == "--a").then(|| "1px".to_string());
+        assert_eq!(
+            tokens.substitute(&mut lookup),
+            Some("1px  2px ".to_string())
+        );
+
+        let tokens = TokenStream::parse("var(--b)").unwrap();
+        assert_eq!(tokens.substitute(&mut lookup), None);
+    }
+}
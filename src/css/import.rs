@@ -0,0 +1,336 @@
+//! `@import` 規則の構文解析と読み込み
+//!
+//! https://www.w3.org/TR/css-cascade-5/#at-import
+
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use super::{
+    parse_with_errors, CssRule, ImportLayer, ImportRule, LayerName, Origin, ParseError,
+    ParseErrorKind, Parser, Stylesheet,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImportError {
+    /// ファイルを読めなかった
+    Io(PathBuf, io::ErrorKind),
+    /// 読み込み中のスタイルシートを再び読み込もうとした
+    Cycle(PathBuf),
+}
+
+/// 読み込んだファイルのうち `path` で無視した宣言や規則
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileParseError {
+    pub path: PathBuf,
+    pub error: ParseError,
+}
+
+impl fmt::Display for FileParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.error)
+    }
+}
+
+/// `path` のスタイルシートを読み込み、`@import` を再帰的に解決する
+///
+/// 参照先はそれを読み込むスタイルシートのあるディレクトリからの相対パスとして解決する
+/// 読み込んだ全てのファイルで無視した宣言や規則をエラーとして返す
+/// 読み込めなかった `@import` は `ImportRule::stylesheet` にエラーを記録し、カスケードでは無視される
+pub fn load(path: &Path, origin: Origin) -> Result<(Stylesheet, Vec<FileParseError>), ImportError> {
+    let mut errors = Vec::new();
+    let stylesheet = load_recursive(path, origin, &mut Vec::new(), &mut errors)?;
+    Ok((stylesheet, errors))
+}
+
+/// `stack` は読み込み中のスタイルシートのパス
+fn load_recursive(
+    path: &Path,
+    origin: Origin,
+    stack: &mut Vec<PathBuf>,
+    errors: &mut Vec<FileParseError>,
+) -> Result<Stylesheet, ImportError> {
+    let io_error = |e: io::Error| ImportError::Io(path.to_path_buf(), e.kind());
+    let canonical = path.canonicalize().map_err(io_error)?;
+    if stack.contains(&canonical) {
+        return Err(ImportError::Cycle(path.to_path_buf()));
+    }
+    let source = fs::read_to_string(path).map_err(io_error)?;
+
+    let (mut stylesheet, parse_errors) = parse_with_errors(source, origin);
+    stylesheet.path = Some(path.to_path_buf());
+    errors.extend(parse_errors.into_iter().map(|error| FileParseError {
+        path: path.to_path_buf(),
+        error,
+    }));
+    let base = path.parent().unwrap_or_else(|| Path::new(""));
+    stack.push(canonical);
+    for rule in &mut stylesheet.rules {
+        if let CssRule::Import(ref mut import) = *rule {
+            let path = base.join(&import.url);
+            import.stylesheet = Some(load_recursive(&path, origin, stack, errors));
+        }
+    }
+    stack.pop();
+    Ok(stylesheet)
+}
+
+impl Stylesheet {
+    /// 読み込めなかったものも含め、`@import` で参照されたスタイルシートを深さ優先で列挙する
+    pub fn imports(&self) -> Vec<&ImportRule> {
+        let mut imports = Vec::new();
+        collect_imports(self, &mut imports);
+        imports
+    }
+}
+
+fn collect_imports<'a>(stylesheet: &'a Stylesheet, imports: &mut Vec<&'a ImportRule>) {
+    for rule in &stylesheet.rules {
+        if let CssRule::Import(ref import) = *rule {
+            imports.push(import);
+            if let Some(Ok(ref child)) = import.stylesheet {
+                collect_imports(child, imports);
+            }
+        }
+    }
+}
+
+impl Parser {
+    /// `@import` に続く `<url> [layer | layer(<name>)]? <media-query-list>;` を読む
+    ///
+    /// 文法に合わなければエラーを記録し、規則を読み飛ばして `None` を返す
+    pub(super) fn parse_import_rule(&mut self) -> Option<ImportRule> {
+        let line = self.location().line;
+        let start = self.pos;
+        let rule = self.parse_import_prelude();
+        if rule.is_none() {
+            self.pos = start;
+            self.skip_at_rule();
+            self.errors.push(ParseError {
+                line,
+                kind: ParseErrorKind::InvalidRule,
+                property: String::from("@import"),
+                value: self.input[start..self.pos]
+                    .trim_end_matches(';')
+                    .trim()
+                    .to_string(),
+            });
+        }
+        rule
+    }
+
+    fn parse_import_prelude(&mut self) -> Option<ImportRule> {
+        let url = self.parse_url()?;
+        self.consume_whitespace();
+
+        let layer = if self.starts_with_keyword("layer") {
+            self.parse_identifier();
+            if self.consume_expected('(').is_some() {
+                self.consume_whitespace();
+                let name = self.parse_layer_name();
+                if name.iter().any(String::is_empty) {
+                    return None;
+                }
+                self.consume_whitespace();
+                self.consume_expected(')')?;
+                Some(ImportLayer::Named(name))
+            } else {
+                Some(ImportLayer::Anonymous)
+            }
+        } else {
+            None
+        };
+
+        let media = self.parse_media_query_list();
+        self.consume_expected(';')?;
+        Some(ImportRule {
+            url,
+            layer,
+            media,
+            stylesheet: None,
+        })
+    }
+
+    /// `url(...)` または文字列
+    fn parse_url(&mut self) -> Option<String> {
+        if self.eof() {
+            return None;
+        }
+        match self.next_char() {
+            '"' | '\'' => self.try_parse_string(),
+            _ => {
+                let name = self.parse_identifier();
+                if !name.eq_ignore_ascii_case("url") {
+                    return None;
+                }
+                self.consume_expected('(')?;
+                self.consume_whitespace();
+                let url = match (!self.eof()).then(|| self.next_char()) {
+                    Some('"' | '\'') => self.try_parse_string()?,
+                    _ => self.consume_while(|c| c != ')' && !c.is_whitespace()),
+                };
+                self.consume_whitespace();
+                self.consume_expected(')')?;
+                Some(url)
+            }
+        }
+    }
+
+    /// ドット区切りのレイヤー名 (`a.b`)
+    pub(super) fn parse_layer_name(&mut self) -> LayerName {
+        let mut name = vec![self.parse_identifier()];
+        while !self.eof() && self.next_char() == '.' {
+            self.consume_char();
+            name.push(self.parse_identifier());
+        }
+        name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::css::{media::MediaQueryList, parse};
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("test/import")
+            .join(name)
+    }
+
+    #[test]
+    fn test_parse_import() {
+        let stylesheet = parse(String::from(
+            r#"
+            @import "a.css";
+            @import url(b.css) layer;
+            @import url('c.css') layer(base.reset) screen;
+            p { color: red; }
+            @import "ignored.css";
+            "#,
+        ));
+
+        let imports = stylesheet.imports();
+        assert_eq!(imports.len(), 3);
+        assert_eq!(
+            *imports[0],
+            ImportRule {
+                url: String::from("a.css"),
+                layer: None,
+                media: MediaQueryList(vec![]),
+                stylesheet: None,
+            }
+        );
+        assert_eq!(imports[1].url, "b.css");
+        assert_eq!(imports[1].layer, Some(ImportLayer::Anonymous));
+        assert_eq!(imports[2].url, "c.css");
+        assert_eq!(
+            imports[2].layer,
+            Some(ImportLayer::Named(vec![
+                String::from("base"),
+                String::from("reset")
+            ]))
+        );
+        assert_eq!(imports[2].media.0.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_invalid_import() {
+        let (stylesheet, errors) = parse_with_errors(
+            String::from(
+                r#"
+            @import ;
+            @import url(a.css;
+            @import "b.css" layer(;
+            @import "c.css" { p { color: red; } }
+            @import "d.css" screen;
+            @import "unterminated.css"#,
+            ),
+            Origin::Author,
+        );
+
+        let urls: Vec<&str> = stylesheet
+            .imports()
+            .iter()
+            .map(|import| &*import.url)
+            .collect();
+        assert_eq!(urls, vec!["d.css"]);
+        assert_eq!(
+            errors
+                .iter()
+                .map(|error| (error.line, error.kind, &*error.value))
+                .collect::<Vec<_>>(),
+            vec![
+                (2, ParseErrorKind::InvalidRule, ""),
+                (3, ParseErrorKind::InvalidRule, "url(a.css"),
+                (4, ParseErrorKind::InvalidRule, "\"b.css\" layer("),
+                (
+                    5,
+                    ParseErrorKind::InvalidRule,
+                    "\"c.css\" { p { color: red; } }"
+                ),
+                (7, ParseErrorKind::InvalidRule, "\"unterminated.css"),
+            ]
+        );
+        assert_eq!(errors[0].to_string(), "line 2: invalid @import rule \"\"");
+    }
+
+    #[test]
+    fn test_load() {
+        let (stylesheet, errors) = load(&fixture("main.css"), Origin::Author).unwrap();
+        assert_eq!(errors, vec![]);
+
+        let urls: Vec<&str> = stylesheet
+            .imports()
+            .iter()
+            .map(|import| &*import.url)
+            .collect();
+        assert_eq!(urls, vec!["base.css", "parts/colors.css", "missing.css"]);
+
+        let imports = stylesheet.imports();
+        assert!(matches!(imports[0].stylesheet, Some(Ok(_))));
+        assert!(matches!(imports[1].stylesheet, Some(Ok(_))));
+        assert_eq!(
+            imports[2].stylesheet,
+            Some(Err(ImportError::Io(
+                fixture("missing.css"),
+                io::ErrorKind::NotFound
+            )))
+        );
+    }
+
+    #[test]
+    fn test_load_errors() {
+        let (stylesheet, errors) = load(&fixture("invalid.css"), Origin::Author).unwrap();
+
+        assert!(matches!(stylesheet.imports()[0].stylesheet, Some(Ok(_))));
+        let errors: Vec<String> = errors.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            errors,
+            vec![
+                format!(
+                    "{}: line 2: invalid @import rule \"url(\"",
+                    fixture("invalid.css").display()
+                ),
+                format!(
+                    "{}: line 2: unknown property colour",
+                    fixture("parts/invalid.css").display()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_import_cycle() {
+        let (stylesheet, _) = load(&fixture("cycle-a.css"), Origin::Author).unwrap();
+
+        let imports = stylesheet.imports();
+        assert_eq!(imports.len(), 2);
+        assert!(matches!(imports[0].stylesheet, Some(Ok(_))));
+        assert_eq!(
+            imports[1].stylesheet,
+            Some(Err(ImportError::Cycle(fixture("cycle-a.css"))))
+        );
+    }
+}
@@ -0,0 +1,683 @@
+//! メディアクエリ
+//!
+//! https://www.w3.org/TR/mediaqueries-4/
+
+use super::{valid_identifier_char, LengthContext, Parser, Unit};
+use crate::font;
+
+/// カンマ区切りのメディアクエリの並び。いずれかが一致すれば一致する
+///
+/// 空のリストは常に一致する
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaQueryList(pub Vec<MediaQuery>);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaQuery {
+    pub qualifier: Option<Qualifier>,
+    pub media_type: MediaType,
+    pub condition: Option<MediaCondition>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Qualifier {
+    Only,
+    Not,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MediaType {
+    All,
+    Screen,
+    Print,
+    /// 未知のメディア型は一致しない
+    Unknown(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MediaCondition {
+    Feature(MediaFeature),
+    Not(Box<MediaCondition>),
+    And(Vec<MediaCondition>),
+    Or(Vec<MediaCondition>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MediaFeature {
+    /// `(orientation)` のように値を持たない形式
+    Boolean(String),
+    /// `(orientation: portrait)` の形式
+    Plain(String, MediaFeatureValue),
+    /// `(width >= 600px)` などの範囲形式
+    ///
+    /// `min-width`/`max-width` もこの形式に変換し、比較は常に「特性 演算子 値」の向きで持つ
+    Range(String, Vec<(Comparison, MediaFeatureValue)>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    Lt,
+    Le,
+    Eq,
+    Ge,
+    Gt,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MediaFeatureValue {
+    Number(f32),
+    Length(f32, Unit),
+    /// dppx 単位の解像度
+    Resolution(f32),
+    Ident(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorScheme {
+    Light,
+    Dark,
+}
+
+/// メディアクエリを評価する対象の出力デバイス
+#[derive(Debug, Clone, PartialEq)]
+pub struct Device {
+    pub media_type: MediaType,
+    /// ビューポートの幅 (px)
+    pub width: f32,
+    /// ビューポートの高さ (px)
+    pub height: f32,
+    /// CSS ピクセルあたりのデバイスピクセル数
+    pub resolution: f32,
+    pub prefers_color_scheme: ColorScheme,
+}
+
+impl Device {
+    /// 指定された大きさのビューポートを持つ画面
+    pub fn new(width: f32, height: f32) -> Device {
+        Device {
+            media_type: MediaType::Screen,
+            width,
+            height,
+            resolution: 1.0,
+            prefers_color_scheme: ColorScheme::Light,
+        }
+    }
+
+    /// メディアクエリ中の長さの基準値
+    ///
+    /// フォント相対長さは初期値のフォントサイズと既定のフォントを基準にする
+    fn length_context(&self) -> LengthContext {
+        LengthContext {
+            font_size: 16.0,
+            root_font_size: 16.0,
+            viewport_width: self.width,
+            viewport_height: self.height,
+            ..LengthContext::default()
+        }
+        .with_font(font::database().default_font())
+    }
+}
+
+impl MediaQueryList {
+    pub fn matches(&self, device: &Device) -> bool {
+        self.0.is_empty() || self.0.iter().any(|query| query.matches(device))
+    }
+}
+
+impl MediaQuery {
+    pub fn matches(&self, device: &Device) -> bool {
+        let type_matches = match self.media_type {
+            MediaType::All => true,
+            MediaType::Unknown(_) => false,
+            ref media_type => *media_type == device.media_type,
+        };
+        // 条件が不明なクエリは、`not` が付いていても一致しない
+        let matches = match self.condition {
+            Some(ref condition) if type_matches => condition.evaluate(device),
+            _ => Some(type_matches),
+        };
+        match self.qualifier {
+            Some(Qualifier::Not) => matches.is_some_and(|matches| !matches),
+            _ => matches.unwrap_or(false),
+        }
+    }
+}
+
+impl MediaCondition {
+    /// 3値論理で評価する。未知の特性を含み結果が決まらなければ `None`
+    ///
+    /// https://www.w3.org/TR/mediaqueries-4/#evaluating
+    pub fn evaluate(&self, device: &Device) -> Option<bool> {
+        match *self {
+            MediaCondition::Feature(ref feature) => feature.evaluate(device),
+            MediaCondition::Not(ref condition) => condition.evaluate(device).map(|b| !b),
+            MediaCondition::And(ref conditions) => {
+                let results: Vec<_> = conditions.iter().map(|c| c.evaluate(device)).collect();
+                if results.contains(&Some(false)) {
+                    Some(false)
+                } else {
+                    results
+                        .into_iter()
+                        .collect::<Option<Vec<_>>>()
+                        .map(|_| true)
+                }
+            }
+            MediaCondition::Or(ref conditions) => {
+                let results: Vec<_> = conditions.iter().map(|c| c.evaluate(device)).collect();
+                if results.contains(&Some(true)) {
+                    Some(true)
+                } else {
+                    results
+                        .into_iter()
+                        .collect::<Option<Vec<_>>>()
+                        .map(|_| false)
+                }
+            }
+        }
+    }
+}
+
+impl MediaFeature {
+    /// 未知の特性や型の合わない値は `None` (不明)
+    pub fn evaluate(&self, device: &Device) -> Option<bool> {
+        match *self {
+            MediaFeature::Boolean(ref name) => match &**name {
+                "width" => Some(device.width != 0.0),
+                "height" => Some(device.height != 0.0),
+                "resolution" => Some(device.resolution != 0.0),
+                "orientation" | "prefers-color-scheme" => Some(true),
+                _ => None,
+            },
+            MediaFeature::Plain(ref name, ref value) => match (&**name, value) {
+                ("orientation", MediaFeatureValue::Ident(orientation)) => {
+                    let portrait = device.height >= device.width;
+                    match &**orientation {
+                        "portrait" => Some(portrait),
+                        "landscape" => Some(!portrait),
+                        _ => None,
+                    }
+                }
+                ("prefers-color-scheme", MediaFeatureValue::Ident(scheme)) => match &**scheme {
+                    "light" => Some(device.prefers_color_scheme == ColorScheme::Light),
+                    "dark" => Some(device.prefers_color_scheme == ColorScheme::Dark),
+                    _ => None,
+                },
+                ("orientation" | "prefers-color-scheme", _) => None,
+                _ => MediaFeature::Range(name.clone(), vec![(Comparison::Eq, value.clone())])
+                    .evaluate(device),
+            },
+            MediaFeature::Range(ref name, ref comparisons) => {
+                let results = comparisons.iter().map(|(comparison, value)| {
+                    let (actual, expected) = match (&**name, value) {
+                        ("width", _) => (device.width, value.to_px(device)?),
+                        ("height", _) => (device.height, value.to_px(device)?),
+                        ("resolution", MediaFeatureValue::Resolution(dppx)) => {
+                            (device.resolution, *dppx)
+                        }
+                        _ => return None,
+                    };
+                    Some(comparison.evaluate(actual, expected))
+                });
+                results
+                    .collect::<Option<Vec<_>>>()
+                    .map(|results| results.into_iter().all(|b| b))
+            }
+        }
+    }
+}
+
+impl MediaFeatureValue {
+    fn to_px(&self, device: &Device) -> Option<f32> {
+        match *self {
+            MediaFeatureValue::Length(f, unit) => Some(f * unit.to_px(&device.length_context())),
+            MediaFeatureValue::Number(0.0) => Some(0.0),
+            _ => None,
+        }
+    }
+}
+
+impl Comparison {
+    fn evaluate(self, actual: f32, expected: f32) -> bool {
+        match self {
+            Comparison::Lt => actual < expected,
+            Comparison::Le => actual <= expected,
+            Comparison::Eq => actual == expected,
+            Comparison::Ge => actual >= expected,
+            Comparison::Gt => actual > expected,
+        }
+    }
+
+    /// 左右を入れ替えた比較 (`600px < width` を `width > 600px` にする)
+    fn flip(self) -> Comparison {
+        match self {
+            Comparison::Lt => Comparison::Gt,
+            Comparison::Le => Comparison::Ge,
+            Comparison::Eq => Comparison::Eq,
+            Comparison::Ge => Comparison::Le,
+            Comparison::Gt => Comparison::Lt,
+        }
+    }
+}
+
+impl Parser {
+    /// `{` または `;` の手前までのメディアクエリの並びを読む
+    ///
+    /// 構文解析できないクエリは `not all` として扱う
+    /// https://www.w3.org/TR/mediaqueries-4/#error-handling
+    pub(super) fn parse_media_query_list(&mut self) -> MediaQueryList {
+        let mut queries = Vec::new();
+        loop {
+            self.consume_whitespace();
+            if self.eof() || matches!(self.next_char(), '{' | ';') {
+                break;
+            }
+            let mut parser = Parser::new(self.consume_media_query_text());
+            let query = parser
+                .parse_media_query()
+                .filter(|_| {
+                    parser.consume_whitespace();
+                    parser.eof()
+                })
+                .unwrap_or(MediaQuery {
+                    qualifier: Some(Qualifier::Not),
+                    media_type: MediaType::All,
+                    condition: None,
+                });
+            queries.push(query);
+            if !self.eof() && self.next_char() == ',' {
+                self.consume_char();
+            }
+        }
+        MediaQueryList(queries)
+    }
+
+    /// 括弧の外の `,` か、`{`、`;` の手前までの1つのクエリの文字列
+    fn consume_media_query_text(&mut self) -> String {
+        let mut depth = 0;
+        let mut text = String::new();
+        while !self.eof() {
+            match self.next_char() {
+                '{' | ';' => break,
+                ',' if depth == 0 => break,
+                '(' => depth += 1,
+                ')' if depth > 0 => depth -= 1,
+                _ => {}
+            }
+            text.push(self.consume_char());
+        }
+        text
+    }
+
+    fn parse_media_query(&mut self) -> Option<MediaQuery> {
+        if self.eof() {
+            return None;
+        }
+        if self.next_char() == '(' {
+            return Some(MediaQuery {
+                qualifier: None,
+                media_type: MediaType::All,
+                condition: Some(self.parse_media_condition()?),
+            });
+        }
+
+        let mut word = self.parse_media_identifier()?;
+        if word == "not" && self.peek_after_whitespace() == '(' {
+            self.consume_whitespace();
+            return Some(MediaQuery {
+                qualifier: None,
+                media_type: MediaType::All,
+                condition: Some(MediaCondition::Not(Box::new(self.parse_media_in_parens()?))),
+            });
+        }
+        let qualifier = match &*word {
+            "only" => Some(Qualifier::Only),
+            "not" => Some(Qualifier::Not),
+            _ => None,
+        };
+        if qualifier.is_some() {
+            self.consume_whitespace();
+            word = self.parse_media_identifier()?;
+        }
+        let media_type = match &*word {
+            "all" => MediaType::All,
+            "screen" => MediaType::Screen,
+            "print" => MediaType::Print,
+            _ => MediaType::Unknown(word),
+        };
+
+        self.consume_whitespace();
+        let condition = if self.starts_with_keyword("and") {
+            self.parse_identifier();
+            self.consume_whitespace();
+            let mut conditions = vec![self.parse_media_in_parens()?];
+            self.parse_media_condition_chain("and", &mut conditions)?;
+            Some(combine(conditions, MediaCondition::And))
+        } else {
+            None
+        };
+        Some(MediaQuery {
+            qualifier,
+            media_type,
+            condition,
+        })
+    }
+
+    fn parse_media_condition(&mut self) -> Option<MediaCondition> {
+        self.consume_whitespace();
+        if self.starts_with_keyword("not") {
+            self.parse_identifier();
+            self.consume_whitespace();
+            return Some(MediaCondition::Not(Box::new(self.parse_media_in_parens()?)));
+        }
+
+        let mut conditions = vec![self.parse_media_in_parens()?];
+        self.consume_whitespace();
+        if self.starts_with_keyword("or") {
+            self.parse_media_condition_chain("or", &mut conditions)?;
+            Some(combine(conditions, MediaCondition::Or))
+        } else {
+            self.parse_media_condition_chain("and", &mut conditions)?;
+            Some(combine(conditions, MediaCondition::And))
+        }
+    }
+
+    /// `and (...) and (...)` のように同じ演算子で繋がる条件を読む
+    fn parse_media_condition_chain(
+        &mut self,
+        keyword: &str,
+        conditions: &mut Vec<MediaCondition>,
+    ) -> Option<()> {
+        loop {
+            self.consume_whitespace();
+            if !self.starts_with_keyword(keyword) {
+                return Some(());
+            }
+            self.parse_identifier();
+            self.consume_whitespace();
+            conditions.push(self.parse_media_in_parens()?);
+        }
+    }
+
+    fn parse_media_in_parens(&mut self) -> Option<MediaCondition> {
+        self.consume_expected('(')?;
+        self.consume_whitespace();
+        let condition = if !self.eof() && self.next_char() == '(' || self.starts_with_keyword("not")
+        {
+            self.parse_media_condition()?
+        } else {
+            MediaCondition::Feature(self.parse_media_feature()?)
+        };
+        self.consume_whitespace();
+        self.consume_expected(')')?;
+        Some(condition)
+    }
+
+    fn parse_media_feature(&mut self) -> Option<MediaFeature> {
+        if !self.starts_media_value_number() {
+            let name = self.parse_media_identifier()?;
+            self.consume_whitespace();
+            if self.eof() {
+                return None;
+            }
+            return Some(match self.next_char() {
+                ')' => MediaFeature::Boolean(name),
+                ':' => {
+                    self.consume_char();
+                    self.consume_whitespace();
+                    let value = self.parse_media_feature_value()?;
+                    if let Some(name) = name.strip_prefix("min-") {
+                        MediaFeature::Range(name.to_string(), vec![(Comparison::Ge, value)])
+                    } else if let Some(name) = name.strip_prefix("max-") {
+                        MediaFeature::Range(name.to_string(), vec![(Comparison::Le, value)])
+                    } else {
+                        MediaFeature::Plain(name, value)
+                    }
+                }
+                _ => {
+                    // width >= 600px
+                    let comparison = self.parse_comparison()?;
+                    self.consume_whitespace();
+                    let value = self.parse_media_feature_value()?;
+                    MediaFeature::Range(name, vec![(comparison, value)])
+                }
+            });
+        }
+
+        // 600px <= width < 900px
+        let value = self.parse_media_feature_value()?;
+        self.consume_whitespace();
+        let comparison = self.parse_comparison()?.flip();
+        self.consume_whitespace();
+        let name = self.parse_media_identifier()?;
+        let mut comparisons = vec![(comparison, value)];
+        self.consume_whitespace();
+        if !self.eof() && self.next_char() != ')' {
+            let comparison = self.parse_comparison()?;
+            self.consume_whitespace();
+            comparisons.push((comparison, self.parse_media_feature_value()?));
+        }
+        Some(MediaFeature::Range(name, comparisons))
+    }
+
+    fn parse_comparison(&mut self) -> Option<Comparison> {
+        if self.eof() {
+            return None;
+        }
+        let comparison = match self.consume_char() {
+            '<' => Comparison::Lt,
+            '>' => Comparison::Gt,
+            '=' => return Some(Comparison::Eq),
+            _ => return None,
+        };
+        if self.eof() || self.next_char() != '=' {
+            return Some(comparison);
+        }
+        self.consume_char();
+        Some(match comparison {
+            Comparison::Lt => Comparison::Le,
+            _ => Comparison::Ge,
+        })
+    }
+
+    /// `<ratio>` などの未対応の値は `None`
+    fn parse_media_feature_value(&mut self) -> Option<MediaFeatureValue> {
+        if !self.starts_media_value_number() {
+            return Some(MediaFeatureValue::Ident(self.parse_media_identifier()?));
+        }
+        let mut num = String::new();
+        if matches!(self.next_char(), '+' | '-') {
+            num.push(self.consume_char());
+        }
+        num.push_str(&self.consume_while(|c| matches!(c, '0'..='9' | '.')));
+        let num: f32 = num.parse().ok()?;
+        if self.eof() || !valid_identifier_char(self.next_char()) {
+            return Some(MediaFeatureValue::Number(num));
+        }
+        let unit = self.parse_identifier().to_ascii_lowercase();
+        Some(match &*unit {
+            "dppx" | "x" => MediaFeatureValue::Resolution(num),
+            "dpi" => MediaFeatureValue::Resolution(num / 96.0),
+            "dpcm" => MediaFeatureValue::Resolution(num * 2.54 / 96.0),
+            _ => MediaFeatureValue::Length(num, Unit::from_name(&unit)?),
+        })
+    }
+
+    /// 小文字にした識別子。識別子がなければ `None`
+    fn parse_media_identifier(&mut self) -> Option<String> {
+        Some(self.parse_identifier().to_ascii_lowercase()).filter(|name| !name.is_empty())
+    }
+
+    fn starts_media_value_number(&self) -> bool {
+        if self.eof() {
+            return false;
+        }
+        match self.next_char() {
+            '0'..='9' | '.' => true,
+            '+' | '-' => self.starts_number(),
+            _ => false,
+        }
+    }
+
+    /// 次の語が `keyword` であるかどうか (大文字小文字は区別しない)
+    pub(super) fn starts_with_keyword(&self, keyword: &str) -> bool {
+        let rest = &self.input[self.pos..];
+        rest.get(..keyword.len())
+            .is_some_and(|prefix| prefix.eq_ignore_ascii_case(keyword))
+            && !rest[keyword.len()..]
+                .chars()
+                .next()
+                .is_some_and(valid_identifier_char)
+    }
+
+    /// 空白を読み飛ばした先の文字
+    fn peek_after_whitespace(&self) -> char {
+        self.input[self.pos..]
+            .chars()
+            .find(|c| !c.is_whitespace())
+            .unwrap_or(' ')
+    }
+}
+
+/// 条件が1つだけならそのまま返す
+fn combine<F>(mut conditions: Vec<MediaCondition>, op: F) -> MediaCondition
+where
+    F: Fn(Vec<MediaCondition>) -> MediaCondition,
+{
+    if conditions.len() == 1 {
+        conditions.pop().unwrap()
+    } else {
+        op(conditions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> MediaQueryList {
+        let mut parser = Parser::new(format!("{} {{", source));
+        parser.parse_media_query_list()
+    }
+
+    #[test]
+    fn test_parse_media_query() {
+        assert_eq!(
+            parse("screen and (min-width: 600px), print"),
+            MediaQueryList(vec![
+                MediaQuery {
+                    qualifier: None,
+                    media_type: MediaType::Screen,
+                    condition: Some(MediaCondition::Feature(MediaFeature::Range(
+                        "width".to_string(),
+                        vec![(Comparison::Ge, MediaFeatureValue::Length(600.0, Unit::Px))]
+                    ))),
+                },
+                MediaQuery {
+                    qualifier: None,
+                    media_type: MediaType::Print,
+                    condition: None,
+                },
+            ])
+        );
+        assert_eq!(
+            parse("(400px <= width < 700px)"),
+            MediaQueryList(vec![MediaQuery {
+                qualifier: None,
+                media_type: MediaType::All,
+                condition: Some(MediaCondition::Feature(MediaFeature::Range(
+                    "width".to_string(),
+                    vec![
+                        (Comparison::Ge, MediaFeatureValue::Length(400.0, Unit::Px)),
+                        (Comparison::Lt, MediaFeatureValue::Length(700.0, Unit::Px)),
+                    ]
+                ))),
+            }])
+        );
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let device = Device::new(800.0, 600.0);
+        let matches = |source: &str| parse(source).matches(&device);
+
+        assert!(matches(""));
+        assert!(matches("screen"));
+        assert!(!matches("print"));
+        assert!(matches("not print"));
+        assert!(!matches("tv"));
+        assert!(matches(
+            "only screen and (min-width: 600px) and (max-width: 800px)"
+        ));
+        assert!(!matches("(max-width: 799.5px)"));
+        assert!(matches("(width >= 50em)"));
+        assert!(matches("(400px <= width < 900px)"));
+        assert!(!matches("(900px <= width)"));
+        assert!(matches("(height: 600px)"));
+        assert!(matches("(orientation: landscape)"));
+        assert!(!matches("(orientation: portrait)"));
+        assert!(matches("(prefers-color-scheme: light)"));
+        assert!(matches(
+            "(min-resolution: 96dpi) and (max-resolution: 1dppx)"
+        ));
+        assert!(matches("(max-width: 600px) or (orientation: landscape)"));
+        assert!(matches("not (max-width: 600px)"));
+        assert!(!matches("(not (width)) or (monochrome)"));
+        assert!(matches("print, screen and (width)"));
+
+        // 未知の特性や型の合わない値は不明となり、否定しても一致しない
+        for source in [
+            "(foo: bar)",
+            "(foo)",
+            "(orientation: sideways)",
+            "(resolution: 10px)",
+            "(width: 3)",
+        ] {
+            assert!(!matches(source), "{}", source);
+            assert!(!matches(&format!("not {}", source)), "{}", source);
+        }
+        assert!(!matches("not screen and (foo)"));
+        assert!(!matches("(width) and (foo)"));
+        assert!(!matches("not ((width) and (foo))"));
+        assert!(matches("not ((foo) and (max-width: 10px))"));
+        assert!(matches("(foo) or (width)"));
+        assert!(!matches("(foo) or (max-width: 10px)"));
+    }
+
+    #[test]
+    fn test_invalid_media_queries() {
+        let device = Device::new(800.0, 600.0);
+        let matches = |source| parse(source).matches(&device);
+        let not_all = MediaQuery {
+            qualifier: Some(Qualifier::Not),
+            media_type: MediaType::All,
+            condition: None,
+        };
+
+        // 構文解析できないクエリは `not all` になり、リストの他のクエリは評価する
+        for source in [
+            "(aspect-ratio: 16/9)",
+            "(width ~ 3px)",
+            "(w\u{ef}dth: 3px)",
+            "(\u{e9})",
+            "(width) \u{65e5}",
+            "(width: 3foo)",
+            "(width: .)",
+            "screen and",
+            "(width",
+        ] {
+            assert_eq!(
+                parse(source),
+                MediaQueryList(vec![not_all.clone()]),
+                "{}",
+                source
+            );
+            assert!(!matches(source), "{}", source);
+        }
+        assert!(matches("(width ~ 3px), screen"));
+        assert_eq!(parse("print, (width ~ 3px), screen").0.len(), 3);
+
+        let stylesheet = super::super::parse(
+            "@media (\u{e9}), (width { p { color: red; } } p { color: blue; }".to_string(),
+        );
+        assert_eq!(stylesheet.rules.len(), 2);
+    }
+}
@@ -0,0 +1,901 @@
+//! エンジンが実装しているプロパティの一覧
+//!
+//! https://www.w3.org/TR/css-cascade-4/#property-declarations
+
+use std::sync::OnceLock;
+
+use self::{AnimationType::*, AppliesTo::*, ValueType::*};
+use super::{calc::CalcNode, Parser, Value};
+
+/// ロングハンドプロパティ
+///
+/// `PROPERTIES` と同じく名前の順に並べる
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PropertyId {
+    BackgroundAttachment,
+    BackgroundColor,
+    BackgroundImage,
+    BackgroundPosition,
+    BackgroundRepeat,
+    BorderBottomColor,
+    BorderBottomStyle,
+    BorderBottomWidth,
+    BorderCollapse,
+    BorderLeftColor,
+    BorderLeftStyle,
+    BorderLeftWidth,
+    BorderRightColor,
+    BorderRightStyle,
+    BorderRightWidth,
+    BorderSpacing,
+    BorderTopColor,
+    BorderTopStyle,
+    BorderTopWidth,
+    Color,
+    Content,
+    CounterIncrement,
+    CounterReset,
+    Display,
+    FontFamily,
+    FontSize,
+    FontStyle,
+    FontWeight,
+    Height,
+    Hyphens,
+    LineBreak,
+    LineHeight,
+    ListStylePosition,
+    ListStyleType,
+    MarginBottom,
+    MarginLeft,
+    MarginRight,
+    MarginTop,
+    OverflowWrap,
+    PaddingBottom,
+    PaddingLeft,
+    PaddingRight,
+    PaddingTop,
+    TextAlign,
+    TextDecoration,
+    VerticalAlign,
+    WhiteSpace,
+    Width,
+    WordBreak,
+}
+
+/// プロパティの定義
+///
+/// https://www.w3.org/TR/css-values-4/#property-def-table
+#[derive(Debug)]
+pub struct PropertyDef {
+    pub id: PropertyId,
+    pub name: &'static str,
+    pub grammar: Grammar,
+    /// 初期値の CSS 表記
+    pub initial: &'static str,
+    pub inherited: bool,
+    pub applies_to: AppliesTo,
+    pub animation: AnimationType,
+}
+
+/// 受け付ける値の文法
+#[derive(Debug, Clone, Copy)]
+pub struct Grammar {
+    /// 各成分値が取りうる型
+    pub types: &'static [ValueType],
+    /// 空白区切りで並べられる成分値の最大数
+    pub max: usize,
+    /// 負の長さと数値を受け付けないかどうか
+    pub non_negative: bool,
+    /// カンマ区切りで並べられるかどうか
+    pub comma_separated: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum ValueType {
+    /// 長さ (`0` を含む)
+    Length,
+    Percentage,
+    Number,
+    Color,
+    /// 列挙されたキーワードのいずれか
+    Keyword(&'static [&'static str]),
+    /// 任意の識別子
+    Ident,
+    String,
+    /// `attr()`、`counter()`、`counters()`
+    ContentFunction,
+}
+
+/// https://www.w3.org/TR/css-values-4/#property-def-table
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AppliesTo {
+    AllElements,
+    /// 置換要素でないインライン要素を除くすべての要素
+    AllButNonReplacedInlines,
+    /// 表の内部要素を除くすべての要素
+    AllButTableInternal,
+    BlockContainers,
+    ListItems,
+    Tables,
+    InlineLevelAndTableCells,
+}
+
+/// https://www.w3.org/TR/web-animations-1/#animation-type
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AnimationType {
+    /// 計算値を補間する
+    ByComputedValue,
+    /// 中間で切り替わる
+    Discrete,
+    NotAnimatable,
+}
+
+/// すべてのプロパティが受け付けるキーワード
+///
+/// https://www.w3.org/TR/css-cascade-4/#defaulting-keywords
+pub const CSS_WIDE_KEYWORDS: [&str; 4] = ["initial", "inherit", "unset", "revert"];
+
+pub fn is_css_wide_keyword(value: &Value) -> bool {
+    matches!(*value, Value::Keyword(ref k) if CSS_WIDE_KEYWORDS.contains(&&**k))
+}
+
+impl PropertyId {
+    /// プロパティ名 (大文字小文字は区別しない) からロングハンドを得る
+    pub fn from_name(name: &str) -> Option<PropertyId> {
+        let name = name.to_ascii_lowercase();
+        PROPERTIES
+            .binary_search_by_key(&&*name, |def| def.name)
+            .ok()
+            .map(|i| PROPERTIES[i].id)
+    }
+
+    pub fn def(self) -> &'static PropertyDef {
+        &PROPERTIES[self as usize]
+    }
+
+    pub fn name(self) -> &'static str {
+        self.def().name
+    }
+
+    /// 初期値
+    pub fn initial_value(self) -> Value {
+        static INITIAL_VALUES: OnceLock<Vec<Value>> = OnceLock::new();
+        INITIAL_VALUES.get_or_init(|| {
+            PROPERTIES
+                .iter()
+                .map(|def| {
+                    let mut parser = Parser::new(def.initial.to_string());
+                    let values = parser.parse_values().unwrap();
+                    super::shorthand::into_value(values)
+                })
+                .collect()
+        })[self as usize]
+            .clone()
+    }
+}
+
+impl PropertyDef {
+    /// 値がこのプロパティの文法に合うかどうか
+    ///
+    /// `var()` を含む値は置換するまで検査できないので受け付ける
+    pub fn accepts(&self, value: &Value) -> bool {
+        match *value {
+            Value::Unparsed(_) => true,
+            _ if is_css_wide_keyword(value) => true,
+            Value::CommaList(ref items) => {
+                self.grammar.comma_separated
+                    && items.iter().all(|item| self.grammar.accepts_list(item))
+            }
+            _ => self.grammar.accepts_list(value),
+        }
+    }
+}
+
+impl Grammar {
+    /// 空白区切りの成分値の並び (1つでもよい) を受け付けるかどうか
+    fn accepts_list(&self, value: &Value) -> bool {
+        match *value {
+            Value::List(ref values) => {
+                (2..=self.max).contains(&values.len())
+                    && values.iter().all(|value| self.accepts(value))
+            }
+            _ => self.accepts(value),
+        }
+    }
+
+    /// 1つの成分値を受け付けるかどうか
+    fn accepts(&self, value: &Value) -> bool {
+        let negative = match *value {
+            Value::Length(f, _) | Value::Number(f) | Value::Percentage(f) => f < 0.0,
+            // 値が文脈によるものは計算値の時点で0に切り詰める
+            Value::Calc(ref calc) => calc.static_value().is_some_and(|f| f < 0.0),
+            _ => false,
+        };
+        if self.non_negative && negative {
+            return false;
+        }
+        if let Value::Calc(ref calc) = *value {
+            return self.accepts_calc(calc);
+        }
+        self.types.iter().any(|ty| match (*ty, value) {
+            (ValueType::Length, Value::Length(..)) => true,
+            (ValueType::Length, Value::Number(f)) => *f == 0.0,
+            (ValueType::Percentage, Value::Percentage(_)) => true,
+            (ValueType::Number, Value::Number(_)) => true,
+            (ValueType::Color, Value::ColorValue(_)) => true,
+            (ValueType::Color, Value::Keyword(k)) => k == "currentcolor",
+            (ValueType::Keyword(keywords), Value::Keyword(k)) => keywords.contains(&&**k),
+            (ValueType::Ident, Value::Keyword(_)) => true,
+            (ValueType::String, Value::String(_)) => true,
+            (ValueType::ContentFunction, Value::Content(_)) => true,
+            _ => false,
+        })
+    }
+
+    /// 式の型が取りうる型のいずれかに合うかどうか
+    ///
+    /// 長さとパーセンテージを混ぜた式は、その両方を受け付ける場合だけ受け付ける
+    fn accepts_calc(&self, calc: &CalcNode) -> bool {
+        let has = |f: fn(&ValueType) -> bool| self.types.iter().any(f);
+        if calc.is_number() {
+            return has(|ty| matches!(ty, ValueType::Number));
+        }
+        (!calc.has_length() || has(|ty| matches!(ty, ValueType::Length)))
+            && (!calc.has_percentage() || has(|ty| matches!(ty, ValueType::Percentage)))
+    }
+}
+
+const fn one(types: &'static [ValueType]) -> Grammar {
+    Grammar {
+        types,
+        max: 1,
+        non_negative: false,
+        comma_separated: false,
+    }
+}
+
+const fn non_negative(types: &'static [ValueType]) -> Grammar {
+    Grammar {
+        types,
+        max: 1,
+        non_negative: true,
+        comma_separated: false,
+    }
+}
+
+const fn pair(types: &'static [ValueType]) -> Grammar {
+    Grammar {
+        types,
+        max: 2,
+        non_negative: false,
+        comma_separated: false,
+    }
+}
+
+const BORDER_STYLE: Grammar = one(&[Keyword(&[
+    "none", "hidden", "dotted", "dashed", "solid", "double", "groove", "ridge", "inset", "outset",
+])]);
+
+const BORDER_WIDTH: Grammar = non_negative(&[Length, Keyword(&["thin", "medium", "thick"])]);
+
+/// `none` か、カウンタ名と省略可能な整数の並び
+const COUNTER: Grammar = Grammar {
+    types: &[Ident, Number],
+    max: usize::MAX,
+    non_negative: false,
+    comma_separated: false,
+};
+
+const MARGIN: Grammar = one(&[Length, Percentage, Keyword(&["auto"])]);
+
+const PADDING: Grammar = non_negative(&[Length, Percentage]);
+
+const SIZE: Grammar = non_negative(&[Length, Percentage, Keyword(&["auto"])]);
+
+const DISPLAY_KEYWORDS: [&str; 14] = [
+    "block",
+    "inline",
+    "flow-root",
+    "list-item",
+    "none",
+    "table",
+    "table-caption",
+    "table-header-group",
+    "table-row-group",
+    "table-footer-group",
+    "table-row",
+    "table-cell",
+    "table-column-group",
+    "table-column",
+];
+
+const FONT_SIZE_KEYWORDS: [&str; 10] = [
+    "xx-small",
+    "x-small",
+    "small",
+    "medium",
+    "large",
+    "x-large",
+    "xx-large",
+    "xxx-large",
+    "larger",
+    "smaller",
+];
+
+/// 名前の順に並べる
+pub static PROPERTIES: [PropertyDef; 49] = [
+    PropertyDef {
+        id: PropertyId::BackgroundAttachment,
+        name: "background-attachment",
+        grammar: one(&[Keyword(&["scroll", "fixed", "local"])]),
+        initial: "scroll",
+        inherited: false,
+        applies_to: AllElements,
+        animation: Discrete,
+    },
+    PropertyDef {
+        id: PropertyId::BackgroundColor,
+        name: "background-color",
+        grammar: one(&[Color]),
+        initial: "transparent",
+        inherited: false,
+        applies_to: AllElements,
+        animation: ByComputedValue,
+    },
+    PropertyDef {
+        id: PropertyId::BackgroundImage,
+        name: "background-image",
+        grammar: one(&[Keyword(&["none"])]),
+        initial: "none",
+        inherited: false,
+        applies_to: AllElements,
+        animation: Discrete,
+    },
+    PropertyDef {
+        id: PropertyId::BackgroundPosition,
+        name: "background-position",
+        grammar: pair(&[
+            Length,
+            Percentage,
+            Keyword(&["left", "right", "top", "bottom", "center"]),
+        ]),
+        initial: "0% 0%",
+        inherited: false,
+        applies_to: AllElements,
+        animation: ByComputedValue,
+    },
+    PropertyDef {
+        id: PropertyId::BackgroundRepeat,
+        name: "background-repeat",
+        grammar: one(&[Keyword(&[
+            "repeat",
+            "repeat-x",
+            "repeat-y",
+            "no-repeat",
+            "space",
+            "round",
+        ])]),
+        initial: "repeat",
+        inherited: false,
+        applies_to: AllElements,
+        animation: Discrete,
+    },
+    PropertyDef {
+        id: PropertyId::BorderBottomColor,
+        name: "border-bottom-color",
+        grammar: one(&[Color]),
+        initial: "currentcolor",
+        inherited: false,
+        applies_to: AllElements,
+        animation: ByComputedValue,
+    },
+    PropertyDef {
+        id: PropertyId::BorderBottomStyle,
+        name: "border-bottom-style",
+        grammar: BORDER_STYLE,
+        initial: "none",
+        inherited: false,
+        applies_to: AllElements,
+        animation: Discrete,
+    },
+    PropertyDef {
+        id: PropertyId::BorderBottomWidth,
+        name: "border-bottom-width",
+        grammar: BORDER_WIDTH,
+        initial: "medium",
+        inherited: false,
+        applies_to: AllElements,
+        animation: ByComputedValue,
+    },
+    PropertyDef {
+        id: PropertyId::BorderCollapse,
+        name: "border-collapse",
+        grammar: one(&[Keyword(&["separate", "collapse"])]),
+        initial: "separate",
+        inherited: true,
+        applies_to: Tables,
+        animation: Discrete,
+    },
+    PropertyDef {
+        id: PropertyId::BorderLeftColor,
+        name: "border-left-color",
+        grammar: one(&[Color]),
+        initial: "currentcolor",
+        inherited: false,
+        applies_to: AllElements,
+        animation: ByComputedValue,
+    },
+    PropertyDef {
+        id: PropertyId::BorderLeftStyle,
+        name: "border-left-style",
+        grammar: BORDER_STYLE,
+        initial: "none",
+        inherited: false,
+        applies_to: AllElements,
+        animation: Discrete,
+    },
+    PropertyDef {
+        id: PropertyId::BorderLeftWidth,
+        name: "border-left-width",
+        grammar: BORDER_WIDTH,
+        initial: "medium",
+        inherited: false,
+        applies_to: AllElements,
+        animation: ByComputedValue,
+    },
+    PropertyDef {
+        id: PropertyId::BorderRightColor,
+        name: "border-right-color",
+        grammar: one(&[Color]),
+        initial: "currentcolor",
+        inherited: false,
+        applies_to: AllElements,
+        animation: ByComputedValue,
+    },
+    PropertyDef {
+        id: PropertyId::BorderRightStyle,
+        name: "border-right-style",
+        grammar: BORDER_STYLE,
+        initial: "none",
+        inherited: false,
+        applies_to: AllElements,
+        animation: Discrete,
+    },
+    PropertyDef {
+        id: PropertyId::BorderRightWidth,
+        name: "border-right-width",
+        grammar: BORDER_WIDTH,
+        initial: "medium",
+        inherited: false,
+        applies_to: AllElements,
+        animation: ByComputedValue,
+    },
+    PropertyDef {
+        id: PropertyId::BorderSpacing,
+        name: "border-spacing",
+        grammar: Grammar {
+            types: &[Length],
+            max: 2,
+            non_negative: true,
+            comma_separated: false,
+        },
+        initial: "0px",
+        inherited: true,
+        applies_to: Tables,
+        animation: ByComputedValue,
+    },
+    PropertyDef {
+        id: PropertyId::BorderTopColor,
+        name: "border-top-color",
+        grammar: one(&[Color]),
+        initial: "currentcolor",
+        inherited: false,
+        applies_to: AllElements,
+        animation: ByComputedValue,
+    },
+    PropertyDef {
+        id: PropertyId::BorderTopStyle,
+        name: "border-top-style",
+        grammar: BORDER_STYLE,
+        initial: "none",
+        inherited: false,
+        applies_to: AllElements,
+        animation: Discrete,
+    },
+    PropertyDef {
+        id: PropertyId::BorderTopWidth,
+        name: "border-top-width",
+        grammar: BORDER_WIDTH,
+        initial: "medium",
+        inherited: false,
+        applies_to: AllElements,
+        animation: ByComputedValue,
+    },
+    PropertyDef {
+        id: PropertyId::Color,
+        name: "color",
+        grammar: one(&[Color]),
+        initial: "black",
+        inherited: true,
+        applies_to: AllElements,
+        animation: ByComputedValue,
+    },
+    PropertyDef {
+        id: PropertyId::Content,
+        name: "content",
+        grammar: Grammar {
+            types: &[
+                String,
+                ContentFunction,
+                Keyword(&[
+                    "normal",
+                    "none",
+                    "open-quote",
+                    "close-quote",
+                    "no-open-quote",
+                    "no-close-quote",
+                ]),
+            ],
+            max: usize::MAX,
+            non_negative: false,
+            comma_separated: false,
+        },
+        initial: "normal",
+        inherited: false,
+        applies_to: AllElements,
+        animation: Discrete,
+    },
+    PropertyDef {
+        id: PropertyId::CounterIncrement,
+        name: "counter-increment",
+        grammar: COUNTER,
+        initial: "none",
+        inherited: false,
+        applies_to: AllElements,
+        animation: Discrete,
+    },
+    PropertyDef {
+        id: PropertyId::CounterReset,
+        name: "counter-reset",
+        grammar: COUNTER,
+        initial: "none",
+        inherited: false,
+        applies_to: AllElements,
+        animation: Discrete,
+    },
+    PropertyDef {
+        id: PropertyId::Display,
+        name: "display",
+        grammar: one(&[Keyword(&DISPLAY_KEYWORDS)]),
+        initial: "inline",
+        inherited: false,
+        applies_to: AllElements,
+        animation: Discrete,
+    },
+    PropertyDef {
+        id: PropertyId::FontFamily,
+        name: "font-family",
+        grammar: Grammar {
+            types: &[String, Ident],
+            max: usize::MAX,
+            non_negative: false,
+            comma_separated: true,
+        },
+        initial: "serif",
+        inherited: true,
+        applies_to: AllElements,
+        animation: Discrete,
+    },
+    PropertyDef {
+        id: PropertyId::FontSize,
+        name: "font-size",
+        grammar: non_negative(&[Length, Percentage, Keyword(&FONT_SIZE_KEYWORDS)]),
+        initial: "medium",
+        inherited: true,
+        applies_to: AllElements,
+        animation: ByComputedValue,
+    },
+    PropertyDef {
+        id: PropertyId::FontStyle,
+        name: "font-style",
+        grammar: one(&[Keyword(&["normal", "italic", "oblique"])]),
+        initial: "normal",
+        inherited: true,
+        applies_to: AllElements,
+        animation: Discrete,
+    },
+    PropertyDef {
+        id: PropertyId::FontWeight,
+        name: "font-weight",
+        grammar: non_negative(&[Number, Keyword(&["normal", "bold", "bolder", "lighter"])]),
+        initial: "normal",
+        inherited: true,
+        applies_to: AllElements,
+        animation: ByComputedValue,
+    },
+    PropertyDef {
+        id: PropertyId::Height,
+        name: "height",
+        grammar: SIZE,
+        initial: "auto",
+        inherited: false,
+        applies_to: AllButNonReplacedInlines,
+        animation: ByComputedValue,
+    },
+    PropertyDef {
+        id: PropertyId::Hyphens,
+        name: "hyphens",
+        grammar: one(&[Keyword(&["none", "manual", "auto"])]),
+        initial: "manual",
+        inherited: true,
+        applies_to: AllElements,
+        animation: Discrete,
+    },
+    PropertyDef {
+        id: PropertyId::LineBreak,
+        name: "line-break",
+        grammar: one(&[Keyword(&["auto", "loose", "normal", "strict", "anywhere"])]),
+        initial: "auto",
+        inherited: true,
+        applies_to: AllElements,
+        animation: Discrete,
+    },
+    PropertyDef {
+        id: PropertyId::LineHeight,
+        name: "line-height",
+        grammar: non_negative(&[Number, Length, Percentage, Keyword(&["normal"])]),
+        initial: "normal",
+        inherited: true,
+        applies_to: AllElements,
+        animation: ByComputedValue,
+    },
+    PropertyDef {
+        id: PropertyId::ListStylePosition,
+        name: "list-style-position",
+        grammar: one(&[Keyword(&["inside", "outside"])]),
+        initial: "outside",
+        inherited: true,
+        applies_to: ListItems,
+        animation: Discrete,
+    },
+    PropertyDef {
+        id: PropertyId::ListStyleType,
+        name: "list-style-type",
+        grammar: one(&[Ident, String]),
+        initial: "disc",
+        inherited: true,
+        applies_to: ListItems,
+        animation: Discrete,
+    },
+    PropertyDef {
+        id: PropertyId::MarginBottom,
+        name: "margin-bottom",
+        grammar: MARGIN,
+        initial: "0px",
+        inherited: false,
+        applies_to: AllButTableInternal,
+        animation: ByComputedValue,
+    },
+    PropertyDef {
+        id: PropertyId::MarginLeft,
+        name: "margin-left",
+        grammar: MARGIN,
+        initial: "0px",
+        inherited: false,
+        applies_to: AllButTableInternal,
+        animation: ByComputedValue,
+    },
+    PropertyDef {
+        id: PropertyId::MarginRight,
+        name: "margin-right",
+        grammar: MARGIN,
+        initial: "0px",
+        inherited: false,
+        applies_to: AllButTableInternal,
+        animation: ByComputedValue,
+    },
+    PropertyDef {
+        id: PropertyId::MarginTop,
+        name: "margin-top",
+        grammar: MARGIN,
+        initial: "0px",
+        inherited: false,
+        applies_to: AllButTableInternal,
+        animation: ByComputedValue,
+    },
+    PropertyDef {
+        id: PropertyId::OverflowWrap,
+        name: "overflow-wrap",
+        grammar: one(&[Keyword(&["normal", "break-word", "anywhere"])]),
+        initial: "normal",
+        inherited: true,
+        applies_to: AllElements,
+        animation: Discrete,
+    },
+    PropertyDef {
+        id: PropertyId::PaddingBottom,
+        name: "padding-bottom",
+        grammar: PADDING,
+        initial: "0px",
+        inherited: false,
+        applies_to: AllButTableInternal,
+        animation: ByComputedValue,
+    },
+    PropertyDef {
+        id: PropertyId::PaddingLeft,
+        name: "padding-left",
+        grammar: PADDING,
+        initial: "0px",
+        inherited: false,
+        applies_to: AllButTableInternal,
+        animation: ByComputedValue,
+    },
+    PropertyDef {
+        id: PropertyId::PaddingRight,
+        name: "padding-right",
+        grammar: PADDING,
+        initial: "0px",
+        inherited: false,
+        applies_to: AllButTableInternal,
+        animation: ByComputedValue,
+    },
+    PropertyDef {
+        id: PropertyId::PaddingTop,
+        name: "padding-top",
+        grammar: PADDING,
+        initial: "0px",
+        inherited: false,
+        applies_to: AllButTableInternal,
+        animation: ByComputedValue,
+    },
+    PropertyDef {
+        id: PropertyId::TextAlign,
+        name: "text-align",
+        grammar: one(&[Keyword(&[
+            "start", "end", "left", "right", "center", "justify",
+        ])]),
+        initial: "start",
+        inherited: true,
+        applies_to: BlockContainers,
+        animation: Discrete,
+    },
+    PropertyDef {
+        id: PropertyId::TextDecoration,
+        name: "text-decoration",
+        grammar: one(&[Keyword(&["none", "underline", "overline", "line-through"])]),
+        initial: "none",
+        inherited: false,
+        applies_to: AllElements,
+        animation: Discrete,
+    },
+    PropertyDef {
+        id: PropertyId::VerticalAlign,
+        name: "vertical-align",
+        grammar: one(&[
+            Length,
+            Percentage,
+            Keyword(&[
+                "baseline",
+                "sub",
+                "super",
+                "text-top",
+                "text-bottom",
+                "middle",
+                "top",
+                "bottom",
+            ]),
+        ]),
+        initial: "baseline",
+        inherited: false,
+        applies_to: InlineLevelAndTableCells,
+        animation: ByComputedValue,
+    },
+    PropertyDef {
+        id: PropertyId::WhiteSpace,
+        name: "white-space",
+        grammar: one(&[Keyword(&[
+            "normal", "pre", "nowrap", "pre-wrap", "pre-line",
+        ])]),
+        initial: "normal",
+        inherited: true,
+        applies_to: AllElements,
+        animation: Discrete,
+    },
+    PropertyDef {
+        id: PropertyId::Width,
+        name: "width",
+        grammar: SIZE,
+        initial: "auto",
+        inherited: false,
+        applies_to: AllButNonReplacedInlines,
+        animation: ByComputedValue,
+    },
+    PropertyDef {
+        id: PropertyId::WordBreak,
+        name: "word-break",
+        grammar: one(&[Keyword(&["normal", "keep-all", "break-all", "break-word"])]),
+        initial: "normal",
+        inherited: true,
+        applies_to: AllElements,
+        animation: Discrete,
+    },
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::css::Unit;
+
+    #[test]
+    fn test_registry_order() {
+        for (i, def) in PROPERTIES.iter().enumerate() {
+            assert_eq!(def.id as usize, i, "{}", def.name);
+            assert_eq!(PropertyId::from_name(def.name), Some(def.id));
+        }
+        assert!(PROPERTIES.windows(2).all(|w| w[0].name < w[1].name));
+        assert_eq!(
+            PropertyId::from_name("Margin-Top"),
+            Some(PropertyId::MarginTop)
+        );
+        assert_eq!(PropertyId::from_name("margin"), None);
+    }
+
+    #[test]
+    fn test_initial_values() {
+        assert_eq!(
+            PropertyId::Width.initial_value(),
+            Value::Keyword("auto".to_string())
+        );
+        assert_eq!(
+            PropertyId::PaddingTop.initial_value(),
+            Value::Length(0.0, Unit::Px)
+        );
+        assert_eq!(
+            PropertyId::BackgroundPosition.initial_value(),
+            Value::List(vec![Value::Percentage(0.0), Value::Percentage(0.0)])
+        );
+        for def in &PROPERTIES {
+            assert!(def.accepts(&def.id.initial_value()), "{}", def.name);
+        }
+    }
+
+    #[test]
+    fn test_accepts() {
+        let width = PropertyId::Width.def();
+        assert!(width.accepts(&Value::Length(10.0, Unit::Em)));
+        assert!(width.accepts(&Value::Number(0.0)));
+        assert!(width.accepts(&Value::Keyword("inherit".to_string())));
+        assert!(!width.accepts(&Value::Length(-1.0, Unit::Px)));
+        assert!(!width.accepts(&Value::Number(10.0)));
+        assert!(!width.accepts(&Value::Keyword("red".to_string())));
+
+        let display = PropertyId::Display.def();
+        assert!(display.accepts(&Value::Keyword("flow-root".to_string())));
+        assert!(!display.accepts(&Value::Keyword("grid".to_string())));
+
+        let position = PropertyId::BackgroundPosition.def();
+        let left = Value::Keyword("left".to_string());
+        assert!(position.accepts(&Value::List(vec![left.clone(), Value::Percentage(5.0)])));
+        assert!(!position.accepts(&Value::List(vec![left.clone(), left.clone(), left])));
+
+        let calc = |args: &str| Value::Calc(crate::css::calc::parse("calc", args).unwrap());
+        assert!(width.accepts(&calc("100% - 1em")));
+        assert!(!width.accepts(&calc("2 * 3")));
+        // 負になりうる式は受け付け、計算値の時点で切り詰める
+        assert!(width.accepts(&calc("1em - 10px")));
+        assert!(!width.accepts(&calc("-1px")));
+        assert!(!width.accepts(&calc("1cm - 1in")));
+        let border_width = PropertyId::BorderTopWidth.def();
+        assert!(border_width.accepts(&calc("1px + 1em")));
+        assert!(!border_width.accepts(&calc("10%")));
+        let line_height = PropertyId::LineHeight.def();
+        assert!(line_height.accepts(&calc("1.5 * 2")));
+        assert!(!line_height.accepts(&calc("-1.5")));
+        assert!(!PropertyId::Color.def().accepts(&calc("1px")));
+        assert_eq!(
+            crate::css::expand_declaration("padding", "calc(-1px)").err(),
+            Some(crate::css::ParseErrorKind::InvalidValue)
+        );
+    }
+}
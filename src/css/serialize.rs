@@ -0,0 +1,655 @@
+//! スタイルシートを CSS のテキストに書き出す
+//!
+//! 書き出したテキストを解析し直すと元と等しいスタイルシートになる
+//! ただし `@import` で読み込んだスタイルシートは書き出さない
+//!
+//! https://www.w3.org/TR/cssom-1/#serializing-css-values
+
+use std::fmt;
+
+use super::{
+    calc::CalcNode,
+    content::ContentFunction,
+    custom::UnparsedValue,
+    media::{
+        Comparison, MediaCondition, MediaFeature, MediaFeatureValue, MediaQuery, MediaQueryList,
+        MediaType, Qualifier,
+    },
+    shorthand,
+    supports::SupportsCondition,
+    Color, Combinator, CssRule, Declaration, ImportLayer, LayerName, Rule, Selector,
+    SimpleSelector, Stylesheet, Value,
+};
+
+/// 1行に1つの宣言を置き、2文字ずつ字下げする
+pub fn pretty(stylesheet: &Stylesheet) -> String {
+    let mut serializer = Serializer::new(false);
+    serializer.rules(&stylesheet.rules);
+    serializer.out
+}
+
+/// 構文上不要な空白を除き、色と数値を短く書く
+pub fn minify(stylesheet: &Stylesheet) -> String {
+    let mut serializer = Serializer::new(true);
+    serializer.rules(&stylesheet.rules);
+    serializer.out
+}
+
+struct Serializer {
+    minify: bool,
+    /// 字下げの深さ
+    depth: usize,
+    out: String,
+}
+
+impl Serializer {
+    fn new(minify: bool) -> Serializer {
+        Serializer {
+            minify,
+            depth: 0,
+            out: String::new(),
+        }
+    }
+
+    fn rules(&mut self, rules: &[CssRule]) {
+        for (i, rule) in rules.iter().enumerate() {
+            // トップレベルの規則の間には空行を入れる
+            if !self.minify && i > 0 && self.depth == 0 {
+                self.out.push('\n');
+            }
+            self.rule(rule);
+        }
+    }
+
+    fn rule(&mut self, rule: &CssRule) {
+        self.indent();
+        match *rule {
+            CssRule::Style(ref rule) => self.style_rule(rule),
+            CssRule::Media(ref media) => {
+                self.out.push_str("@media");
+                if !media.queries.0.is_empty() {
+                    self.out.push(' ');
+                    self.out.push_str(&self.media_query_list(&media.queries));
+                }
+                self.block(&media.rules);
+            }
+            CssRule::Import(ref import) => {
+                self.out.push_str("@import ");
+                self.out.push_str(&string(&import.url));
+                match import.layer {
+                    None => {}
+                    Some(ImportLayer::Anonymous) => self.out.push_str(" layer"),
+                    Some(ImportLayer::Named(ref name)) => {
+                        self.out.push_str(&format!(" layer({})", layer_name(name)));
+                    }
+                }
+                if !import.media.0.is_empty() {
+                    self.out.push(' ');
+                    self.out.push_str(&self.media_query_list(&import.media));
+                }
+                self.statement_end();
+            }
+            CssRule::Layer(ref layer) => {
+                self.out.push_str("@layer");
+                if let Some(ref name) = layer.name {
+                    self.out.push(' ');
+                    self.out.push_str(&layer_name(name));
+                }
+                self.block(&layer.rules);
+            }
+            CssRule::LayerStatement(ref names) => {
+                self.out.push_str("@layer ");
+                let names: Vec<String> = names.iter().map(layer_name).collect();
+                self.out.push_str(&names.join(self.comma()));
+                self.statement_end();
+            }
+            CssRule::Supports(ref supports) => {
+                self.out.push_str("@supports ");
+                self.out
+                    .push_str(&self.supports_condition(&supports.condition));
+                self.block(&supports.rules);
+            }
+        }
+    }
+
+    fn style_rule(&mut self, rule: &Rule) {
+        let selectors: Vec<String> = rule.selectors.iter().map(selector).collect();
+        self.out.push_str(&selectors.join(self.comma()));
+        self.open_block();
+
+        let mut declarations = rule.declarations.iter().peekable();
+        while let Some(declaration) = declarations.next() {
+            let name = match declaration.value {
+                // `var()` を含むショートハンドから展開されたロングハンドは、元のショートハンドに戻す
+                Value::Unparsed(UnparsedValue {
+                    shorthand: Some(ref name),
+                    ..
+                }) => {
+                    let count = shorthand::longhands(name).map_or(1, |longhands| longhands.len());
+                    for _ in 1..count {
+                        declarations.next_if(|next| same_shorthand(declaration, next));
+                    }
+                    name
+                }
+                _ => &declaration.name,
+            };
+            self.declaration(name, declaration);
+        }
+        self.close_block();
+    }
+
+    fn declaration(&mut self, name: &str, declaration: &Declaration) {
+        self.indent();
+        self.out.push_str(name);
+        self.out.push(':');
+        if !self.minify {
+            self.out.push(' ');
+        }
+        self.out.push_str(&self.value(&declaration.value));
+        if declaration.important {
+            self.out.push_str(if self.minify {
+                "!important"
+            } else {
+                " !important"
+            });
+        }
+        self.statement_end();
+    }
+
+    fn block(&mut self, rules: &[CssRule]) {
+        self.open_block();
+        self.rules(rules);
+        self.close_block();
+    }
+
+    fn open_block(&mut self) {
+        self.out.push_str(if self.minify { "{" } else { " {\n" });
+        self.depth += 1;
+    }
+
+    fn close_block(&mut self) {
+        self.depth -= 1;
+        self.indent();
+        self.out.push('}');
+        if !self.minify {
+            self.out.push('\n');
+        }
+    }
+
+    fn statement_end(&mut self) {
+        self.out.push(';');
+        if !self.minify {
+            self.out.push('\n');
+        }
+    }
+
+    fn indent(&mut self) {
+        if !self.minify {
+            self.out.push_str(&"  ".repeat(self.depth));
+        }
+    }
+
+    fn comma(&self) -> &'static str {
+        if self.minify {
+            ","
+        } else {
+            ", "
+        }
+    }
+
+    fn value(&self, value: &Value) -> String {
+        match *value {
+            Value::Keyword(ref keyword) => keyword.clone(),
+            Value::Length(f, unit) => self.number(f) + unit.name(),
+            Value::Number(f) => self.number(f),
+            Value::Percentage(f) => self.number(f) + "%",
+            Value::ColorValue(color) => self.color(color),
+            Value::List(ref values) => {
+                let values: Vec<String> = values.iter().map(|v| self.value(v)).collect();
+                values.join(" ")
+            }
+            Value::CommaList(ref values) => {
+                let values: Vec<String> = values.iter().map(|v| self.value(v)).collect();
+                values.join(self.comma())
+            }
+            Value::Calc(ref calc) => match *calc {
+                CalcNode::Min(_) | CalcNode::Max(_) | CalcNode::Clamp(..) => self.calc(calc),
+                _ => format!("calc({})", self.calc(calc)),
+            },
+            Value::Unparsed(ref unparsed) => unparsed.tokens.to_string(),
+            Value::String(ref s) => string(s),
+            Value::Content(ref function) => self.content_function(function),
+        }
+    }
+
+    fn content_function(&self, function: &ContentFunction) -> String {
+        match *function {
+            ContentFunction::Attr(ref name) => format!("attr({})", name),
+            ContentFunction::Counter {
+                ref name,
+                ref separator,
+                ref style,
+            } => {
+                let mut args = vec![name.clone()];
+                args.extend(separator.as_deref().map(string));
+                if style != "decimal" {
+                    args.push(style.clone());
+                }
+                let function = match separator {
+                    Some(_) => "counters",
+                    None => "counter",
+                };
+                format!("{}({})", function, args.join(self.comma()))
+            }
+        }
+    }
+
+    /// 最短の表記で、解析し直すと同じ値になる数値
+    fn number(&self, f: f32) -> String {
+        let s = f.to_string();
+        if !self.minify {
+            return s;
+        }
+        if let Some(rest) = s.strip_prefix("0.") {
+            format!(".{}", rest)
+        } else if let Some(rest) = s.strip_prefix("-0.") {
+            format!("-.{}", rest)
+        } else {
+            s
+        }
+    }
+
+    fn color(&self, color: Color) -> String {
+        let mut digits = vec![color.r, color.g, color.b];
+        if color.a != 255 {
+            digits.push(color.a);
+        }
+        if self.minify && digits.iter().all(|d| d >> 4 == d & 0xf) {
+            let hex: String = digits.iter().map(|d| format!("{:x}", d & 0xf)).collect();
+            return format!("#{}", hex);
+        }
+        let hex: String = digits.iter().map(|d| format!("{:02x}", d)).collect();
+        format!("#{}", hex)
+    }
+
+    /// `calc()` の中身。入れ子の二項演算は括弧で囲み、木の形を保つ
+    fn calc(&self, node: &CalcNode) -> String {
+        let operand = |node: &CalcNode| match *node {
+            CalcNode::Add(..) | CalcNode::Sub(..) | CalcNode::Mul(..) | CalcNode::Div(..) => {
+                format!("({})", self.calc(node))
+            }
+            _ => self.calc(node),
+        };
+        let (mul, div) = if self.minify {
+            ("*", "/")
+        } else {
+            (" * ", " / ")
+        };
+        let args = |nodes: &[&CalcNode]| {
+            let args: Vec<String> = nodes.iter().map(|node| self.calc(node)).collect();
+            args.join(self.comma())
+        };
+        match *node {
+            CalcNode::Number(f) => self.number(f),
+            CalcNode::Length(f, unit) => self.number(f) + unit.name(),
+            CalcNode::Percentage(f) => self.number(f) + "%",
+            // `+` と `-` の前後の空白は省略できない
+            CalcNode::Add(ref a, ref b) => format!("{} + {}", operand(a), operand(b)),
+            CalcNode::Sub(ref a, ref b) => format!("{} - {}", operand(a), operand(b)),
+            CalcNode::Mul(ref a, ref b) => format!("{}{}{}", operand(a), mul, operand(b)),
+            CalcNode::Div(ref a, ref b) => format!("{}{}{}", operand(a), div, operand(b)),
+            CalcNode::Min(ref nodes) => format!("min({})", args(&nodes.iter().collect::<Vec<_>>())),
+            CalcNode::Max(ref nodes) => format!("max({})", args(&nodes.iter().collect::<Vec<_>>())),
+            CalcNode::Clamp(ref min, ref val, ref max) => {
+                format!("clamp({})", args(&[&**min, &**val, &**max]))
+            }
+        }
+    }
+
+    fn media_query_list(&self, queries: &MediaQueryList) -> String {
+        let queries: Vec<String> = queries.0.iter().map(|q| self.media_query(q)).collect();
+        queries.join(self.comma())
+    }
+
+    fn media_query(&self, query: &MediaQuery) -> String {
+        let condition = query.condition.as_ref();
+        if query.qualifier.is_none() && query.media_type == MediaType::All {
+            if let Some(condition) = condition {
+                return self.media_condition(condition);
+            }
+        }
+
+        let mut result = String::new();
+        match query.qualifier {
+            Some(Qualifier::Only) => result.push_str("only "),
+            Some(Qualifier::Not) => result.push_str("not "),
+            None => {}
+        }
+        result.push_str(match query.media_type {
+            MediaType::All => "all",
+            MediaType::Screen => "screen",
+            MediaType::Print => "print",
+            MediaType::Unknown(ref name) => name,
+        });
+        match condition {
+            // メディア型の後には `and` で繋いだ条件しか書けない
+            Some(MediaCondition::And(ref conditions)) => {
+                for condition in conditions {
+                    result.push_str(" and ");
+                    result.push_str(&self.media_in_parens(condition));
+                }
+            }
+            Some(condition) => {
+                result.push_str(" and ");
+                result.push_str(&self.media_in_parens(condition));
+            }
+            None => {}
+        }
+        result
+    }
+
+    fn media_condition(&self, condition: &MediaCondition) -> String {
+        let join = |conditions: &[MediaCondition], op: &str| {
+            let conditions: Vec<String> =
+                conditions.iter().map(|c| self.media_in_parens(c)).collect();
+            conditions.join(op)
+        };
+        match *condition {
+            MediaCondition::Feature(_) => self.media_in_parens(condition),
+            MediaCondition::Not(ref condition) => {
+                format!("not {}", self.media_in_parens(condition))
+            }
+            MediaCondition::And(ref conditions) => join(conditions, " and "),
+            MediaCondition::Or(ref conditions) => join(conditions, " or "),
+        }
+    }
+
+    fn media_in_parens(&self, condition: &MediaCondition) -> String {
+        match *condition {
+            MediaCondition::Feature(ref feature) => format!("({})", self.media_feature(feature)),
+            _ => format!("({})", self.media_condition(condition)),
+        }
+    }
+
+    fn media_feature(&self, feature: &MediaFeature) -> String {
+        let space = if self.minify { "" } else { " " };
+        match *feature {
+            MediaFeature::Boolean(ref name) => name.clone(),
+            MediaFeature::Plain(ref name, ref value) => {
+                format!("{}:{}{}", name, space, self.media_feature_value(value))
+            }
+            MediaFeature::Range(ref name, ref comparisons) => match comparisons.as_slice() {
+                [(comparison, ref value)] => format!(
+                    "{}{}{}{}{}",
+                    name,
+                    space,
+                    comparison_operator(*comparison),
+                    space,
+                    self.media_feature_value(value)
+                ),
+                // 値が先に来る形式は比較の向きを反転して保持している
+                [(first, ref min), (second, ref max)] => format!(
+                    "{}{}{}{}{}{}{}{}{}",
+                    self.media_feature_value(min),
+                    space,
+                    comparison_operator(flip(*first)),
+                    space,
+                    name,
+                    space,
+                    comparison_operator(*second),
+                    space,
+                    self.media_feature_value(max)
+                ),
+                _ => panic!("Invalid range feature {:?}", feature),
+            },
+        }
+    }
+
+    fn media_feature_value(&self, value: &MediaFeatureValue) -> String {
+        match *value {
+            MediaFeatureValue::Number(f) => self.number(f),
+            MediaFeatureValue::Length(f, unit) => self.number(f) + unit.name(),
+            MediaFeatureValue::Resolution(f) => self.number(f) + "dppx",
+            MediaFeatureValue::Ident(ref ident) => ident.clone(),
+        }
+    }
+
+    fn supports_condition(&self, condition: &SupportsCondition) -> String {
+        let join = |conditions: &[SupportsCondition], op: &str| {
+            let conditions: Vec<String> = conditions
+                .iter()
+                .map(|c| self.supports_in_parens(c))
+                .collect();
+            conditions.join(op)
+        };
+        match *condition {
+            SupportsCondition::Not(ref condition) => {
+                format!("not {}", self.supports_in_parens(condition))
+            }
+            SupportsCondition::And(ref conditions) => join(conditions, " and "),
+            SupportsCondition::Or(ref conditions) => join(conditions, " or "),
+            _ => self.supports_in_parens(condition),
+        }
+    }
+
+    fn supports_in_parens(&self, condition: &SupportsCondition) -> String {
+        match *condition {
+            SupportsCondition::Declaration(ref name, ref value) => {
+                let space = if self.minify { "" } else { " " };
+                format!("({}:{}{})", name, space, value)
+            }
+            SupportsCondition::Selector(ref selector) => format!("selector({})", selector),
+            SupportsCondition::Unknown(ref contents) => format!("({})", contents),
+            _ => format!("({})", self.supports_condition(condition)),
+        }
+    }
+}
+
+/// 同じショートハンドの宣言から展開されたロングハンドかどうか
+fn same_shorthand(a: &Declaration, b: &Declaration) -> bool {
+    match (&a.value, &b.value) {
+        (Value::Unparsed(a_value), Value::Unparsed(b_value)) => {
+            a_value == b_value && a.important == b.important
+        }
+        _ => false,
+    }
+}
+
+fn selector(selector: &Selector) -> String {
+    match *selector {
+        Selector::Simple(ref simple) => compound_selector(simple),
+        Selector::Complex(ref complex) => {
+            let mut result = String::new();
+            for (combinator, compound) in complex.ancestors.iter().rev() {
+                result.push_str(&compound_selector(compound));
+                result.push_str(match combinator {
+                    Combinator::Descendant => " ",
+                    Combinator::Child => " > ",
+                });
+            }
+            result.push_str(&compound_selector(&complex.subject));
+            result
+        }
+    }
+}
+
+fn compound_selector(simple: &SimpleSelector) -> String {
+    let mut result = String::new();
+    if let Some(ref tag_name) = simple.tag_name {
+        result.push_str(tag_name);
+    }
+    if let Some(ref id) = simple.id {
+        result.push('#');
+        result.push_str(id);
+    }
+    for class in &simple.class {
+        result.push('.');
+        result.push_str(class);
+    }
+    for pseudo_class in &simple.pseudo_classes {
+        result.push(':');
+        result.push_str(pseudo_class.name());
+    }
+    if let Some(pseudo_element) = simple.pseudo_element {
+        result.push_str("::");
+        result.push_str(pseudo_element.name());
+    }
+    if result.is_empty() {
+        result.push('*');
+    }
+    result
+}
+
+fn layer_name(name: &LayerName) -> String {
+    name.join(".")
+}
+
+/// 二重引用符で囲み、`"` と `\` をエスケープする
+fn string(s: &str) -> String {
+    let mut result = String::from("\"");
+    for c in s.chars() {
+        if c == '"' || c == '\\' {
+            result.push('\\');
+        }
+        result.push(c);
+    }
+    result.push('"');
+    result
+}
+
+fn comparison_operator(comparison: Comparison) -> &'static str {
+    match comparison {
+        Comparison::Lt => "<",
+        Comparison::Le => "<=",
+        Comparison::Eq => "=",
+        Comparison::Ge => ">=",
+        Comparison::Gt => ">",
+    }
+}
+
+fn flip(comparison: Comparison) -> Comparison {
+    match comparison {
+        Comparison::Lt => Comparison::Gt,
+        Comparison::Le => Comparison::Ge,
+        Comparison::Eq => Comparison::Eq,
+        Comparison::Ge => Comparison::Le,
+        Comparison::Gt => Comparison::Lt,
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&Serializer::new(false).value(self))
+    }
+}
+
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&selector(self))
+    }
+}
+
+impl fmt::Display for Stylesheet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&pretty(self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::css::{parse, parse_with_origin, Origin};
+
+    const STYLESHEET: &str = r#"
+        @layer reset, base.components;
+        @import url("a \"b\".css") layer(base) screen and (min-width: 400px);
+        @import 'c.css' layer;
+
+        * { margin: 0 auto 1.5em -0.25px; }
+        h1, div#main.a.b, ul > li .c, a:hover > :focus-visible { color: rgb(255 0 0 / 50%); background: #00ccff; }
+        p {
+            --brand: { x: y } 1px;
+            padding: var(--gap, 2px) 0;
+            border: thin solid currentcolor !important;
+            width: calc(100% - (2 * 1em + 3px) / 2);
+            height: min(10vw, max(5px, 1rem), clamp(1px, 50%, 80px));
+            font-size: calc(10px * -1);
+        }
+        @media only screen and (orientation: landscape), not print, (400px <= width < 800px) or (resolution) {
+            @layer base { p { color: transparent; } }
+        }
+        @media not ((hover) and (prefers-color-scheme: dark)) { p { color: #fff; } }
+        @supports ((display: grid) and (not (display: inline-grid))) or selector(a.b) {
+            @supports (foo bar) or (--x: 1) { p { color: #123456; } }
+        }
+        @layer { p { width: 0.5px; } }
+        @media { p { height: 1px; } }
+        q::before, a:after { content: open-quote "\"" attr(title) counters(a, '.', upper-roman) counter(b); }
+    "#;
+
+    #[test]
+    fn test_round_trip() {
+        let sources = [
+            (STYLESHEET, Origin::Author),
+            (include_str!("../ua.css"), Origin::UserAgent),
+            (include_str!("../../test/test.css"), Origin::Author),
+        ];
+        for (source, origin) in sources {
+            let stylesheet = parse_with_origin(source.to_string(), origin);
+            let pretty = pretty(&stylesheet);
+            assert_eq!(
+                parse_with_origin(pretty.clone(), origin),
+                stylesheet,
+                "{}",
+                pretty
+            );
+            let minified = minify(&stylesheet);
+            assert_eq!(
+                parse_with_origin(minified.clone(), origin),
+                stylesheet,
+                "{}",
+                minified
+            );
+            assert!(minified.len() < pretty.len());
+        }
+    }
+
+    #[test]
+    fn test_pretty() {
+        let stylesheet = parse(String::from(
+            "@media screen and (width >= 400px) { p, .a { color: red; margin: var(--m) !important; } } div { width: calc(50% - 0.5px); }",
+        ));
+        assert_eq!(
+            pretty(&stylesheet),
+            r#"@media screen and (width >= 400px) {
+  .a, p {
+    color: #ff0000;
+    margin: var(--m) !important;
+  }
+}
+
+div {
+  width: calc(50% - 0.5px);
+}
+"#
+        );
+    }
+
+    #[test]
+    fn test_minify() {
+        let stylesheet = parse(String::from("@media { p { color: red; } }"));
+        assert_eq!(
+            pretty(&stylesheet),
+            "@media {\n  p {\n    color: #ff0000;\n  }\n}\n"
+        );
+        assert_eq!(minify(&stylesheet), "@media{p{color:#f00;}}");
+
+        let stylesheet = parse(String::from(
+            "@media (min-width: 400px) { p, .a { color: red; margin: var(--m) !important; } } div { width: calc(50% - 0.5px); background-color: #11223380; }",
+        ));
+        assert_eq!(
+            minify(&stylesheet),
+            "@media (width>=400px){.a,p{color:#f00;margin:var(--m)!important;}}div{width:calc(50% - .5px);background-color:#11223380;}"
+        );
+    }
+}
@@ -0,0 +1,446 @@
+//! ショートハンドプロパティをロングハンドに展開する
+//!
+//! https://www.w3.org/TR/css-cascade-4/#shorthand
+
+use super::{
+    custom::{TokenStream, UnparsedValue},
+    properties, Declaration, Value,
+};
+
+const SIDES: [&str; 4] = ["top", "right", "bottom", "left"];
+
+const BORDER_STYLES: [&str; 10] = [
+    "none", "hidden", "dotted", "dashed", "solid", "double", "groove", "ridge", "inset", "outset",
+];
+
+const BORDER_WIDTHS: [&str; 3] = ["thin", "medium", "thick"];
+
+const BACKGROUND_REPEATS: [&str; 6] = [
+    "repeat",
+    "repeat-x",
+    "repeat-y",
+    "no-repeat",
+    "space",
+    "round",
+];
+
+const BACKGROUND_ATTACHMENTS: [&str; 3] = ["scroll", "fixed", "local"];
+
+const BACKGROUND_POSITIONS: [&str; 5] = ["left", "right", "top", "bottom", "center"];
+
+/// ショートハンドの名前と、それを構成するロングハンドの並び
+const SHORTHANDS: [(&str, Shorthand); 12] = [
+    ("background", Shorthand::Background),
+    ("border", Shorthand::Border(&SIDES)),
+    ("border-bottom", Shorthand::Border(&["bottom"])),
+    ("border-color", Shorthand::Sides("border-", "-color")),
+    ("border-left", Shorthand::Border(&["left"])),
+    ("border-right", Shorthand::Border(&["right"])),
+    ("border-style", Shorthand::Sides("border-", "-style")),
+    ("border-top", Shorthand::Border(&["top"])),
+    ("border-width", Shorthand::Sides("border-", "-width")),
+    ("list-style", Shorthand::ListStyle),
+    ("margin", Shorthand::Sides("margin-", "")),
+    ("padding", Shorthand::Sides("padding-", "")),
+];
+
+const BACKGROUND_LONGHANDS: [&str; 5] = [
+    "background-color",
+    "background-image",
+    "background-repeat",
+    "background-attachment",
+    "background-position",
+];
+
+const LIST_STYLE_LONGHANDS: [&str; 2] = ["list-style-position", "list-style-type"];
+
+#[derive(Debug, Clone, Copy)]
+enum Shorthand {
+    /// 上・右・下・左の順の、前置きと後置きの間に辺の名前が入るロングハンド
+    Sides(&'static str, &'static str),
+    /// 各辺の `border-<side>-width`、`-style`、`-color`
+    Border(&'static [&'static str]),
+    /// `BACKGROUND_LONGHANDS`
+    Background,
+    /// `LIST_STYLE_LONGHANDS`
+    ListStyle,
+}
+
+impl Shorthand {
+    fn from_name(name: &str) -> Option<Shorthand> {
+        SHORTHANDS
+            .iter()
+            .find(|&&(shorthand, _)| shorthand == name)
+            .map(|&(_, shorthand)| shorthand)
+    }
+
+    /// 展開したときの順に並べたロングハンドの名前
+    fn longhands(self) -> Vec<String> {
+        match self {
+            Shorthand::Sides(prefix, suffix) => SIDES
+                .iter()
+                .map(|side| format!("{}{}{}", prefix, side, suffix))
+                .collect(),
+            Shorthand::Border(sides) => sides
+                .iter()
+                .flat_map(|side| {
+                    ["width", "style", "color"].map(|part| format!("border-{}-{}", side, part))
+                })
+                .collect(),
+            Shorthand::Background => BACKGROUND_LONGHANDS.map(str::to_string).to_vec(),
+            Shorthand::ListStyle => LIST_STYLE_LONGHANDS.map(str::to_string).to_vec(),
+        }
+    }
+}
+
+/// 宣言 `name: values` をロングハンドの宣言の列に展開する
+///
+/// ショートハンドでないプロパティはそのまま1つの宣言になる
+/// 値がショートハンドの文法に合わない場合は `None` を返し、宣言は無視される
+/// CSS全体のキーワード (`inherit` など) 単独の値は、すべてのロングハンドに同じ値を与える
+pub fn expand(name: &str, values: Vec<Value>) -> Option<Vec<Declaration>> {
+    let Some(shorthand) = Shorthand::from_name(name) else {
+        return Some(vec![declaration(name.to_string(), values)]);
+    };
+    let longhands = shorthand.longhands();
+    let values = match values[..] {
+        [ref keyword] if properties::is_css_wide_keyword(keyword) => {
+            vec![keyword.clone(); longhands.len()]
+        }
+        _ => match shorthand {
+            Shorthand::Sides(..) => expand_sides(values)?,
+            Shorthand::Border(sides) => expand_border(values, sides.len())?,
+            Shorthand::Background => expand_background(values)?,
+            Shorthand::ListStyle => expand_list_style(values)?,
+        },
+    };
+    Some(
+        longhands
+            .into_iter()
+            .zip(values)
+            .map(|(name, value)| longhand(name, value))
+            .collect(),
+    )
+}
+
+/// `var()` を含む宣言 `name: tokens` を、置換を待つロングハンドの宣言の列に展開する
+///
+/// https://www.w3.org/TR/css-variables-1/#variables-in-shorthands
+pub fn expand_unparsed(name: &str, tokens: TokenStream) -> Vec<Declaration> {
+    let unparsed = |shorthand: Option<&str>| {
+        Value::Unparsed(UnparsedValue {
+            tokens: tokens.clone(),
+            shorthand: shorthand.map(str::to_string),
+        })
+    };
+    match longhands(name) {
+        Some(longhands) => longhands
+            .into_iter()
+            .map(|longhand_name| longhand(longhand_name, unparsed(Some(name))))
+            .collect(),
+        None => vec![longhand(name.to_string(), unparsed(None))],
+    }
+}
+
+/// ショートハンド `name` を構成するロングハンドの名前
+pub fn longhands(name: &str) -> Option<Vec<String>> {
+    Shorthand::from_name(name).map(Shorthand::longhands)
+}
+
+/// 値の列を1つの値にまとめる
+///
+/// 成分が1つならその値そのもの、複数なら `Value::List` になる
+pub fn into_value(mut values: Vec<Value>) -> Value {
+    if values.len() == 1 {
+        values.pop().unwrap()
+    } else {
+        Value::List(values)
+    }
+}
+
+fn declaration(name: String, values: Vec<Value>) -> Declaration {
+    longhand(name, into_value(values))
+}
+
+/// `!important` は構文解析の側で付け直す
+pub fn longhand(name: String, value: Value) -> Declaration {
+    Declaration {
+        name,
+        value,
+        important: false,
+    }
+}
+
+/// 1〜4個の値を上・右・下・左に割り当てる
+///
+/// https://www.w3.org/TR/css-box-4/#margin-shorthand
+fn expand_sides(values: Vec<Value>) -> Option<Vec<Value>> {
+    let sides = match values.as_slice() {
+        [all] => [all, all, all, all],
+        [vertical, horizontal] => [vertical, horizontal, vertical, horizontal],
+        [top, horizontal, bottom] => [top, horizontal, bottom, horizontal],
+        [top, right, bottom, left] => [top, right, bottom, left],
+        _ => return None,
+    };
+    Some(sides.into_iter().cloned().collect())
+}
+
+/// `border` と `border-<side>` を幅・スタイル・色に分解する
+///
+/// 各成分は任意の順序で高々1回ずつ現れ、省略された成分は初期値になる
+/// `sides` 個の辺のそれぞれに、幅・スタイル・色の順に値を返す
+fn expand_border(values: Vec<Value>, sides: usize) -> Option<Vec<Value>> {
+    let mut width = None;
+    let mut style = None;
+    let mut color = None;
+    for value in values {
+        let slot = match value {
+            Value::Length(..) | Value::Number(_) | Value::Calc(_) => &mut width,
+            Value::Keyword(ref k) if BORDER_WIDTHS.contains(&&**k) => &mut width,
+            Value::Keyword(ref k) if BORDER_STYLES.contains(&&**k) => &mut style,
+            Value::Keyword(_) | Value::ColorValue(_) => &mut color,
+            Value::Percentage(_)
+            | Value::List(_)
+            | Value::CommaList(_)
+            | Value::String(_)
+            | Value::Content(_)
+            | Value::Unparsed(_) => return None,
+        };
+        if slot.replace(value).is_some() {
+            return None;
+        }
+    }
+
+    let width = width.unwrap_or_else(|| Value::Keyword("medium".to_string()));
+    let style = style.unwrap_or_else(|| Value::Keyword("none".to_string()));
+    let color = color.unwrap_or_else(|| Value::Keyword("currentcolor".to_string()));
+
+    Some(
+        [width, style, color]
+            .iter()
+            .cycle()
+            .take(3 * sides)
+            .cloned()
+            .collect(),
+    )
+}
+
+/// `background` を各ロングハンドに分解する
+///
+/// 複数レイヤー (カンマ区切り) と画像は未対応
+fn expand_background(values: Vec<Value>) -> Option<Vec<Value>> {
+    let mut color = None;
+    let mut image = None;
+    let mut repeat = None;
+    let mut attachment = None;
+    let mut position = Vec::new();
+    for value in values {
+        let slot = match value {
+            Value::Length(..) | Value::Number(_) | Value::Percentage(_) | Value::Calc(_) => {
+                position.push(value);
+                continue;
+            }
+            Value::Keyword(ref k) if BACKGROUND_POSITIONS.contains(&&**k) => {
+                position.push(value);
+                continue;
+            }
+            Value::Keyword(ref k) if k == "none" => &mut image,
+            Value::Keyword(ref k) if BACKGROUND_REPEATS.contains(&&**k) => &mut repeat,
+            Value::Keyword(ref k) if BACKGROUND_ATTACHMENTS.contains(&&**k) => &mut attachment,
+            Value::Keyword(_) | Value::ColorValue(_) => &mut color,
+            Value::List(_)
+            | Value::CommaList(_)
+            | Value::String(_)
+            | Value::Content(_)
+            | Value::Unparsed(_) => return None,
+        };
+        if slot.replace(value).is_some() {
+            return None;
+        }
+    }
+    if position.len() > 2 {
+        return None;
+    }
+    if position.is_empty() {
+        position = vec![
+            Value::Keyword("left".to_string()),
+            Value::Keyword("top".to_string()),
+        ];
+    }
+
+    let keyword = |k: &str| Value::Keyword(k.to_string());
+    Some(vec![
+        color.unwrap_or_else(|| keyword("transparent")),
+        image.unwrap_or_else(|| keyword("none")),
+        repeat.unwrap_or_else(|| keyword("repeat")),
+        attachment.unwrap_or_else(|| keyword("scroll")),
+        into_value(position),
+    ])
+}
+
+/// `list-style` を位置と種類に分解する
+///
+/// `list-style-image` は未実装なので、`none` は種類の `none` とみなす
+fn expand_list_style(values: Vec<Value>) -> Option<Vec<Value>> {
+    let mut position = None;
+    let mut list_style_type = None;
+    for value in values {
+        let slot = match value {
+            Value::Keyword(ref k) if k == "inside" || k == "outside" => &mut position,
+            Value::Keyword(_) | Value::String(_) => &mut list_style_type,
+            _ => return None,
+        };
+        if slot.replace(value).is_some() {
+            return None;
+        }
+    }
+    Some(vec![
+        position.unwrap_or_else(|| Value::Keyword("outside".to_string())),
+        list_style_type.unwrap_or_else(|| Value::Keyword("disc".to_string())),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::css::{Color, Unit};
+
+    fn px(f: f32) -> Value {
+        Value::Length(f, Unit::Px)
+    }
+
+    fn keyword(k: &str) -> Value {
+        Value::Keyword(k.to_string())
+    }
+
+    fn pairs(declarations: Vec<Declaration>) -> Vec<(String, Value)> {
+        declarations
+            .into_iter()
+            .map(|d| (d.name, d.value))
+            .collect()
+    }
+
+    #[test]
+    fn test_expand_sides() {
+        let expanded = expand("margin", vec![px(10.0), px(20.0)]).unwrap();
+        assert_eq!(
+            pairs(expanded),
+            vec![
+                ("margin-top".to_string(), px(10.0)),
+                ("margin-right".to_string(), px(20.0)),
+                ("margin-bottom".to_string(), px(10.0)),
+                ("margin-left".to_string(), px(20.0)),
+            ]
+        );
+
+        let expanded = expand("padding", vec![px(1.0), px(2.0), px(3.0)]).unwrap();
+        assert_eq!(
+            pairs(expanded),
+            vec![
+                ("padding-top".to_string(), px(1.0)),
+                ("padding-right".to_string(), px(2.0)),
+                ("padding-bottom".to_string(), px(3.0)),
+                ("padding-left".to_string(), px(2.0)),
+            ]
+        );
+
+        let expanded = expand("border-color", vec![keyword("red")]).unwrap();
+        assert_eq!(expanded[3].name, "border-left-color");
+
+        assert_eq!(expand("margin", vec![px(1.0); 5]), None);
+    }
+
+    #[test]
+    fn test_expand_border() {
+        let black = Value::ColorValue(Color {
+            r: 0,
+            g: 0,
+            b: 0,
+            a: 255,
+        });
+        let expanded = expand("border", vec![px(1.0), keyword("solid"), black.clone()]).unwrap();
+        assert_eq!(expanded.len(), 12);
+        assert_eq!(
+            pairs(expanded)[..3],
+            [
+                ("border-top-width".to_string(), px(1.0)),
+                ("border-top-style".to_string(), keyword("solid")),
+                ("border-top-color".to_string(), black),
+            ]
+        );
+
+        let expanded = expand("border-left", vec![keyword("dashed")]).unwrap();
+        assert_eq!(
+            pairs(expanded),
+            vec![
+                ("border-left-width".to_string(), keyword("medium")),
+                ("border-left-style".to_string(), keyword("dashed")),
+                ("border-left-color".to_string(), keyword("currentcolor")),
+            ]
+        );
+
+        assert_eq!(expand("border", vec![px(1.0), px(2.0)]), None);
+    }
+
+    #[test]
+    fn test_expand_background() {
+        let expanded = expand("background", vec![keyword("red"), keyword("no-repeat")]).unwrap();
+        assert_eq!(
+            pairs(expanded),
+            vec![
+                ("background-color".to_string(), keyword("red")),
+                ("background-image".to_string(), keyword("none")),
+                ("background-repeat".to_string(), keyword("no-repeat")),
+                ("background-attachment".to_string(), keyword("scroll")),
+                (
+                    "background-position".to_string(),
+                    Value::List(vec![keyword("left"), keyword("top")])
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_expand_list_style() {
+        let expanded = expand("list-style", vec![keyword("inside"), keyword("square")]).unwrap();
+        assert_eq!(
+            pairs(expanded),
+            vec![
+                ("list-style-position".to_string(), keyword("inside")),
+                ("list-style-type".to_string(), keyword("square")),
+            ]
+        );
+        assert_eq!(expand("list-style", vec![keyword("a"), keyword("b")]), None);
+    }
+
+    #[test]
+    fn test_longhand_is_kept() {
+        let expanded = expand("margin-left", vec![keyword("auto")]).unwrap();
+        assert_eq!(
+            pairs(expanded),
+            vec![("margin-left".to_string(), keyword("auto"))]
+        );
+    }
+
+    #[test]
+    fn test_longhands() {
+        for (name, shorthand) in SHORTHANDS {
+            let longhands = shorthand.longhands();
+            for longhand in &longhands {
+                assert!(
+                    properties::PropertyId::from_name(longhand).is_some(),
+                    "{}",
+                    longhand
+                );
+            }
+            let expanded = expand(name, vec![keyword("inherit")]).unwrap();
+            let names: Vec<String> = expanded.into_iter().map(|d| d.name).collect();
+            assert_eq!(names, longhands, "{}", name);
+        }
+        assert_eq!(
+            longhands("border-top").unwrap(),
+            ["border-top-width", "border-top-style", "border-top-color"]
+        );
+        assert_eq!(longhands("color"), None);
+    }
+}
@@ -0,0 +1,182 @@
+//! `@supports` の条件の構文解析と評価
+//!
+//! https://www.w3.org/TR/css-conditional-3/#at-supports
+
+use super::{custom, properties::PropertyId, shorthand, Parser};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SupportsCondition {
+    Not(Box<SupportsCondition>),
+    And(Vec<SupportsCondition>),
+    Or(Vec<SupportsCondition>),
+    /// `(<property>: <value>)`
+    Declaration(String, String),
+    /// `selector(<selector>)`
+    Selector(String),
+    /// 解釈できない括弧 (常に偽)
+    Unknown(String),
+}
+
+impl SupportsCondition {
+    pub fn matches(&self) -> bool {
+        match *self {
+            SupportsCondition::Not(ref condition) => !condition.matches(),
+            SupportsCondition::And(ref conditions) => conditions.iter().all(|c| c.matches()),
+            SupportsCondition::Or(ref conditions) => conditions.iter().any(|c| c.matches()),
+            SupportsCondition::Declaration(ref name, ref value) => {
+                supports_declaration(name, value)
+            }
+            SupportsCondition::Selector(ref selector) => supports_selector(selector),
+            SupportsCondition::Unknown(_) => false,
+        }
+    }
+}
+
+/// 宣言 `name: value` をエンジンが解釈して描画に使えるかどうか
+///
+/// ショートハンドは展開したロングハンドがすべて `properties::PROPERTIES` の文法に合えば対応とみなす
+fn supports_declaration(name: &str, value: &str) -> bool {
+    if custom::is_custom_property(name) {
+        return true;
+    }
+    let mut parser = Parser::new(value.to_string());
+    let values = match parser.parse_values() {
+        Some(values) if !values.is_empty() && parser.eof() => values,
+        _ => return false,
+    };
+    shorthand::expand(name, values).is_some_and(|declarations| {
+        declarations.iter().all(|declaration| {
+            PropertyId::from_name(&declaration.name)
+                .is_some_and(|id| id.def().accepts(&declaration.value))
+        })
+    })
+}
+
+/// 結合子を含む1つのセレクタとして構文解析できるかどうか
+fn supports_selector(selector: &str) -> bool {
+    let mut parser = Parser::new(selector.trim().to_string());
+    parser.parse_selector().is_some() && parser.eof()
+}
+
+impl Parser {
+    /// `@supports` に続く条件を `{` の手前まで読む
+    pub(super) fn parse_supports_condition(&mut self) -> SupportsCondition {
+        self.consume_whitespace();
+        if self.starts_with_keyword("not") {
+            self.parse_identifier();
+            self.consume_whitespace();
+            return SupportsCondition::Not(Box::new(self.parse_supports_in_parens()));
+        }
+
+        let mut conditions = vec![self.parse_supports_in_parens()];
+        self.consume_whitespace();
+        let (keyword, op): (_, fn(_) -> _) = if self.starts_with_keyword("or") {
+            ("or", SupportsCondition::Or)
+        } else {
+            ("and", SupportsCondition::And)
+        };
+        loop {
+            self.consume_whitespace();
+            if !self.starts_with_keyword(keyword) {
+                break;
+            }
+            self.parse_identifier();
+            self.consume_whitespace();
+            conditions.push(self.parse_supports_in_parens());
+        }
+        if conditions.len() == 1 {
+            conditions.pop().unwrap()
+        } else {
+            op(conditions)
+        }
+    }
+
+    /// 括弧で囲まれた条件、宣言、`selector()` のいずれか
+    fn parse_supports_in_parens(&mut self) -> SupportsCondition {
+        if self.starts_with("selector(") {
+            self.parse_identifier();
+            return SupportsCondition::Selector(self.parse_parenthesized());
+        }
+        let contents = self.parse_parenthesized();
+        let mut parser = Parser::new(contents.clone());
+        parser.consume_whitespace();
+        if parser.eof() {
+            return SupportsCondition::Unknown(contents);
+        }
+        if parser.next_char() == '(' || parser.starts_with_keyword("not") {
+            let condition = parser.parse_supports_condition();
+            parser.consume_whitespace();
+            return if parser.eof() {
+                condition
+            } else {
+                SupportsCondition::Unknown(contents)
+            };
+        }
+
+        let name = parser.parse_identifier();
+        parser.consume_whitespace();
+        if name.is_empty() || parser.eof() || parser.consume_char() != ':' {
+            return SupportsCondition::Unknown(contents);
+        }
+        let value = parser.input[parser.pos..].trim().to_string();
+        SupportsCondition::Declaration(name, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> SupportsCondition {
+        let mut parser = Parser::new(source.to_string());
+        parser.parse_supports_condition()
+    }
+
+    #[test]
+    fn test_parse_supports_condition() {
+        let declaration = |name: &str, value: &str| {
+            SupportsCondition::Declaration(name.to_string(), value.to_string())
+        };
+        assert_eq!(parse("(display: grid)"), declaration("display", "grid"));
+        assert_eq!(
+            parse("not ((color: red) or (--x: 1 2 3))"),
+            SupportsCondition::Not(Box::new(SupportsCondition::Or(vec![
+                declaration("color", "red"),
+                declaration("--x", "1 2 3"),
+            ])))
+        );
+        assert_eq!(
+            parse("(width: 1px) and selector(.a) and (foo bar)"),
+            SupportsCondition::And(vec![
+                declaration("width", "1px"),
+                SupportsCondition::Selector(".a".to_string()),
+                SupportsCondition::Unknown("foo bar".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_evaluate_supports_condition() {
+        assert!(!parse("(display: grid)").matches());
+        assert!(parse("(display: block)").matches());
+        assert!(parse("not (display: flex)").matches());
+        assert!(parse("(width: calc(100% - 1px)) and (color: rgb(0 0 0 / 50%))").matches());
+        assert!(!parse("(width: red)").matches());
+        assert!(!parse("(float: left)").matches());
+        assert!(parse("(border: 1px solid red) or (float: left)").matches());
+        assert!(!parse("(border: 1px wavy red)").matches());
+        assert!(parse("(--anything: { } !)").matches());
+        assert!(parse("selector(div.a#b)").matches());
+        assert!(parse("selector(a:hover:focus-visible)").matches());
+        assert!(!parse("selector(a:visited)").matches());
+        assert!(parse("selector(div > p)").matches());
+        assert!(parse("selector(ul li > .a:hover)").matches());
+        assert!(!parse("selector(div >)").matches());
+        assert!(!parse("selector(a:)").matches());
+        assert!(!parse("selector(a, b)").matches());
+        assert!(!parse("selector()").matches());
+        assert!(parse("selector(p::before)").matches());
+        assert!(!parse("selector(p::first-line)").matches());
+        assert!(!parse("(unknown syntax)").matches());
+    }
+}
@@ -9,6 +9,20 @@ pub struct Node {
 
     // data specific to each node type
     pub node_type: NodeType,
+
+    /// 前回スタイルを求めてからの変更
+    pub dirty: Dirty,
+}
+
+/// スタイルを求め直すために、ノードに付けておく変更の印
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Dirty {
+    /// 最初に変更される前の属性。属性が変わっていなければ `None`
+    pub old_attributes: Option<AttrMap>,
+    /// 最初に変更される前の状態。状態が変わっていなければ `None`
+    pub old_state: Option<ElementState>,
+    /// 子が追加または削除された
+    pub children_changed: bool,
 }
 
 #[derive(Debug, PartialEq)]
@@ -21,22 +35,116 @@ pub enum NodeType {
 pub struct ElementData {
     pub tag_name: String,
     pub attributes: AttrMap,
+    pub state: ElementState,
+}
+
+/// 利用者の操作で変わる要素の状態
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ElementState {
+    /// ポインタが要素か、その子孫の上にある
+    pub hover: bool,
+    /// ポインタのボタンが要素の上で押されている
+    pub active: bool,
+    pub focus: bool,
+    /// フォーカスを示す印を表示する (キーボードでフォーカスを移した場合など)
+    pub focus_visible: bool,
+    pub checked: bool,
 }
 
 pub fn text(data: String) -> Node {
     Node {
         children: Vec::new(),
         node_type: NodeType::Text(data),
+        dirty: Dirty::default(),
     }
 }
 
+/// 状態の初期値は `checked` 属性から決める
 pub fn elem(name: String, attrs: AttrMap, children: Vec<Node>) -> Node {
+    let state = ElementState {
+        checked: attrs.contains_key("checked"),
+        ..ElementState::default()
+    };
     Node {
         children,
         node_type: NodeType::Element(ElementData {
             tag_name: name,
             attributes: attrs,
+            state,
         }),
+        dirty: Dirty::default(),
+    }
+}
+
+impl Node {
+    /// 要素の属性を変更する。テキストノードでは panic する
+    pub fn set_attribute(&mut self, name: &str, value: &str) {
+        self.attributes_mut()
+            .insert(name.to_string(), value.to_string());
+    }
+
+    /// 要素の属性を削除する。テキストノードでは panic する
+    pub fn remove_attribute(&mut self, name: &str) {
+        self.attributes_mut().remove(name);
+    }
+
+    pub fn append_child(&mut self, child: Node) {
+        self.children.push(child);
+        self.dirty.children_changed = true;
+    }
+
+    pub fn remove_child(&mut self, index: usize) -> Node {
+        self.dirty.children_changed = true;
+        self.children.remove(index)
+    }
+
+    /// 要素の状態を変更する。テキストノードでは何もしない
+    pub fn update_state(&mut self, update: impl FnOnce(&mut ElementState)) {
+        let NodeType::Element(ref mut elem) = self.node_type else {
+            return;
+        };
+        let old = elem.state;
+        update(&mut elem.state);
+        if elem.state != old {
+            self.dirty.old_state.get_or_insert(old);
+        }
+    }
+
+    /// `path` は子の番号を根から順に並べたもの
+    pub fn descendant_mut(&mut self, path: &[usize]) -> &mut Node {
+        path.iter()
+            .fold(self, |node, &index| &mut node.children[index])
+    }
+
+    /// `node` までの子の番号。`node` がこの部分木になければ `None`
+    pub fn path_to(&self, node: &Node) -> Option<Vec<usize>> {
+        if std::ptr::eq(self, node) {
+            return Some(Vec::new());
+        }
+        self.children.iter().enumerate().find_map(|(index, child)| {
+            let mut path = child.path_to(node)?;
+            path.insert(0, index);
+            Some(path)
+        })
+    }
+
+    /// 変更前の属性を `dirty` に残してから、属性を返す
+    fn attributes_mut(&mut self) -> &mut AttrMap {
+        let NodeType::Element(ref mut elem) = self.node_type else {
+            panic!("a text node has no attributes");
+        };
+        self.dirty
+            .old_attributes
+            .get_or_insert_with(|| elem.attributes.clone());
+        &mut elem.attributes
+    }
+
+    /// 子孫を含めて変更の印を消す。スタイルを求め直した後に呼ぶ
+    pub fn clear_dirty(&mut self) {
+        self.dirty = Dirty::default();
+        for child in &mut self.children {
+            child.clear_dirty();
+        }
     }
 }
 
@@ -45,6 +153,33 @@ impl ElementData {
         self.attributes.get("id")
     }
 
+    /// `disabled` 属性で無効にできるフォームの要素かどうか
+    pub fn is_form_control(&self) -> bool {
+        matches!(
+            &*self.tag_name,
+            "button" | "input" | "select" | "textarea" | "optgroup" | "option" | "fieldset"
+        )
+    }
+
+    /// `disabled` 属性を持つフォームの要素かどうか
+    ///
+    /// 無効な `fieldset` や `optgroup` の子孫であることによる無効化は扱わない
+    pub fn is_disabled(&self) -> bool {
+        self.is_form_control() && self.attributes.contains_key("disabled")
+    }
+
+    /// キーボードやクリックでフォーカスを受け取れるかどうか
+    pub fn is_focusable(&self) -> bool {
+        if self.is_disabled() {
+            return false;
+        }
+        match &*self.tag_name {
+            "button" | "input" | "select" | "textarea" => true,
+            "a" => self.attributes.contains_key("href"),
+            _ => self.attributes.contains_key("tabindex"),
+        }
+    }
+
     pub fn classes(&self) -> HashSet<&str> {
         match self.attributes.get("class") {
             Some(classlist) => classlist.split(' ').collect(),
@@ -0,0 +1,394 @@
+//! フォントの読み込みとメトリクス
+//!
+//! TrueType と OpenType のファイルから、グリフの送り幅、アセントとディセント、カーニングを読む
+//! 合字や文脈による字形の置き換えは行わない
+
+use std::{borrow::Cow, fs, io, path::Path, sync::OnceLock};
+
+use ttf_parser::{
+    gpos::{PairAdjustment, PositioningSubtable},
+    name_id, Face, FaceParsingError, GlyphId, Tag,
+};
+
+use crate::style::computed::{FontFamily, FontStyle};
+
+/// 同梱する既定のフォント。ライセンスは `fonts/OFL.txt`
+const DEFAULT_FONT: &[u8] = include_bytes!("../fonts/Lato-Regular.ttf");
+
+/// 1つのフォントフェイス
+///
+/// 構文解析したフェイスを持ち続けるため、フォントファイルの内容はプログラムの終わりまで解放しない
+#[derive(Debug, Clone)]
+pub struct Font {
+    face: Face<'static>,
+    /// em に対する x-height の比
+    x_height_ratio: f32,
+    /// em に対する "0" の送り幅の比
+    zero_advance_ratio: f32,
+    pub family: String,
+    /// 1から1000までの太さ
+    pub weight: f32,
+    pub style: FontStyle,
+}
+
+/// px 単位の縦のメトリクス
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FontMetrics {
+    /// ベースラインから上の高さ
+    pub ascent: f32,
+    /// ベースラインから下の深さ (正の値)
+    pub descent: f32,
+    /// 行間として推奨される余白
+    pub line_gap: f32,
+}
+
+impl Font {
+    /// フォントファイルの内容 `data` の `index` 番目のフェイスを読む
+    pub fn from_data(
+        data: impl Into<Cow<'static, [u8]>>,
+        index: u32,
+    ) -> Result<Font, FaceParsingError> {
+        let data: &'static [u8] = match data.into() {
+            Cow::Borrowed(data) => data,
+            Cow::Owned(data) => Box::leak(data.into_boxed_slice()),
+        };
+        let face = Face::parse(data, index)?;
+        let name = |id| {
+            face.names()
+                .into_iter()
+                .filter(|name| name.name_id == id && name.is_unicode())
+                .find_map(|name| name.to_string())
+        };
+        let family = name(name_id::TYPOGRAPHIC_FAMILY)
+            .or_else(|| name(name_id::FAMILY))
+            .unwrap_or_default();
+        let weight = face.weight().to_number() as f32;
+        let style = match face.style() {
+            ttf_parser::Style::Normal => FontStyle::Normal,
+            ttf_parser::Style::Italic => FontStyle::Italic,
+            ttf_parser::Style::Oblique => FontStyle::Oblique,
+        };
+        let units_per_em = face.units_per_em() as f32;
+        let x_height_ratio = match face.x_height() {
+            Some(x_height) if x_height > 0 => x_height as f32 / units_per_em,
+            _ => 0.5,
+        };
+        let zero_advance_ratio = face
+            .glyph_index('0')
+            .and_then(|glyph| face.glyph_hor_advance(glyph))
+            .map_or(0.5, |advance| advance as f32 / units_per_em);
+        Ok(Font {
+            face,
+            x_height_ratio,
+            zero_advance_ratio,
+            family,
+            weight,
+            style,
+        })
+    }
+
+    /// TrueType か OpenType のファイルを読む。コレクションなら最初のフェイス
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Font> {
+        let data = fs::read(path)?;
+        Font::from_data(data, 0).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn metrics(&self, font_size: f32) -> FontMetrics {
+        let face = &self.face;
+        let scale = font_size / face.units_per_em() as f32;
+        FontMetrics {
+            ascent: face.ascender() as f32 * scale,
+            descent: -face.descender() as f32 * scale,
+            line_gap: face.line_gap() as f32 * scale,
+        }
+    }
+
+    /// em に対する x-height の比。フォントが持たなければ 0.5
+    pub fn x_height_ratio(&self) -> f32 {
+        self.x_height_ratio
+    }
+
+    /// em に対する "0" の送り幅の比。フォントに "0" がなければ 0.5
+    pub fn zero_advance_ratio(&self) -> f32 {
+        self.zero_advance_ratio
+    }
+
+    /// 文字 `c` のグリフの送り幅。フォントにない文字は `None`
+    pub fn advance(&self, c: char, font_size: f32) -> Option<f32> {
+        let face = &self.face;
+        let advance = face.glyph_hor_advance(face.glyph_index(c)?)?;
+        Some(advance as f32 * font_size / face.units_per_em() as f32)
+    }
+
+    /// 文字 `left` の直後に `right` が来たときの送り幅の調整
+    pub fn kerning(&self, left: char, right: char, font_size: f32) -> f32 {
+        let face = &self.face;
+        let glyph = |c| face.glyph_index(c).unwrap_or(GlyphId(0));
+        kerning(face, glyph(left), glyph(right)) as f32 * font_size / face.units_per_em() as f32
+    }
+
+    /// 文字列の幅。送り幅とカーニングを足し合わせる
+    ///
+    /// フォントにない文字は `.notdef` のグリフで表す
+    pub fn text_width(&self, text: &str, font_size: f32) -> f32 {
+        let face = &self.face;
+        let mut units = 0;
+        let mut previous = None;
+        for c in text.chars() {
+            let glyph = face.glyph_index(c).unwrap_or(GlyphId(0));
+            if let Some(previous) = previous {
+                units += kerning(face, previous, glyph) as i32;
+            }
+            units += face.glyph_hor_advance(glyph).unwrap_or(0) as i32;
+            previous = Some(glyph);
+        }
+        units as f32 * font_size / face.units_per_em() as f32
+    }
+}
+
+/// グリフの組のカーニング (フォント単位)
+///
+/// GPOS テーブルの `kern` 機能を優先し、なければ `kern` テーブルを使う
+fn kerning(face: &Face, left: GlyphId, right: GlyphId) -> i16 {
+    if let Some(gpos) = face.tables().gpos {
+        let lookups = gpos
+            .features
+            .into_iter()
+            .filter(|feature| feature.tag == Tag::from_bytes(b"kern"))
+            .flat_map(|feature| feature.lookup_indices)
+            .filter_map(|index| gpos.lookups.get(index));
+        for lookup in lookups {
+            for subtable in lookup.subtables.into_iter::<PositioningSubtable>() {
+                if let PositioningSubtable::Pair(pair) = subtable {
+                    if let Some(kerning) = pair_kerning(pair, left, right) {
+                        return kerning;
+                    }
+                }
+            }
+        }
+    }
+    if let Some(kern) = face.tables().kern {
+        for subtable in kern.subtables {
+            if subtable.horizontal && !subtable.variable {
+                if let Some(kerning) = subtable.glyphs_kerning(left, right) {
+                    return kerning;
+                }
+            }
+        }
+    }
+    0
+}
+
+/// 組が部分テーブルの対象でなければ `None`
+fn pair_kerning(pair: PairAdjustment, left: GlyphId, right: GlyphId) -> Option<i16> {
+    let index = pair.coverage().get(left)?;
+    let (first, _) = match pair {
+        PairAdjustment::Format1 { sets, .. } => sets.get(index)?.get(right)?,
+        PairAdjustment::Format2 {
+            classes, matrix, ..
+        } => matrix.get((classes.0.get(left), classes.1.get(right)))?,
+    };
+    Some(first.x_advance)
+}
+
+/// 使えるフォントの一覧
+///
+/// 最初のフォントのファミリーを、総称ファミリーと、どのファミリーも見つからないときに使う
+#[derive(Debug)]
+pub struct FontDatabase {
+    fonts: Vec<Font>,
+}
+
+impl FontDatabase {
+    /// 同梱のフォントだけを持つ
+    pub fn new() -> FontDatabase {
+        FontDatabase {
+            fonts: vec![Font::from_data(DEFAULT_FONT, 0).expect("bundled font is valid")],
+        }
+    }
+
+    /// 初期値の `font-family` などで選ばれる、最初のフォント
+    pub fn default_font(&self) -> &Font {
+        &self.fonts[0]
+    }
+
+    pub fn add(&mut self, font: Font) {
+        self.fonts.push(font);
+    }
+
+    /// `font-family`、`font-weight`、`font-style` に最も合うフォント
+    ///
+    /// https://www.w3.org/TR/css-fonts-4/#font-matching-algorithm
+    pub fn query(&self, families: &[FontFamily], weight: f32, style: FontStyle) -> &Font {
+        let default_family = &self.fonts[0].family;
+        let faces = |family: &str| -> Vec<&Font> {
+            self.fonts
+                .iter()
+                .filter(|font| font.family.eq_ignore_ascii_case(family))
+                .collect()
+        };
+        let candidates = families
+            .iter()
+            .map(|family| match *family {
+                FontFamily::Named(ref name) => faces(name),
+                FontFamily::Generic(_) => faces(default_family),
+            })
+            .find(|faces| !faces.is_empty())
+            .unwrap_or_else(|| faces(default_family));
+
+        // 斜体の好みの順に探し、見つかった形の中から太さを選ぶ
+        let styles = match style {
+            FontStyle::Normal => [FontStyle::Normal, FontStyle::Oblique, FontStyle::Italic],
+            FontStyle::Italic => [FontStyle::Italic, FontStyle::Oblique, FontStyle::Normal],
+            FontStyle::Oblique => [FontStyle::Oblique, FontStyle::Italic, FontStyle::Normal],
+        };
+        let style = styles
+            .into_iter()
+            .find(|&style| candidates.iter().any(|font| font.style == style))
+            .unwrap();
+        candidates
+            .into_iter()
+            .filter(|font| font.style == style)
+            .min_by(|a, b| {
+                weight_distance(weight, a.weight)
+                    .partial_cmp(&weight_distance(weight, b.weight))
+                    .unwrap()
+            })
+            .unwrap()
+    }
+}
+
+impl Default for FontDatabase {
+    fn default() -> FontDatabase {
+        FontDatabase::new()
+    }
+}
+
+/// 太さ `desired` を求めるときの、太さ `available` のフォントの順位 (小さいほど良い)
+///
+/// 400から500の間ならまず500まで太い方、次に細い方、最後に500より太い方を探す
+/// 400未満なら細い方を、500より大きければ太い方を先に探す
+fn weight_distance(desired: f32, available: f32) -> (u8, f32) {
+    let distance = (desired - available).abs();
+    let first = if (400.0..=500.0).contains(&desired) {
+        if (desired..=500.0).contains(&available) {
+            0
+        } else if available < desired {
+            1
+        } else {
+            2
+        }
+    } else if desired < 400.0 {
+        if available <= desired {
+            0
+        } else {
+            1
+        }
+    } else if available >= desired {
+        0
+    } else {
+        1
+    };
+    (first, distance)
+}
+
+static DATABASE: OnceLock<FontDatabase> = OnceLock::new();
+
+/// スタイルの計算とレイアウトが使うフォントの一覧
+///
+/// `set_database` で設定されていなければ、同梱のフォントだけを持つ一覧になる
+pub fn database() -> &'static FontDatabase {
+    DATABASE.get_or_init(FontDatabase::new)
+}
+
+/// スタイルの計算とレイアウトが使うフォントの一覧を設定する
+///
+/// 最初に `database` を呼ぶ前にだけ設定でき、それより後なら渡した一覧を `Err` で返す
+pub fn set_database(database: FontDatabase) -> Result<(), FontDatabase> {
+    DATABASE.set(database)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bundled_font() {
+        let font = &database().fonts[0];
+        assert_eq!(font.family, "Lato");
+        assert_eq!(font.weight, 400.0);
+        assert_eq!(font.style, FontStyle::Normal);
+
+        let metrics = font.metrics(20.0);
+        assert!(metrics.ascent > 0.0 && metrics.descent > 0.0);
+        assert!(metrics.ascent + metrics.descent > 20.0);
+
+        let a = font.advance('A', 20.0).unwrap();
+        let v = font.advance('V', 20.0).unwrap();
+        assert_eq!(font.text_width("A", 20.0), a);
+        assert_eq!(font.advance('\u{3042}', 20.0), None);
+        let x_height = font.x_height_ratio();
+        assert!(x_height > 0.4 && x_height < 0.6);
+        let zero = font.zero_advance_ratio();
+        assert!(zero > 0.4 && zero < 0.7);
+        // 同梱のフォントは GPOS でカーニングを持つ
+        let kerning = font.kerning('A', 'V', 20.0);
+        assert!(kerning < 0.0);
+        assert_eq!(font.text_width("AV", 20.0), a + v + kerning);
+        assert_eq!(
+            font.text_width("AV", 40.0),
+            2.0 * font.text_width("AV", 20.0)
+        );
+    }
+
+    #[test]
+    fn test_font_matching() {
+        let lato = database().fonts[0].clone();
+        let face = |family: &str, weight: f32, style: FontStyle| Font {
+            family: family.to_string(),
+            weight,
+            style,
+            ..lato.clone()
+        };
+        let mut database = FontDatabase::new();
+        database.add(face("Lato", 300.0, FontStyle::Normal));
+        database.add(face("Lato", 700.0, FontStyle::Normal));
+        database.add(face("Lato", 700.0, FontStyle::Italic));
+        database.add(face("Mono", 600.0, FontStyle::Oblique));
+
+        let named = |name: &str| FontFamily::Named(name.to_string());
+        let query = |families: &[FontFamily], weight, style| {
+            let font = database.query(families, weight, style);
+            (font.family.clone(), font.weight, font.style)
+        };
+        let lato = |weight, style| ("Lato".to_string(), weight, style);
+        let mono = ("Mono".to_string(), 600.0, FontStyle::Oblique);
+
+        assert_eq!(
+            query(&[named("missing"), named("mono")], 400.0, FontStyle::Normal),
+            mono
+        );
+        assert_eq!(
+            query(&[named("missing")], 400.0, FontStyle::Normal),
+            lato(400.0, FontStyle::Normal)
+        );
+        let serif = [FontFamily::Generic("serif".to_string())];
+        assert_eq!(
+            query(&serif, 350.0, FontStyle::Normal),
+            lato(300.0, FontStyle::Normal)
+        );
+        assert_eq!(
+            query(&serif, 450.0, FontStyle::Normal),
+            lato(400.0, FontStyle::Normal)
+        );
+        assert_eq!(
+            query(&serif, 600.0, FontStyle::Normal),
+            lato(700.0, FontStyle::Normal)
+        );
+        assert_eq!(
+            query(&serif, 400.0, FontStyle::Oblique),
+            lato(700.0, FontStyle::Italic)
+        );
+        assert_eq!(query(&[named("MONO")], 900.0, FontStyle::Normal), mono);
+    }
+}
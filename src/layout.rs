@@ -1,7 +1,20 @@
-use crate::css::{Unit, Value};
-use crate::style::{Display, StyledNode};
-
-pub use self::BoxType::{AnonymousBlock, BlockNode, InlineNode};
+use crate::{
+    css::{content::ContentFunction, counter_style, PseudoElement},
+    dom::{Node, NodeType},
+    style::{
+        computed::{
+            ComputedStyle, ContentItem, LengthPercentageOrAuto, ListStylePosition, ListStyleType,
+        },
+        Display, StyledNode,
+    },
+};
+
+pub use self::BoxType::{
+    AnonymousBlock, BlockNode, GeneratedText, InlineNode, LineBox, Marker, Text,
+};
+
+mod inline;
+mod line_break;
 
 #[derive(Debug, Default, Clone, Copy)]
 pub struct Dimensions {
@@ -37,6 +50,16 @@ pub enum BoxType<'a> {
     BlockNode(&'a StyledNode<'a>),
     InlineNode(&'a StyledNode<'a>),
     AnonymousBlock,
+    /// 匿名ブロックの中の1行。子はその行に収まるインラインボックスの断片
+    LineBox,
+    /// テキストノードの文字列。レイアウトの後は1行に収まる断片
+    Text(&'a StyledNode<'a>, String),
+    /// 擬似要素の `content` から生成した文字列。スタイルはその擬似要素のもの
+    ///
+    /// `list-style-position: inside` のマーカーもこれで、スタイルはリスト項目のもの
+    GeneratedText(&'a StyledNode<'a>, String),
+    /// `list-style-position: outside` のマーカー。リスト項目の主ボックスの外に置く
+    Marker(&'a StyledNode<'a>, String),
 }
 
 impl<'a> LayoutBox<'a> {
@@ -50,7 +73,8 @@ impl<'a> LayoutBox<'a> {
 
     fn get_inline_container(&mut self) -> &mut LayoutBox<'a> {
         match self.box_type {
-            InlineNode(_) | AnonymousBlock => self,
+            InlineNode(_) | AnonymousBlock | LineBox | Text(..) | GeneratedText(..)
+            | Marker(..) => self,
             BlockNode(_) => {
                 match self.children.last() {
                     Some(&LayoutBox {
@@ -65,9 +89,9 @@ impl<'a> LayoutBox<'a> {
     }
 
     fn layout(&mut self, containing_block: Dimensions) {
-        match self.box_type {
-            BlockNode(_) => self.layout_block(containing_block),
-            InlineNode(_) | AnonymousBlock => {} // TODO
+        // インラインレベルのボックスは、匿名ブロックの `layout_inline` が行に並べる
+        if let BlockNode(_) = self.box_type {
+            self.layout_block(containing_block);
         }
     }
 
@@ -87,101 +111,79 @@ impl<'a> LayoutBox<'a> {
         self.calculate_block_height();
     }
 
+    /// 計算値のパーセンテージはコンテナブロックの幅に対して解決する
     fn calculate_block_width(&mut self, containing_block: Dimensions) {
-        let style = self.get_style_node();
+        let style = &self.get_style_node().computed;
+        let base = containing_block.content.width;
 
-        let auto = Value::Keyword("auto".to_string());
-        let mut width = style.value("width").unwrap_or(auto.clone());
+        // `auto` は `None`
+        let width = style.width.resolve(base);
+        let mut margin_left = style.margin.left.resolve(base);
+        let mut margin_right = style.margin.right.resolve(base);
 
-        let zero = Value::Length(0.0, Unit::Px);
+        let border_left = style.border_width.left;
+        let border_right = style.border_width.right;
 
-        let mut margin_left = style.lookup("margin-left", "margin", &zero);
-        let mut margin_right = style.lookup("margin-right", "margin", &zero);
-
-        let border_left = style.lookup("border-left-width", "border-width", &zero);
-        let border_right = style.lookup("border-right-width", "border-width", &zero);
-
-        let padding_left = style.lookup("padding-left", "padding", &zero);
-        let padding_right = style.lookup("padding-right", "padding", &zero);
+        let padding_left = style.padding.left.resolve(base);
+        let padding_right = style.padding.right.resolve(base);
 
         let total = sum([
-            &margin_left,
-            &margin_right,
-            &border_left,
-            &border_right,
-            &padding_left,
-            &padding_right,
-            &width,
+            margin_left.unwrap_or(0.0),
+            margin_right.unwrap_or(0.0),
+            border_left,
+            border_right,
+            padding_left,
+            padding_right,
+            width.unwrap_or(0.0),
         ]
-        .iter()
-        .map(|v| v.to_px()));
+        .into_iter());
 
-        if width != auto && total > containing_block.content.width {
-            if margin_left == auto {
-                margin_left = Value::Length(0.0, Unit::Px);
-            }
-            if margin_right == auto {
-                margin_right = Value::Length(0.0, Unit::Px);
-            }
+        if width.is_some() && total > containing_block.content.width {
+            margin_left.get_or_insert(0.0);
+            margin_right.get_or_insert(0.0);
         }
 
         // 上記の合計が `containing_block.width` と等しくなるように、使用する値を調整する
         // `match` の各アームは合計幅をちょうど `underflow` だけ増加させる
-        // その後、すべての値はpx単位の絶対長になる。
         let underflow = containing_block.content.width - total;
 
-        match (width == auto, margin_left == auto, margin_right == auto) {
+        let (width, margin_left, margin_right) = match (width, margin_left, margin_right) {
             // 値が過剰に制約されている場合は、margin_rightを計算する
-            (false, false, false) => {
-                margin_right = Value::Length(margin_right.to_px() + underflow, Unit::Px);
-            }
+            (Some(width), Some(left), Some(right)) => (width, left, right + underflow),
 
             // サイズが1つだけautoの場合、その使用値は等号に従う
-            (false, true, false) => {
-                margin_left = Value::Length(underflow, Unit::Px);
-            }
-            (false, false, true) => {
-                margin_right = Value::Length(underflow, Unit::Px);
-            }
+            (Some(width), None, Some(right)) => (width, underflow, right),
+            (Some(width), Some(left), None) => (width, left, underflow),
 
             // widthがautoに設定されている場合、その他のautoの値は0になる
-            (true, _, _) => {
-                if margin_left == auto {
-                    margin_left = Value::Length(0.0, Unit::Px);
-                }
-                if margin_right == auto {
-                    margin_right = Value::Length(0.0, Unit::Px);
-                }
-
+            (None, left, right) => {
+                let left = left.unwrap_or(0.0);
+                let right = right.unwrap_or(0.0);
                 if underflow >= 0.0 {
                     // アンダーフローを埋めるために幅を広げる
-                    width = Value::Length(underflow, Unit::Px);
+                    (underflow, left, right)
                 } else {
                     // 幅をマイナスにはできない
                     // 右マージンを調整する
-                    width = Value::Length(0.0, Unit::Px);
-                    margin_right = Value::Length(margin_right.to_px() + underflow, Unit::Px);
+                    (0.0, left, right + underflow)
                 }
             }
 
             // margin-leftとmargin-rightが両方ともautoの場合、使用される値は等しくなる
-            (false, true, true) => {
-                margin_left = Value::Length(underflow / 2.0, Unit::Px);
-                margin_right = Value::Length(underflow / 2.0, Unit::Px);
-            }
-        }
+            (Some(width), None, None) => (width, underflow / 2.0, underflow / 2.0),
+        };
 
         let d = &mut self.dimensions;
-        d.content.width = width.to_px();
+        d.content.width = width;
 
-        d.padding.left = padding_left.to_px();
-        d.padding.right = padding_right.to_px();
+        d.padding.left = padding_left;
+        d.padding.right = padding_right;
 
-        d.border.left = border_left.to_px();
-        d.border.right = border_right.to_px();
+        d.border.left = border_left;
+        d.border.right = border_right;
 
-        d.margin.left = margin_left.to_px();
-        d.margin.right = margin_right.to_px();
+        d.margin.left = margin_left;
+        d.margin.right = margin_right;
     }
 
     /// ブロックのエッジサイズの計算を終了し、それを含むブロック内に配置する
@@ -190,25 +192,20 @@ impl<'a> LayoutBox<'a> {
     ///
     /// 垂直マージン/パディング/ボーダー寸法と、`x`, `y` 値を設定する
     fn calculate_block_position(&mut self, containing_block: Dimensions) {
-        let style = self.get_style_node();
+        let style = &self.get_style_node().computed;
+        let base = containing_block.content.width;
         let d = &mut self.dimensions;
 
-        // マージン，ボーダー，パディングの初期値
-        let zero = Value::Length(0.0, Unit::Px);
-
         // margin-topまたはmargin-bottomが`auto`の場合、使用される値は0
-        d.margin.top = style.lookup("margin-top", "margin", &zero).to_px();
-        d.margin.bottom = style.lookup("margin-bottom", "margin", &zero).to_px();
+        d.margin.top = style.margin.top.resolve(base).unwrap_or(0.0);
+        d.margin.bottom = style.margin.bottom.resolve(base).unwrap_or(0.0);
 
-        d.border.top = style
-            .lookup("border-top-width", "border-width", &zero)
-            .to_px();
-        d.border.bottom = style
-            .lookup("border-bottom-width", "border-width", &zero)
-            .to_px();
+        d.border.top = style.border_width.top;
+        d.border.bottom = style.border_width.bottom;
 
-        d.padding.top = style.lookup("padding-top", "padding", &zero).to_px();
-        d.padding.bottom = style.lookup("padding-bottom", "padding", &zero).to_px();
+        // 上下のパディングのパーセンテージもコンテナブロックの幅を基準にする
+        d.padding.top = style.padding.top.resolve(base);
+        d.padding.bottom = style.padding.bottom.resolve(base);
 
         d.content.x = containing_block.content.x + d.margin.left + d.border.left + d.padding.left;
 
@@ -220,10 +217,56 @@ impl<'a> LayoutBox<'a> {
             + d.padding.top;
     }
 
+    /// 点 `(x, y)` をボーダーボックスに含む、最も深いボックスのノード
+    ///
+    /// 後に描かれる子ほど手前にあるので、子は後ろから調べる。匿名ブロックはノードを持たないので、
+    /// その子だけを調べる
+    pub fn hit_test(&self, x: f32, y: f32) -> Option<&'a Node> {
+        if let Some(node) = self
+            .children
+            .iter()
+            .rev()
+            .find_map(|child| child.hit_test(x, y))
+        {
+            return Some(node);
+        }
+        match self.box_type {
+            BlockNode(node) | InlineNode(node) | Marker(node, _)
+                if self.dimensions.border_box().contains(x, y) =>
+            {
+                Some(node.node)
+            }
+            _ => None,
+        }
+    }
+
+    /// ノードを持つボックスのボーダーボックスとそのノードを、描く順に集める
+    ///
+    /// 後ろから調べて最初に点を含むもののノードが、`hit_test` の結果と同じになる
+    /// レイアウトを捨てた後もヒットテストできるように、結果を持っておくために使う
+    pub fn hit_regions(&self) -> Vec<(Rect, &'a Node)> {
+        let mut regions = Vec::new();
+        self.collect_hit_regions(&mut regions);
+        regions
+    }
+
+    fn collect_hit_regions(&self, regions: &mut Vec<(Rect, &'a Node)>) {
+        if let BlockNode(node) | InlineNode(node) | Marker(node, _) = self.box_type {
+            regions.push((self.dimensions.border_box(), node.node));
+        }
+        for child in &self.children {
+            child.collect_hit_regions(regions);
+        }
+    }
+
     fn get_style_node(&self) -> &'a StyledNode<'a> {
         match self.box_type {
-            BlockNode(node) | InlineNode(node) => node,
-            AnonymousBlock => panic!("Anonymous block box has no style node"),
+            BlockNode(node)
+            | InlineNode(node)
+            | Text(node, _)
+            | GeneratedText(node, _)
+            | Marker(node, _) => node,
+            AnonymousBlock | LineBox => panic!("Anonymous block box has no style node"),
         }
     }
 
@@ -231,9 +274,18 @@ impl<'a> LayoutBox<'a> {
     ///
     /// `self.dimensions.height` をコンテンツ全体の高さに設定する
     fn layout_block_children(&mut self) {
+        let style = &self.get_style_node().computed;
         let d = &mut self.dimensions;
         for child in &mut self.children {
-            child.layout(*d);
+            match child.box_type {
+                AnonymousBlock => child.layout_inline(*d, style),
+                // 外側のマーカーは流れに含めない
+                Marker(..) => {
+                    child.layout_marker(*d);
+                    continue;
+                }
+                _ => child.layout(*d),
+            }
             // 各子が前の子の下にレイアウトされるように高さを増加させる
             d.content.height += child.dimensions.margin_box().height;
         }
@@ -243,8 +295,13 @@ impl<'a> LayoutBox<'a> {
     fn calculate_block_height(&mut self) {
         // 高さが明示的な長さに設定されている場合は、その長さを使用する
         // それ以外の場合は、`layout_block_children`で設定された値を保持する
-        if let Some(Value::Length(h, Unit::Px)) = self.get_style_node().value("height") {
-            self.dimensions.content.height = h;
+        // コンテナブロックの高さは内容に依存するので、パーセンテージは`auto`として扱う
+        if let LengthPercentageOrAuto::LengthPercentage(ref height) =
+            self.get_style_node().computed.height
+        {
+            if !height.has_percentage() {
+                self.dimensions.content.height = height.resolve(0.0);
+            }
         }
     }
 }
@@ -253,30 +310,221 @@ pub fn layout_tree<'a>(
     node: &'a StyledNode<'a>,
     mut containing_block: Dimensions,
 ) -> LayoutBox<'a> {
+    // 高さは子を並べるたびに増えるので0から始める
     containing_block.content.height = 0.0;
 
-    let mut root_box = build_layout_tree(node);
-    root_box.layout(containing_block);
-    root_box
+    let mut root_box = BoxTreeBuilder::default().build_layout_tree(node);
+    if let BlockNode(_) = root_box.box_type {
+        root_box.layout(containing_block);
+        root_box
+    } else {
+        // インラインのルートは匿名ブロックに入れて行に並べる
+        let mut anonymous = LayoutBox::new(AnonymousBlock);
+        anonymous.children.push(root_box);
+        anonymous.layout_inline(containing_block, &node.computed);
+        anonymous
+    }
+}
+
+/// 開き引用符と閉じ引用符。入れ子の深さがこれを超えたら最後の組を使う
+const QUOTES: [(&str, &str); 2] = [("\u{201c}", "\u{201d}"), ("\u{2018}", "\u{2019}")];
+
+/// ボックスツリーを文書順に作る間の状態
+#[derive(Default)]
+struct BoxTreeBuilder {
+    /// 引用符の入れ子の深さ
+    quote_depth: usize,
+    /// スコープにあるカウンタ (外側から順)
+    counters: Vec<Counter>,
+    /// 処理中の要素の深さ。ルート要素が0
+    depth: usize,
+}
+
+/// https://www.w3.org/TR/css-lists-3/#creating-a-counter
+struct Counter {
+    name: String,
+    value: i32,
+    /// カウンタを作った要素の深さ。その要素の親が終わるとスコープを外れる
+    depth: usize,
 }
 
-fn build_layout_tree<'a>(style_node: &'a StyledNode<'a>) -> LayoutBox<'a> {
-    let mut root = LayoutBox::new(match style_node.display() {
-        Display::Block => BlockNode(style_node),
-        Display::Inline => InlineNode(style_node),
-        Display::None => panic!("Root node has not display none."),
-    });
-    for child in &style_node.children {
-        match child.display() {
-            Display::Block => root.children.push(build_layout_tree(child)),
-            Display::Inline => root
+impl BoxTreeBuilder {
+    fn build_layout_tree<'a>(&mut self, style_node: &'a StyledNode<'a>) -> LayoutBox<'a> {
+        let mut root = LayoutBox::new(match style_node.display() {
+            Display::Block | Display::ListItem => BlockNode(style_node),
+            Display::Inline => match style_node.node.node_type {
+                NodeType::Text(ref text) => Text(style_node, text.clone()),
+                NodeType::Element(_) => InlineNode(style_node),
+            },
+            Display::None => panic!("Root node has not display none."),
+        });
+        let list_item = style_node.display() == Display::ListItem;
+        self.update_counters(&style_node.computed, list_item);
+        if style_node.pseudo_element.is_some() {
+            let text = self.generate_text(style_node);
+            root.get_inline_container()
+                .children
+                .push(LayoutBox::new(GeneratedText(style_node, text)));
+            return root;
+        }
+
+        if list_item {
+            self.add_marker(&mut root, style_node);
+        }
+
+        let before = style_node.pseudo_element(PseudoElement::Before);
+        let after = style_node.pseudo_element(PseudoElement::After);
+        self.depth += 1;
+        for child in before.into_iter().chain(&style_node.children).chain(after) {
+            match child.display() {
+                Display::Block | Display::ListItem => {
+                    root.children.push(self.build_layout_tree(child))
+                }
+                Display::Inline => {
+                    let child = self.build_layout_tree(child);
+                    root.get_inline_container().children.push(child)
+                }
+                Display::None => {}
+            }
+        }
+        self.depth -= 1;
+        // 子と擬似要素が作ったカウンタはここでスコープを外れる
+        let depth = self.depth;
+        self.counters.retain(|counter| counter.depth <= depth);
+        root
+    }
+
+    /// 要素の `counter-reset` と `counter-increment` をこの順に適用する
+    ///
+    /// リスト項目は `list-item` カウンタを暗黙に1増やす
+    /// https://www.w3.org/TR/css-lists-3/#counter-properties
+    fn update_counters(&mut self, style: &ComputedStyle, list_item: bool) {
+        let depth = self.depth;
+        for (name, value) in &style.counter_reset {
+            // 兄弟が作った同じ名前のカウンタは置き換える
+            match self
+                .counters
+                .iter_mut()
+                .find(|counter| counter.depth == depth && counter.name == *name)
+            {
+                Some(counter) => counter.value = *value,
+                None => self.counters.push(Counter {
+                    name: name.clone(),
+                    value: *value,
+                    depth,
+                }),
+            }
+        }
+
+        let implicit = list_item
+            && !style
+                .counter_increment
+                .iter()
+                .any(|(name, _)| name == "list-item");
+        let increments = style
+            .counter_increment
+            .iter()
+            .cloned()
+            .chain(implicit.then(|| ("list-item".to_string(), 1)));
+        for (name, value) in increments {
+            match self
+                .counters
+                .iter_mut()
+                .rev()
+                .find(|counter| counter.name == name)
+            {
+                Some(counter) => counter.value = counter.value.saturating_add(value),
+                // スコープにないカウンタは0で作ってから増やす
+                None => self.counters.push(Counter { name, value, depth }),
+            }
+        }
+    }
+
+    /// リスト項目 `style_node` のマーカーのボックスを加える
+    ///
+    /// https://www.w3.org/TR/css-lists-3/#marker-pseudo
+    fn add_marker<'a>(&self, root: &mut LayoutBox<'a>, style_node: &'a StyledNode<'a>) {
+        let style = &style_node.computed;
+        let text = match style.list_style_type {
+            ListStyleType::CounterStyle(ref name) => {
+                let value = self
+                    .counters
+                    .iter()
+                    .rev()
+                    .find(|counter| counter.name == "list-item")
+                    .map_or(0, |counter| counter.value);
+                counter_style::marker(value, name)
+            }
+            ListStyleType::String(ref s) => s.clone(),
+            ListStyleType::None => return,
+        };
+        match style.list_style_position {
+            ListStylePosition::Outside => {
+                root.children.push(LayoutBox::new(Marker(style_node, text)))
+            }
+            ListStylePosition::Inside => root
                 .get_inline_container()
                 .children
-                .push(build_layout_tree(child)),
-            Display::None => {}
+                .push(LayoutBox::new(GeneratedText(style_node, text))),
+        }
+    }
+
+    /// 擬似要素 `style_node` の `content` を文字列にする
+    ///
+    /// https://www.w3.org/TR/css-content-3/#content-property
+    fn generate_text(&mut self, style_node: &StyledNode) -> String {
+        let NodeType::Element(ref elem) = style_node.node.node_type else {
+            unreachable!("pseudo-elements are generated by elements");
+        };
+        let mut text = String::new();
+        for item in &style_node.computed.content {
+            match *item {
+                ContentItem::String(ref s) => text.push_str(s),
+                ContentItem::Function(ContentFunction::Attr(ref name)) => {
+                    text.push_str(elem.attributes.get(name).map_or("", String::as_str))
+                }
+                ContentItem::Function(ContentFunction::Counter {
+                    ref name,
+                    ref separator,
+                    ref style,
+                }) => {
+                    let mut values: Vec<i32> = self
+                        .counters
+                        .iter()
+                        .filter(|counter| counter.name == *name)
+                        .map(|counter| counter.value)
+                        .collect();
+                    // 存在しないカウンタは0として作られる
+                    if values.is_empty() {
+                        values.push(0);
+                    }
+                    // `counter()` は最も内側のカウンタだけ
+                    if separator.is_none() {
+                        values.drain(..values.len() - 1);
+                    }
+                    let values: Vec<String> = values
+                        .into_iter()
+                        .map(|value| counter_style::represent(value, style))
+                        .collect();
+                    text.push_str(&values.join(separator.as_deref().unwrap_or("")));
+                }
+                ContentItem::OpenQuote => {
+                    text.push_str(QUOTES[self.quote_depth.min(QUOTES.len() - 1)].0);
+                    self.quote_depth += 1;
+                }
+                ContentItem::CloseQuote => {
+                    // 対応する開き引用符がなければ何も表示しない
+                    if self.quote_depth > 0 {
+                        self.quote_depth -= 1;
+                        text.push_str(QUOTES[self.quote_depth.min(QUOTES.len() - 1)].1);
+                    }
+                }
+                ContentItem::NoOpenQuote => self.quote_depth += 1,
+                ContentItem::NoCloseQuote => self.quote_depth = self.quote_depth.saturating_sub(1),
+            }
         }
+        text
     }
-    root
 }
 
 impl Rect {
@@ -288,6 +536,11 @@ impl Rect {
             height: self.height + edge.top + edge.bottom,
         }
     }
+
+    /// 右端と下端は含まない
+    pub fn contains(self, x: f32, y: f32) -> bool {
+        self.x <= x && x < self.x + self.width && self.y <= y && y < self.y + self.height
+    }
 }
 
 impl Dimensions {
@@ -311,3 +564,216 @@ where
 {
     iter.fold(0., |a, b| a + b)
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        css::{self, media::Device},
+        html,
+        style::style_tree,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_relative_lengths() {
+        let root_node = html::parse(r#"<div class="a"><div class="b"></div></div>"#.to_string());
+        let stylesheet = css::parse(
+            r#"
+            div { display: block; }
+            .a { font-size: 20px; width: 50%; }
+            .b { width: 10em; margin-left: 5vw; height: 2rem; padding-top: 10%; }
+            "#
+            .to_string(),
+        );
+        let stylesheets = [stylesheet];
+        let style_root = style_tree(&root_node, &stylesheets, &Device::new(800.0, 600.0));
+
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = 800.0;
+        viewport.content.height = 600.0;
+        let layout_root = layout_tree(&style_root, viewport);
+
+        assert_eq!(layout_root.dimensions.content.width, 400.0);
+
+        let inner = &layout_root.children[0].dimensions;
+        assert_eq!(inner.content.width, 200.0);
+        assert_eq!(inner.margin.left, 40.0);
+        assert_eq!(inner.content.height, 40.0);
+        assert_eq!(inner.padding.top, 40.0);
+    }
+
+    #[test]
+    fn test_math_functions() {
+        let root_node = html::parse(r#"<div class="a"><div class="b"></div></div>"#.to_string());
+        let stylesheet = css::parse(
+            r#"
+            .a { width: calc(100% - 2 * 50px); height: calc(5em + 10%); }
+            .b { width: clamp(100px, 50%, 300px); margin-left: min(10%, 20px); height: max(1rem, 5px); }
+            "#
+            .to_string(),
+        );
+        let stylesheets = [stylesheet];
+        let style_root = style_tree(&root_node, &stylesheets, &Device::new(800.0, 600.0));
+
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = 800.0;
+        viewport.content.height = 600.0;
+        let layout_root = layout_tree(&style_root, viewport);
+
+        let outer = &layout_root.dimensions;
+        assert_eq!(outer.content.width, 700.0);
+        // パーセンテージを含む高さは `auto` として扱う
+        assert_eq!(outer.content.height, 16.0);
+
+        let inner = &layout_root.children[0].dimensions;
+        assert_eq!(inner.content.width, 300.0);
+        assert_eq!(inner.margin.left, 20.0);
+        assert_eq!(inner.content.height, 16.0);
+    }
+
+    #[test]
+    fn test_generated_content() {
+        let root_node =
+            html::parse(r#"<div><q title="t"><q></q></q><p class="x"></p></div>"#.to_string());
+        let stylesheet = css::parse(
+            r#"
+            div, p { display: block; }
+            q { color: red; }
+            q::before { content: open-quote attr(title) attr(lang) ":" counter(n); }
+            q::after { content: close-quote; }
+            .x::before { content: none; }
+            p::after { content: ""; display: block; height: 5px; }
+            "#
+            .to_string(),
+        );
+        let stylesheets = [stylesheet];
+        let style_root = style_tree(&root_node, &stylesheets, &Device::new(800.0, 600.0));
+        let q = &style_root.children[0];
+        let before = q.pseudo_element(PseudoElement::Before).unwrap();
+        assert_eq!(before.computed.color, q.computed.color);
+        assert!(style_root.children[1]
+            .pseudo_element(PseudoElement::Before)
+            .is_none());
+
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = 800.0;
+        let layout_root = layout_tree(&style_root, viewport);
+
+        fn generated_texts(layout_box: &LayoutBox, texts: &mut Vec<String>) {
+            if let GeneratedText(_, ref text) = layout_box.box_type {
+                texts.push(text.clone());
+            }
+            for child in &layout_box.children {
+                generated_texts(child, texts);
+            }
+        }
+        let mut texts = Vec::new();
+        generated_texts(&layout_root, &mut texts);
+        assert_eq!(
+            texts,
+            vec!["\u{201c}t:0", "\u{2018}:0", "\u{2019}", "\u{201d}", ""]
+        );
+        // `::after` のブロックボックスは要素の内容の最後に置かれる
+        assert_eq!(layout_root.children[1].dimensions.content.height, 5.0);
+    }
+
+    #[test]
+    fn test_list_markers() {
+        let root_node = html::parse(
+            r#"<ol><li></li><li class="x"><ul><li></li></ul><ol><li></li><li></li></ol></li></ol>"#
+                .to_string(),
+        );
+        let stylesheet = css::parse(
+            r#"
+            .x { list-style: inside upper-roman; }
+            li li::before { content: counters(list-item, ".") " " counter(list-item, lower-alpha); }
+            "#
+            .to_string(),
+        );
+        let stylesheets = [stylesheet];
+        let style_root = style_tree(&root_node, &stylesheets, &Device::new(800.0, 600.0));
+
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = 800.0;
+        let layout_root = layout_tree(&style_root, viewport);
+
+        fn markers(layout_box: &LayoutBox, texts: &mut Vec<String>) {
+            match layout_box.box_type {
+                Marker(_, ref text) => texts.push(format!("outside {}", text)),
+                GeneratedText(_, ref text) => texts.push(text.clone()),
+                _ => {}
+            }
+            for child in &layout_box.children {
+                markers(child, texts);
+            }
+        }
+        let mut texts = Vec::new();
+        markers(&layout_root, &mut texts);
+        assert_eq!(
+            texts,
+            vec![
+                "outside 1. ",
+                "II. ",
+                // `list-style-position` は継承する
+                "\u{25e6} ",
+                "2.1 a",
+                // 兄弟の `ol` が `list-item` カウンタを作り直す
+                "1. ",
+                "2.1 a",
+                "2. ",
+                "2.2 b",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_hit_test() {
+        let root_node = html::parse(
+            r#"<div class="a"><div class="b"></div><div class="c"></div></div>"#.to_string(),
+        );
+        let stylesheet = css::parse(
+            r#"
+            div { display: block; }
+            .a { padding: 10px; }
+            .b { height: 20px; }
+            .c { height: 30px; margin-left: 100px; }
+            "#
+            .to_string(),
+        );
+        let stylesheets = [stylesheet];
+        let style_root = style_tree(&root_node, &stylesheets, &Device::new(800.0, 600.0));
+
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = 800.0;
+        viewport.content.height = 600.0;
+        let layout_root = layout_tree(&style_root, viewport);
+
+        let hit = |x, y| {
+            layout_root
+                .hit_test(x, y)
+                .map(|node| root_node.path_to(node).unwrap())
+        };
+        assert_eq!(hit(5.0, 5.0), Some(vec![]));
+        assert_eq!(hit(15.0, 15.0), Some(vec![0]));
+        assert_eq!(hit(150.0, 40.0), Some(vec![1]));
+        assert_eq!(hit(50.0, 40.0), Some(vec![]));
+        assert_eq!(hit(50.0, 100.0), None);
+
+        let regions = layout_root.hit_regions();
+        for (x, y) in [
+            (5.0, 5.0),
+            (15.0, 15.0),
+            (150.0, 40.0),
+            (50.0, 40.0),
+            (50.0, 100.0),
+        ] {
+            let node = regions
+                .iter()
+                .rev()
+                .find(|(rect, _)| rect.contains(x, y))
+                .map(|&(_, node)| node);
+            assert_eq!(node.map(|node| root_node.path_to(node).unwrap()), hit(x, y));
+        }
+    }
+}
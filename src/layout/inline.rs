@@ -0,0 +1,894 @@
+//! インライン整形文脈
+//!
+//! 匿名ブロックの中のインラインレベルの内容を、コンテナブロックの幅に収まる行ボックスに分けて並べる
+//!
+//! https://www.w3.org/TR/css-inline-3/#model
+
+use std::mem;
+
+use crate::style::{
+    computed::{
+        ComputedStyle, Hyphens, LineBreak, LineHeight, OverflowWrap, TextAlign, WhiteSpace,
+    },
+    StyledNode,
+};
+
+use super::{
+    line_break::{self, class, Break, Class},
+    AnonymousBlock, BlockNode, Dimensions, GeneratedText, InlineNode, LayoutBox, LineBox, Marker,
+    Text,
+};
+
+/// 行に並べる単位
+enum Item<'a> {
+    /// インラインボックスの始まり。左のマージン、ボーダー、パディングの幅を持つ
+    Open(&'a StyledNode<'a>),
+    /// インラインボックスの終わり。右のマージン、ボーダー、パディングの幅を持つ
+    Close(&'a StyledNode<'a>),
+    Text(TextItem<'a>),
+    /// 保存された改行
+    ForcedBreak,
+    /// インラインボックスの中のブロック。その前後で行を改める
+    Block(LayoutBox<'a>),
+}
+
+/// 文字列の断片。途中では折り返さない
+#[derive(Clone)]
+struct TextItem<'a> {
+    style_node: &'a StyledNode<'a>,
+    /// `GeneratedText` から来たかどうか。そうでなければテキストノード
+    generated: bool,
+    text: String,
+    /// 行頭と行末の空白を取り除くかどうか
+    collapsible: bool,
+    /// 行の幅に合わせて折り返すかどうか
+    wraps: bool,
+    /// 直後で折り返せるかどうか
+    break_after: bool,
+    /// ソフトハイフンで終わるかどうか。行末に来たらハイフンを表示する
+    hyphen: bool,
+    /// 行に収まらないときに文字の間で折り返すかどうか
+    overflow_wrap: bool,
+}
+
+/// 1つの行ボックスに入る項目
+struct Line<'a> {
+    items: Vec<Item<'a>>,
+    /// 保存された改行で終わる行は、内容がなくても高さを持つ
+    forced_break: bool,
+}
+
+impl<'a> LayoutBox<'a> {
+    /// 匿名ブロックの子を行ボックスに並べ、その行の高さの合計を匿名ブロックの高さにする
+    ///
+    /// `style` は匿名ブロックを含むブロックの計算値で、行の揃え方と行の最小の高さを決める
+    pub(super) fn layout_inline(&mut self, containing_block: Dimensions, style: &ComputedStyle) {
+        let d = &mut self.dimensions;
+        d.content.x = containing_block.content.x;
+        d.content.y = containing_block.content.y + containing_block.content.height;
+        d.content.width = containing_block.content.width;
+        d.content.height = 0.0;
+
+        let mut flattener = Flattener {
+            items: Vec::new(),
+            after_space: true,
+        };
+        for child in mem::take(&mut self.children) {
+            flattener.flatten(child);
+        }
+        let items = split_at_opportunities(flattener.items);
+        let mut breaker = LineBreaker {
+            available: d.content.width,
+            lines: Vec::new(),
+            line: Vec::new(),
+            line_width: 0.0,
+            chunk: Vec::new(),
+            chunk_width: 0.0,
+            break_opportunity: false,
+        };
+        for item in items {
+            breaker.push(item);
+        }
+
+        // 複数の行にまたがるインラインボックス
+        let mut open = Vec::new();
+        for line in breaker.finish() {
+            let child = self.layout_line(line, &mut open, style);
+            self.dimensions.content.height += child.dimensions.margin_box().height;
+            self.children.push(child);
+        }
+    }
+
+    /// これまでの行の下に1行を置く
+    ///
+    /// `open` は前の行から続くインラインボックスで、この行の終わりでも閉じていないものを残す
+    fn layout_line(
+        &self,
+        mut line: Line<'a>,
+        open: &mut Vec<&'a StyledNode<'a>>,
+        style: &ComputedStyle,
+    ) -> LayoutBox<'a> {
+        let d = self.dimensions;
+        if let [Item::Block(_)] = line.items[..] {
+            let Some(Item::Block(mut block)) = line.items.pop() else {
+                unreachable!();
+            };
+            block.layout(d);
+            return block;
+        }
+
+        trim_line(&mut line.items);
+        let base = d.content.width;
+        let width: f32 = line.items.iter().map(|item| item.width(base)).sum();
+        let free = (d.content.width - width).max(0.0);
+        let mut x = d.content.x
+            + match style.text_align {
+                TextAlign::End | TextAlign::Right => free,
+                TextAlign::Center => free / 2.0,
+                // 両端揃えは未実装
+                TextAlign::Start | TextAlign::Left | TextAlign::Justify => 0.0,
+            };
+
+        let mut line_box = LayoutBox::new(LineBox);
+        // 開いているインラインボックスの断片。前の行から続く断片は左の辺を持たない
+        let mut stack: Vec<LayoutBox<'a>> = open
+            .iter()
+            .map(|&node| {
+                let mut fragment = LayoutBox::new(InlineNode(node));
+                fragment.dimensions.content.x = x;
+                fragment
+            })
+            .collect();
+        for item in line.items {
+            match item {
+                Item::Open(node) => {
+                    let mut fragment = LayoutBox::new(InlineNode(node));
+                    let f = &mut fragment.dimensions;
+                    let style = &node.computed;
+                    f.margin.left = style.margin.left.resolve(base).unwrap_or(0.0);
+                    f.border.left = style.border_width.left;
+                    f.padding.left = style.padding.left.resolve(base);
+                    x += f.margin.left + f.border.left + f.padding.left;
+                    f.content.x = x;
+                    stack.push(fragment);
+                    open.push(node);
+                }
+                Item::Close(node) => {
+                    let mut fragment = stack.pop().unwrap();
+                    let f = &mut fragment.dimensions;
+                    let style = &node.computed;
+                    f.content.width = x - f.content.x;
+                    f.padding.right = style.padding.right.resolve(base);
+                    f.border.right = style.border_width.right;
+                    f.margin.right = style.margin.right.resolve(base).unwrap_or(0.0);
+                    x += f.padding.right + f.border.right + f.margin.right;
+                    stack
+                        .last_mut()
+                        .unwrap_or(&mut line_box)
+                        .children
+                        .push(fragment);
+                    open.pop();
+                }
+                Item::Text(item) => {
+                    // 空白だけだったテキストノードは何も生成しない
+                    if item.text.is_empty() && !item.generated {
+                        continue;
+                    }
+                    let width = text_width(&item.text, &item.style_node.computed);
+                    let parent = stack.last_mut().unwrap_or(&mut line_box);
+                    // 同じボックスから来た隣り合う断片はまとめる
+                    match parent.children.last_mut() {
+                        Some(last) if last.continues_text(&item) => {
+                            if let Text(_, ref mut text) | GeneratedText(_, ref mut text) =
+                                last.box_type
+                            {
+                                text.push_str(&item.text);
+                            }
+                            last.dimensions.content.width += width;
+                        }
+                        _ => {
+                            let mut leaf = LayoutBox::new(if item.generated {
+                                GeneratedText(item.style_node, item.text)
+                            } else {
+                                Text(item.style_node, item.text)
+                            });
+                            leaf.dimensions.content.x = x;
+                            leaf.dimensions.content.width = width;
+                            parent.children.push(leaf);
+                        }
+                    }
+                    x += width;
+                }
+                Item::ForcedBreak | Item::Block(_) => unreachable!("handled by the line breaker"),
+            }
+        }
+        // 次の行に続くインラインボックスは右の辺を持たない
+        while let Some(mut fragment) = stack.pop() {
+            fragment.dimensions.content.width = x - fragment.dimensions.content.x;
+            stack
+                .last_mut()
+                .unwrap_or(&mut line_box)
+                .children
+                .push(fragment);
+        }
+
+        // 行の高さは、ベースラインに揃えた各ボックスの行の高さを含む大きさ
+        let (mut above, mut below) = vertical_extent(style);
+        let mut has_content = line.forced_break;
+        for child in &line_box.children {
+            child.measure_line(&mut above, &mut below, &mut has_content);
+        }
+        // 内容のない行は高さを持たない
+        // https://www.w3.org/TR/css-inline-3/#invisible-line-boxes
+        let height = if has_content { above + below } else { 0.0 };
+        let y = d.content.y + d.content.height;
+        for child in &mut line_box.children {
+            child.align_to_baseline(y + above);
+        }
+        line_box.dimensions.content.x = d.content.x;
+        line_box.dimensions.content.y = y;
+        line_box.dimensions.content.width = d.content.width;
+        line_box.dimensions.content.height = height;
+        line_box
+    }
+
+    fn continues_text(&self, item: &TextItem) -> bool {
+        match self.box_type {
+            Text(node, _) => !item.generated && std::ptr::eq(node, item.style_node),
+            GeneratedText(node, _) => item.generated && std::ptr::eq(node, item.style_node),
+            _ => false,
+        }
+    }
+
+    /// 行の中のボックスがベースラインの上下に必要とする高さを `above` と `below` に含める
+    fn measure_line(&self, above: &mut f32, below: &mut f32, has_content: &mut bool) {
+        let style = &self.get_style_node().computed;
+        let (a, b) = vertical_extent(style);
+        *above = above.max(a);
+        *below = below.max(b);
+        match self.box_type {
+            Text(_, ref text) | GeneratedText(_, ref text) => *has_content |= !text.is_empty(),
+            _ => {
+                let d = &self.dimensions;
+                let edges = d.margin.left + d.border.left + d.padding.left;
+                *has_content |= edges + d.padding.right + d.border.right + d.margin.right != 0.0;
+            }
+        }
+        for child in &self.children {
+            child.measure_line(above, below, has_content);
+        }
+    }
+
+    /// 内容領域をフォントのアセントからディセントまでにして、ベースラインを `baseline` に揃える
+    ///
+    /// インラインボックスの上下のパディングとボーダーは行の高さに影響しない
+    fn align_to_baseline(&mut self, baseline: f32) {
+        let style = &self.get_style_node().computed;
+        let (ascent, descent) = font_metrics(style);
+        let d = &mut self.dimensions;
+        d.content.y = baseline - ascent;
+        d.content.height = ascent + descent;
+        if let InlineNode(_) = self.box_type {
+            d.padding.top = style.padding.top.resolve(0.0);
+            d.padding.bottom = style.padding.bottom.resolve(0.0);
+            d.border.top = style.border_width.top;
+            d.border.bottom = style.border_width.bottom;
+        }
+        for child in &mut self.children {
+            child.align_to_baseline(baseline);
+        }
+    }
+
+    /// 外側のマーカーを、リスト項目 `list_item` の最初の行の左に置く
+    pub(super) fn layout_marker(&mut self, list_item: Dimensions) {
+        let Marker(node, ref text) = self.box_type else {
+            unreachable!();
+        };
+        let style = &node.computed;
+        let (ascent, descent) = font_metrics(style);
+        let (above, _) = vertical_extent(style);
+        let width = text_width(text, style);
+        let d = &mut self.dimensions;
+        d.content.x = list_item.border_box().x - width;
+        d.content.y = list_item.content.y + above - ascent;
+        d.content.width = width;
+        d.content.height = ascent + descent;
+    }
+}
+
+impl Item<'_> {
+    /// 行の中で占める幅。`base` はパーセンテージの基準
+    fn width(&self, base: f32) -> f32 {
+        match *self {
+            Item::Open(node) => {
+                let style = &node.computed;
+                style.margin.left.resolve(base).unwrap_or(0.0)
+                    + style.border_width.left
+                    + style.padding.left.resolve(base)
+            }
+            Item::Close(node) => {
+                let style = &node.computed;
+                style.padding.right.resolve(base)
+                    + style.border_width.right
+                    + style.margin.right.resolve(base).unwrap_or(0.0)
+            }
+            Item::Text(ref item) => text_width(&item.text, &item.style_node.computed),
+            Item::ForcedBreak | Item::Block(_) => 0.0,
+        }
+    }
+}
+
+/// インラインレベルのボックスの木を項目の列にする
+///
+/// `white-space` に従って空白を処理する
+/// https://www.w3.org/TR/css-text-3/#white-space-processing
+struct Flattener<'a> {
+    items: Vec<Item<'a>>,
+    /// 直前の文字が折り畳める空白か行頭かどうか。続く空白は取り除く
+    after_space: bool,
+}
+
+impl<'a> Flattener<'a> {
+    fn flatten(&mut self, layout_box: LayoutBox<'a>) {
+        match layout_box.box_type {
+            InlineNode(node) => {
+                self.items.push(Item::Open(node));
+                for child in layout_box.children {
+                    self.flatten(child);
+                }
+                self.items.push(Item::Close(node));
+            }
+            Text(node, ref text) => self.push_text(node, false, text, node.computed.white_space),
+            GeneratedText(node, ref text) => {
+                // リスト項目のスタイルを持つのはマーカーで、空白を保存する
+                let white_space = match node.pseudo_element {
+                    Some(_) => node.computed.white_space,
+                    None => WhiteSpace::Pre,
+                };
+                self.push_text(node, true, text, white_space)
+            }
+            BlockNode(_) | AnonymousBlock | LineBox | Marker(..) => {
+                self.after_space = true;
+                self.items.push(Item::Block(layout_box));
+            }
+        }
+    }
+
+    /// 空白を処理した文字列を加える。保存された改行では分ける
+    fn push_text(
+        &mut self,
+        style_node: &'a StyledNode<'a>,
+        generated: bool,
+        text: &str,
+        white_space: WhiteSpace,
+    ) {
+        let style = &style_node.computed;
+        let collapsible = white_space.collapses_spaces();
+        let wraps = white_space.wraps();
+        let text_item = |text: String| {
+            Item::Text(TextItem {
+                style_node,
+                generated,
+                text,
+                collapsible,
+                wraps,
+                break_after: false,
+                hyphen: false,
+                overflow_wrap: style.overflow_wrap != OverflowWrap::Normal,
+            })
+        };
+        // 生成した空の文字列もボックスを作る
+        let mut pushed = false;
+        let mut current = String::new();
+        for c in text.chars() {
+            match c {
+                '\n' if white_space.preserves_newlines() => {
+                    // 改行の前の折り畳める空白は取り除く
+                    if collapsible {
+                        current.truncate(current.trim_end_matches(' ').len());
+                    }
+                    self.items.push(text_item(mem::take(&mut current)));
+                    self.items.push(Item::ForcedBreak);
+                    pushed = true;
+                    self.after_space = true;
+                }
+                ' ' | '\t' | '\n' | '\r' if collapsible => {
+                    if !self.after_space {
+                        current.push(' ');
+                        self.after_space = true;
+                    }
+                }
+                // `hyphens: none` ではソフトハイフンを無視する
+                '\u{ad}' if style.hyphens == Hyphens::None => {}
+                _ => {
+                    // タブは8個の空白として扱う
+                    if c == '\t' {
+                        current.push_str("        ");
+                    } else {
+                        current.push(c);
+                    }
+                    self.after_space = false;
+                }
+            }
+        }
+        if !current.is_empty() || (generated && !pushed) {
+            self.items.push(text_item(current));
+        }
+    }
+}
+
+/// 文字列を折り返しの機会で分ける
+///
+/// 折り返しの機会は、ブロックと保存された改行の間の文字列全体で、インラインボックスの境界を越えて決める
+/// https://www.w3.org/TR/css-text-3/#line-breaking
+fn split_at_opportunities(items: Vec<Item>) -> Vec<Item> {
+    let mut result = Vec::new();
+    let mut run = Vec::new();
+    for item in items {
+        match item {
+            Item::ForcedBreak | Item::Block(_) => {
+                split_run(mem::take(&mut run), &mut result);
+                result.push(item);
+            }
+            _ => run.push(item),
+        }
+    }
+    split_run(run, &mut result);
+    result
+}
+
+fn split_run<'a>(run: Vec<Item<'a>>, result: &mut Vec<Item<'a>>) {
+    // 各文字のクラスと、その文字の後で折り返すことを許す文字列
+    let mut chars = Vec::new();
+    let mut owners = Vec::new();
+    for item in &run {
+        if let Item::Text(ref text) = *item {
+            let style = &text.style_node.computed;
+            for c in text.text.chars() {
+                let class = line_break::tailor(c, class(c), style.word_break, style.line_break);
+                chars.push((c, class));
+                owners.push(text);
+            }
+        }
+    }
+    let mut opportunities: Vec<bool> = line_break::opportunities(&chars)
+        .into_iter()
+        .enumerate()
+        .map(|(i, opportunity)| {
+            if i == 0 || !owners[i - 1].wraps {
+                return false;
+            }
+            // 結合文字の前を除いて、どこでも折り返す
+            let style = &owners[i - 1].style_node.computed;
+            opportunity != Break::Prohibited
+                || style.line_break == LineBreak::Anywhere
+                    && !matches!(class(chars[i].0), Class::CM | Class::ZWJ)
+        })
+        .collect();
+    // 続く文字列の前では次の項目の境界として扱う
+    opportunities.push(false);
+
+    let mut index = 0;
+    for item in run {
+        let Item::Text(text) = item else {
+            result.push(item);
+            continue;
+        };
+        if text.text.is_empty() {
+            result.push(Item::Text(text));
+            continue;
+        }
+        let mut pieces = vec![String::new()];
+        let mut breaks = Vec::new();
+        for c in text.text.chars() {
+            if opportunities[index] && !pieces.last().unwrap().is_empty() {
+                pieces.push(String::new());
+            }
+            pieces.last_mut().unwrap().push(c);
+            index += 1;
+            breaks.push(opportunities[index]);
+        }
+        let mut breaks = breaks.into_iter();
+        for piece in pieces {
+            // 断片の最後の文字の後で折り返せるかどうか
+            let break_after = breaks.by_ref().nth(piece.chars().count() - 1).unwrap();
+            let hyphen = break_after && piece.ends_with('\u{ad}');
+            result.push(Item::Text(TextItem {
+                text: piece.replace('\u{ad}', ""),
+                break_after,
+                hyphen,
+                ..text.clone()
+            }));
+        }
+    }
+}
+
+/// 項目を行に分ける
+///
+/// 折り返しの機会から次の機会までの項目をまとめて、行に収まらなければ次の行に送る
+/// https://www.w3.org/TR/css-text-3/#line-breaking
+struct LineBreaker<'a> {
+    available: f32,
+    lines: Vec<Line<'a>>,
+    line: Vec<Item<'a>>,
+    line_width: f32,
+    /// 最後の折り返しの機会より後の項目
+    chunk: Vec<Item<'a>>,
+    chunk_width: f32,
+    /// 次の項目の前で折り返せるかどうか
+    break_opportunity: bool,
+}
+
+impl<'a> LineBreaker<'a> {
+    fn push(&mut self, item: Item<'a>) {
+        match item {
+            Item::ForcedBreak => {
+                self.commit_chunk();
+                self.finish_line(true);
+                self.break_opportunity = false;
+            }
+            Item::Block(_) => {
+                self.commit_chunk();
+                if !self.line.is_empty() {
+                    self.finish_line(false);
+                }
+                self.lines.push(Line {
+                    items: vec![item],
+                    forced_break: false,
+                });
+                self.break_opportunity = false;
+            }
+            _ => {
+                // インラインボックスの終わりは直前の文字列と同じ行に置く
+                if self.break_opportunity && !matches!(item, Item::Close(_)) {
+                    self.commit_chunk();
+                    self.break_opportunity = false;
+                }
+                if let Item::Text(ref text) = item {
+                    self.break_opportunity = text.break_after;
+                }
+                self.chunk_width += item.width(self.available);
+                self.chunk.push(item);
+            }
+        }
+    }
+
+    fn commit_chunk(&mut self) {
+        let end = line_end_width(&self.chunk);
+        if !self.line.is_empty() && self.line_width + self.chunk_width + end > self.available {
+            self.finish_line(false);
+        }
+        let overflow_wrap = self
+            .chunk
+            .iter()
+            .any(|item| matches!(*item, Item::Text(ref text) if text.overflow_wrap));
+        if overflow_wrap && self.line_width + self.chunk_width + end > self.available {
+            self.chunk_width = 0.0;
+            self.wrap_anywhere();
+            return;
+        }
+        self.line_width += self.chunk_width;
+        self.chunk_width = 0.0;
+        self.line.append(&mut self.chunk);
+    }
+
+    /// 次の行にも収まらない断片を、`overflow-wrap` に従って文字の間で折り返す
+    fn wrap_anywhere(&mut self) {
+        for item in mem::take(&mut self.chunk) {
+            let Item::Text(text) = item else {
+                self.place(item);
+                continue;
+            };
+            if !text.overflow_wrap {
+                self.place(Item::Text(text));
+                continue;
+            }
+            // 結合文字と空白は前の文字と分けない
+            let mut pieces: Vec<String> = Vec::new();
+            for c in text.text.chars() {
+                match pieces.last_mut() {
+                    Some(piece) if c == ' ' || matches!(class(c), Class::CM | Class::ZWJ) => {
+                        piece.push(c)
+                    }
+                    _ => pieces.push(c.to_string()),
+                }
+            }
+            let count = pieces.len();
+            for (i, piece) in pieces.into_iter().enumerate() {
+                let last = i + 1 == count;
+                self.place(Item::Text(TextItem {
+                    text: piece,
+                    break_after: !last || text.break_after,
+                    hyphen: last && text.hyphen,
+                    ..text.clone()
+                }));
+            }
+        }
+    }
+
+    /// 項目を1つずつ行に加え、文字列が収まらなければ行を改める
+    fn place(&mut self, item: Item<'a>) {
+        let width = item.width(self.available);
+        let end = line_end_width(std::slice::from_ref(&item));
+        if let Item::Text(_) = item {
+            if !self.line.is_empty() && self.line_width + width + end > self.available {
+                self.finish_line(false);
+            }
+        }
+        self.line_width += width;
+        self.line.push(item);
+    }
+
+    fn finish_line(&mut self, forced_break: bool) {
+        self.lines.push(Line {
+            items: mem::take(&mut self.line),
+            forced_break,
+        });
+        self.line_width = 0.0;
+    }
+
+    fn finish(mut self) -> Vec<Line<'a>> {
+        self.commit_chunk();
+        if !self.line.is_empty() {
+            self.finish_line(false);
+        }
+        self.lines
+    }
+}
+
+/// 項目を行末に置いたときに変わる幅
+///
+/// 最後の文字列の末尾の空白ははみ出してもよく、ソフトハイフンで折り返すとハイフンを表示する
+fn line_end_width(items: &[Item]) -> f32 {
+    let last_text = items.iter().rev().find_map(|item| match *item {
+        Item::Text(ref text) => Some(text),
+        _ => None,
+    });
+    match last_text {
+        Some(text) => {
+            let style = &text.style_node.computed;
+            let trimmed = text.text.trim_end_matches(' ');
+            let hyphen = if text.hyphen {
+                text_width(HYPHEN, style)
+            } else {
+                0.0
+            };
+            hyphen - text_width(&text.text[trimmed.len()..], style)
+        }
+        None => 0.0,
+    }
+}
+
+/// ソフトハイフンで折り返した行の末尾に表示する文字
+const HYPHEN: &str = "-";
+
+/// 行頭と行末の折り畳める空白を取り除き、行末のソフトハイフンをハイフンにする
+fn trim_line(items: &mut [Item]) {
+    let mut texts = items.iter_mut().filter_map(|item| match *item {
+        Item::Text(ref mut text) if text.collapsible => Some(text),
+        _ => None,
+    });
+    if let Some(first) = texts.next() {
+        first.text = first.text.trim_start_matches(' ').to_string();
+    }
+    if let Some(last) = items.iter_mut().rev().find_map(|item| match *item {
+        Item::Text(ref mut text) => Some(text),
+        _ => None,
+    }) {
+        if last.collapsible {
+            last.text.truncate(last.text.trim_end_matches(' ').len());
+        }
+        if last.hyphen {
+            last.text.push_str(HYPHEN);
+        }
+    }
+}
+
+/// 文字列の幅
+pub(super) fn text_width(text: &str, style: &ComputedStyle) -> f32 {
+    style.font().text_width(text, style.font_size)
+}
+
+/// フォントのアセントとディセント
+fn font_metrics(style: &ComputedStyle) -> (f32, f32) {
+    let metrics = style.font().metrics(style.font_size);
+    (metrics.ascent, metrics.descent)
+}
+
+/// ボックスがベースラインの上と下に必要とする高さ
+///
+/// フォントの高さの上下に、行の高さとの差の半分ずつを加える
+/// https://www.w3.org/TR/css-inline-3/#inline-height
+fn vertical_extent(style: &ComputedStyle) -> (f32, f32) {
+    let (ascent, descent) = font_metrics(style);
+    let line_height = match style.line_height {
+        LineHeight::Length(px) => px,
+        LineHeight::Normal => normal_line_height(style),
+    };
+    let half_leading = (line_height - (ascent + descent)) / 2.0;
+    (ascent + half_leading, descent + half_leading)
+}
+
+/// `line-height: normal` の高さ。フォントが勧める行間を使う
+fn normal_line_height(style: &ComputedStyle) -> f32 {
+    let metrics = style.font().metrics(style.font_size);
+    metrics.ascent + metrics.descent + metrics.line_gap
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        css::{self, media::Device},
+        html,
+        layout::{layout_tree, Dimensions},
+        style::style_tree,
+    };
+
+    use super::*;
+
+    /// 行ボックスごとに、その中の文字列の断片と位置を集める
+    fn lines(layout_box: &LayoutBox) -> Vec<Vec<(String, f32, f32)>> {
+        fn texts(layout_box: &LayoutBox, out: &mut Vec<(String, f32, f32)>) {
+            if let Text(_, ref text) | GeneratedText(_, ref text) = layout_box.box_type {
+                let content = layout_box.dimensions.content;
+                out.push((text.clone(), content.x, content.y));
+            }
+            for child in &layout_box.children {
+                texts(child, out);
+            }
+        }
+        let mut result = Vec::new();
+        if let LineBox = layout_box.box_type {
+            let mut line = Vec::new();
+            texts(layout_box, &mut line);
+            result.push(line);
+        }
+        for child in &layout_box.children {
+            result.extend(lines(child));
+        }
+        result
+    }
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-3,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_line_breaking() {
+        let root_node = html::parse(
+            r#"<div>aaaa bb <span class="s">cc dd </span>ee <p>x</p></div>"#.to_string(),
+        );
+        let stylesheet = css::parse(
+            r#"
+            div { width: 50px; font-size: 10px; line-height: 20px; text-align: right; }
+            .s { padding-left: 5px; border-right: 1px solid; }
+            "#
+            .to_string(),
+        );
+        let stylesheets = [stylesheet];
+        let style_root = style_tree(&root_node, &stylesheets, &Device::new(800.0, 600.0));
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = 800.0;
+        let layout_root = layout_tree(&style_root, viewport);
+
+        let style = &style_root.computed;
+        let width = |text| text_width(text, style);
+        let (ascent, _) = font_metrics(style);
+        let (above, _) = vertical_extent(style);
+        let top = above - ascent;
+
+        // "dd " は50pxの1行目に収まらない。行末の空白は取り除く
+        let first_line = width("aaaa bb ") + 5.0 + width("cc");
+        let second_line = width("dd ") + 1.0 + width("ee");
+        assert!(first_line + width("dd") > 50.0 && first_line <= 50.0);
+        let expected = [
+            vec![
+                ("aaaa bb ", 50.0 - first_line, top),
+                ("cc", 50.0 - width("cc"), top),
+            ],
+            vec![
+                ("dd ", 50.0 - second_line, 20.0 + top),
+                ("ee", 50.0 - width("ee"), 20.0 + top),
+            ],
+            vec![("x", 50.0 - width("x"), 50.0 + top)],
+        ];
+        let actual = lines(&layout_root);
+        assert_eq!(actual.len(), expected.len());
+        for (actual, expected) in actual.iter().zip(&expected) {
+            assert_eq!(actual.len(), expected.len());
+            for ((text, x, y), &(expected_text, expected_x, expected_y)) in
+                actual.iter().zip(expected)
+            {
+                assert_eq!(text, expected_text);
+                assert_close(*x, expected_x);
+                assert_close(*y, expected_y);
+            }
+        }
+
+        let anonymous = &layout_root.children[0];
+        assert_eq!(anonymous.dimensions.content.height, 40.0);
+        // `span` は2つの断片に分かれ、左の辺は最初の断片に、右の辺は最後の断片にだけ付く
+        let first = anonymous.children[0].children[1].dimensions.border_box();
+        assert_close(first.x, 50.0 - width("cc") - 5.0);
+        assert_close(first.width, width("cc") + 5.0);
+        let last = anonymous.children[1].children[0].dimensions.border_box();
+        assert_close(last.x, 50.0 - second_line);
+        assert_close(last.width, width("dd ") + 1.0);
+        assert_close(last.y, 20.0 + top);
+        assert_eq!(layout_root.dimensions.content.height, 80.0);
+    }
+
+    #[test]
+    fn test_line_break_opportunities() {
+        let root_node = html::parse(
+            "<div>\
+             <p class=\"h\">aaaa co\u{ad}operate</p>\
+             <p class=\"w\">abcdefghij</p>\
+             <p class=\"n\">abcdefghij</p>\
+             <p class=\"j\">日本語の文章。</p>\
+             </div>"
+                .to_string(),
+        );
+        let style = style_tree(&root_node, &[], &Device::new(800.0, 600.0))
+            .computed
+            .clone();
+        let width = |text| text_width(text, &style);
+        let stylesheet = css::parse(format!(
+            ".h {{ width: {}px; }} .w {{ width: 30px; overflow-wrap: anywhere; }} \
+             .n {{ width: 30px; }} .j {{ width: {}px; }}",
+            width("aaaa co-") + 1.0,
+            width("日本語の") + 1.0,
+        ));
+        let stylesheets = [stylesheet];
+        let style_root = style_tree(&root_node, &stylesheets, &Device::new(800.0, 600.0));
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = 800.0;
+        let layout_root = layout_tree(&style_root, viewport);
+
+        let texts: Vec<Vec<String>> = lines(&layout_root)
+            .into_iter()
+            .map(|line| line.into_iter().map(|(text, _, _)| text).collect())
+            .collect();
+        // ソフトハイフンで折り返した行にだけハイフンを表示する
+        assert_eq!(texts[..2], [vec!["aaaa co-"], vec!["operate"]]);
+        // `overflow-wrap: anywhere` の単語は文字の間で折り返し、そうでなければはみ出す
+        let wrapped: Vec<&str> = texts[2..texts.len() - 3]
+            .iter()
+            .map(|line| &*line[0])
+            .collect();
+        assert!(wrapped.len() > 1);
+        assert_eq!(wrapped.concat(), "abcdefghij");
+        assert!(wrapped.iter().all(|&line| width(line) <= 30.0));
+        // 漢字と仮名の間では折り返すが、句点の前では折り返さない
+        assert_eq!(
+            texts[texts.len() - 3..],
+            [vec!["abcdefghij"], vec!["日本語の"], vec!["文章。"]]
+        );
+    }
+
+    #[test]
+    fn test_preserved_white_space() {
+        let root_node = html::parse("<pre>a  b\n\nc  </pre>".to_string());
+        let style_root = style_tree(&root_node, &[], &Device::new(800.0, 600.0));
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = 800.0;
+        let layout_root = layout_tree(&style_root, viewport);
+
+        let texts: Vec<Vec<String>> = lines(&layout_root)
+            .into_iter()
+            .map(|line| line.into_iter().map(|(text, _, _)| text).collect())
+            .collect();
+        assert_eq!(texts, vec![vec!["a  b"], vec![], vec!["c  "]]);
+        // 空の行も高さを持つ
+        let line_height = normal_line_height(&style_root.children[0].computed);
+        assert_close(
+            layout_root.children[0].dimensions.content.height,
+            3.0 * line_height,
+        );
+    }
+}
@@ -0,0 +1,529 @@
+//! Unicode の行分割アルゴリズム
+//!
+//! 文字の行分割クラスの組から、文字の間で折り返せるかどうかを決める
+//! 南アジアの文字 (SA) は辞書を持たないので、単語の間の空白でだけ折り返す
+//!
+//! https://www.unicode.org/reports/tr14/
+
+use crate::style::computed::{LineBreak, WordBreak};
+
+use self::Class::*;
+
+/// 行分割クラス
+///
+/// https://www.unicode.org/reports/tr14/#Table1
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Class {
+    /// 必須の改行
+    BK,
+    CR,
+    LF,
+    NL,
+    SP,
+    /// ゼロ幅スペース
+    ZW,
+    /// ワードジョイナー
+    WJ,
+    /// ノーブレークスペースなどの結合する文字
+    GL,
+    /// 結合文字と制御文字
+    CM,
+    ZWJ,
+    /// 後で折り返せる (ソフトハイフンなど)
+    BA,
+    /// 前で折り返せる
+    BB,
+    /// エムダッシュ
+    B2,
+    HY,
+    /// オブジェクト置換文字
+    CB,
+    /// 閉じ句読点 (読点と句点を含む)
+    CL,
+    /// 閉じ括弧
+    CP,
+    /// 感嘆符と疑問符
+    EX,
+    /// リーダー
+    IN,
+    /// 行頭禁則の文字
+    NS,
+    /// 開き句読点
+    OP,
+    /// 引用符
+    QU,
+    /// 数字の区切り
+    IS,
+    NU,
+    /// 数字の後に付く記号 (`%` など)
+    PO,
+    /// 数字の前に付く記号 (`$` など)
+    PR,
+    /// `/`
+    SY,
+    AL,
+    /// 小書きの仮名と長音符。`line-break` によって NS か ID になる
+    CJ,
+    /// 絵文字の基底
+    EB,
+    /// 絵文字の修飾子
+    EM,
+    /// ハングルの LV 音節
+    H2,
+    /// ハングルの LVT 音節
+    H3,
+    /// ヘブライ文字
+    HL,
+    /// 漢字と仮名など、前後で折り返せる文字
+    ID,
+    JL,
+    JV,
+    JT,
+    /// 地域指示子
+    RI,
+    /// 辞書で分割する文字
+    SA,
+}
+
+/// 文字 `i` の前の境界
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Break {
+    Prohibited,
+    Allowed,
+    Mandatory,
+}
+
+/// 文字の行分割クラス
+///
+/// Unicode の `LineBreak.txt` のうち、よく使われる文字だけを持つ。それ以外は AL
+/// 曖昧な文字 (AI) も AL とする
+pub fn class(c: char) -> Class {
+    match c {
+        '\u{b}' | '\u{c}' | '\u{2028}' | '\u{2029}' => BK,
+        '\r' => CR,
+        '\n' => LF,
+        '\u{85}' => NL,
+        ' ' => SP,
+        '\u{200b}' => ZW,
+        '\u{2060}' | '\u{feff}' => WJ,
+        '\u{a0}' | '\u{202f}' | '\u{2007}' | '\u{2011}' | '\u{180e}' | '\u{f0c}' => GL,
+        '\u{200d}' => ZWJ,
+        '\u{0}'..='\u{1f}' | '\u{7f}'..='\u{9f}' if c != '\t' => CM,
+        '\u{300}'..='\u{36f}'
+        | '\u{483}'..='\u{489}'
+        | '\u{591}'..='\u{5bd}'
+        | '\u{610}'..='\u{61a}'
+        | '\u{64b}'..='\u{65f}'
+        | '\u{670}'
+        | '\u{1ab0}'..='\u{1aff}'
+        | '\u{1dc0}'..='\u{1dff}'
+        | '\u{200c}'
+        | '\u{20d0}'..='\u{20ff}'
+        | '\u{302a}'..='\u{302f}'
+        | '\u{3099}'..='\u{309a}'
+        | '\u{fe00}'..='\u{fe0f}'
+        | '\u{fe20}'..='\u{fe2f}'
+        | '\u{e0100}'..='\u{e01ef}' => CM,
+        '\t'
+        | '|'
+        | '\u{ad}'
+        | '\u{58a}'
+        | '\u{1680}'
+        | '\u{2000}'..='\u{2006}'
+        | '\u{2008}'..='\u{200a}'
+        | '\u{2010}'
+        | '\u{2012}'
+        | '\u{2013}'
+        | '\u{2027}'
+        | '\u{205f}'
+        | '\u{3000}' => BA,
+        '\u{b4}' | '\u{2c8}' | '\u{2cc}' | '\u{2df}' | '\u{1806}' => BB,
+        '\u{2014}' => B2,
+        '-' => HY,
+        '\u{fffc}' => CB,
+        '}' | '\u{f3b}' | '\u{f3d}' | '\u{3001}' | '\u{3002}' | '\u{3009}' | '\u{300b}'
+        | '\u{300d}' | '\u{300f}' | '\u{3011}' | '\u{3015}' | '\u{3017}' | '\u{3019}'
+        | '\u{301b}' | '\u{301e}' | '\u{301f}' | '\u{fe50}' | '\u{fe52}' | '\u{ff0c}'
+        | '\u{ff0e}' | '\u{ff5d}' | '\u{ff60}' | '\u{ff61}' | '\u{ff63}' | '\u{ff64}' => CL,
+        ')' | ']' | '\u{ff09}' | '\u{ff3d}' => CP,
+        '!' | '?' | '\u{5c6}' | '\u{61b}' | '\u{61f}' | '\u{6d4}' | '\u{fe15}' | '\u{fe16}'
+        | '\u{fe56}' | '\u{fe57}' | '\u{ff01}' | '\u{ff1f}' => EX,
+        '\u{2024}'..='\u{2026}' | '\u{22ef}' | '\u{fe19}' => IN,
+        '\u{17d6}'
+        | '\u{203c}'
+        | '\u{203d}'
+        | '\u{2047}'..='\u{2049}'
+        | '\u{3005}'
+        | '\u{301c}'
+        | '\u{303b}'
+        | '\u{303c}'
+        | '\u{309b}'..='\u{309e}'
+        | '\u{30a0}'
+        | '\u{30fb}'
+        | '\u{30fd}'
+        | '\u{30fe}'
+        | '\u{a015}'
+        | '\u{fe54}'
+        | '\u{fe55}'
+        | '\u{ff1a}'
+        | '\u{ff1b}'
+        | '\u{ff65}'
+        | '\u{ff9e}'
+        | '\u{ff9f}' => NS,
+        '(' | '[' | '{' | '\u{a1}' | '\u{bf}' | '\u{f3a}' | '\u{f3c}' | '\u{201a}' | '\u{201e}'
+        | '\u{3008}' | '\u{300a}' | '\u{300c}' | '\u{300e}' | '\u{3010}' | '\u{3014}'
+        | '\u{3016}' | '\u{3018}' | '\u{301a}' | '\u{301d}' | '\u{ff08}' | '\u{ff3b}'
+        | '\u{ff5b}' | '\u{ff5f}' | '\u{ff62}' => OP,
+        '"'
+        | '\''
+        | '\u{ab}'
+        | '\u{bb}'
+        | '\u{2018}'
+        | '\u{2019}'
+        | '\u{201b}'..='\u{201d}'
+        | '\u{201f}'
+        | '\u{2039}'
+        | '\u{203a}'
+        | '\u{275b}'..='\u{2760}'
+        | '\u{2e00}'..='\u{2e0d}' => QU,
+        ',' | '.' | ':' | ';' | '\u{37e}' | '\u{589}' | '\u{60c}' | '\u{60d}' | '\u{2044}'
+        | '\u{fe10}' | '\u{fe13}' | '\u{fe14}' => IS,
+        '0'..='9'
+        | '\u{660}'..='\u{669}'
+        | '\u{6f0}'..='\u{6f9}'
+        | '\u{966}'..='\u{96f}'
+        | '\u{9e6}'..='\u{9ef}'
+        | '\u{e50}'..='\u{e59}'
+        | '\u{ed0}'..='\u{ed9}'
+        | '\u{1040}'..='\u{1049}'
+        | '\u{17e0}'..='\u{17e9}' => NU,
+        '%'
+        | '\u{a2}'
+        | '\u{b0}'
+        | '\u{66a}'
+        | '\u{2030}'..='\u{2037}'
+        | '\u{2103}'
+        | '\u{2109}'
+        | '\u{fdfc}'
+        | '\u{ff05}'
+        | '\u{ffe0}' => PO,
+        '$'
+        | '+'
+        | '\\'
+        | '\u{a3}'..='\u{a5}'
+        | '\u{b1}'
+        | '\u{20a0}'..='\u{20cf}'
+        | '\u{2116}'
+        | '\u{2212}'
+        | '\u{2213}'
+        | '\u{ff04}'
+        | '\u{ffe1}'
+        | '\u{ffe5}'
+        | '\u{ffe6}' => PR,
+        '/' => SY,
+        '\u{5d0}'..='\u{5ea}' | '\u{5ef}'..='\u{5f2}' | '\u{fb1d}'..='\u{fb4f}' => HL,
+        '\u{3041}'
+        | '\u{3043}'
+        | '\u{3045}'
+        | '\u{3047}'
+        | '\u{3049}'
+        | '\u{3063}'
+        | '\u{3083}'
+        | '\u{3085}'
+        | '\u{3087}'
+        | '\u{308e}'
+        | '\u{3095}'
+        | '\u{3096}'
+        | '\u{30a1}'
+        | '\u{30a3}'
+        | '\u{30a5}'
+        | '\u{30a7}'
+        | '\u{30a9}'
+        | '\u{30c3}'
+        | '\u{30e3}'
+        | '\u{30e5}'
+        | '\u{30e7}'
+        | '\u{30ee}'
+        | '\u{30f5}'
+        | '\u{30f6}'
+        | '\u{30fc}'
+        | '\u{31f0}'..='\u{31ff}'
+        | '\u{ff67}'..='\u{ff70}' => CJ,
+        '\u{1100}'..='\u{115f}' | '\u{a960}'..='\u{a97c}' => JL,
+        '\u{1160}'..='\u{11a7}' | '\u{d7b0}'..='\u{d7c6}' => JV,
+        '\u{11a8}'..='\u{11ff}' | '\u{d7cb}'..='\u{d7fb}' => JT,
+        '\u{ac00}'..='\u{d7a3}' => {
+            if (c as u32 - 0xac00).is_multiple_of(28) {
+                H2
+            } else {
+                H3
+            }
+        }
+        '\u{1f1e6}'..='\u{1f1ff}' => RI,
+        '\u{1f3fb}'..='\u{1f3ff}' => EM,
+        '\u{261d}'
+        | '\u{26f9}'
+        | '\u{270a}'..='\u{270d}'
+        | '\u{1f385}'
+        | '\u{1f3c2}'..='\u{1f3c4}'
+        | '\u{1f3c7}'
+        | '\u{1f3ca}'..='\u{1f3cc}'
+        | '\u{1f442}'..='\u{1f443}'
+        | '\u{1f446}'..='\u{1f450}'
+        | '\u{1f466}'..='\u{1f478}'
+        | '\u{1f481}'..='\u{1f483}'
+        | '\u{1f485}'..='\u{1f487}'
+        | '\u{1f4aa}'
+        | '\u{1f645}'..='\u{1f647}'
+        | '\u{1f64b}'..='\u{1f64f}'
+        | '\u{1f6a3}'
+        | '\u{1f6b4}'..='\u{1f6b6}'
+        | '\u{1f918}'..='\u{1f91f}'
+        | '\u{1f926}'
+        | '\u{1f930}'..='\u{1f939}'
+        | '\u{1f9d1}'..='\u{1f9dd}' => EB,
+        '\u{2e80}'..='\u{2fff}'
+        | '\u{3003}'..='\u{3004}'
+        | '\u{3006}'..='\u{3007}'
+        | '\u{3012}'..='\u{3013}'
+        | '\u{3020}'..='\u{3029}'
+        | '\u{3030}'..='\u{303a}'
+        | '\u{303d}'..='\u{303f}'
+        | '\u{3042}'..='\u{309f}'
+        | '\u{30a2}'..='\u{30ff}'
+        | '\u{3105}'..='\u{31ef}'
+        | '\u{3200}'..='\u{4dbf}'
+        | '\u{4e00}'..='\u{9fff}'
+        | '\u{a000}'..='\u{a48c}'
+        | '\u{f900}'..='\u{faff}'
+        | '\u{fe30}'..='\u{fe4f}'
+        | '\u{ff02}'..='\u{ff5a}'
+        | '\u{1f000}'..='\u{1faff}'
+        | '\u{20000}'..='\u{3fffd}' => ID,
+        '\u{e00}'..='\u{eff}' | '\u{1000}'..='\u{109f}' | '\u{1780}'..='\u{17ff}' => SA,
+        _ => AL,
+    }
+}
+
+/// `word-break` と `line-break` に合わせてクラスを変える
+///
+/// https://www.w3.org/TR/css-text-3/#line-break-details
+pub fn tailor(c: char, class: Class, word_break: WordBreak, line_break: LineBreak) -> Class {
+    let class = match (class, line_break) {
+        (CJ, LineBreak::Strict) => NS,
+        (CJ, _) => ID,
+        // 踊り字の前でも折り返す
+        (NS, LineBreak::Loose) if matches!(c, '\u{3005}' | '\u{303b}' | '\u{309d}'..='\u{309e}' | '\u{30fd}'..='\u{30fe}') => {
+            ID
+        }
+        _ => class,
+    };
+    match (class, word_break) {
+        (AL | HL | NU | SA, WordBreak::BreakAll) => ID,
+        (ID | H2 | H3 | JL | JV | JT, WordBreak::KeepAll) => AL,
+        _ => class,
+    }
+}
+
+/// 各文字の前で折り返せるかどうか。`classes` は `tailor` を通した後のクラス
+///
+/// 最初の文字の前は折り返さない
+pub fn opportunities(text: &[(char, Class)]) -> Vec<Break> {
+    // LB1: 解決できないクラスを AL にする
+    let raw: Vec<Class> = text
+        .iter()
+        .map(|&(_, class)| match class {
+            SA => AL,
+            CJ => NS,
+            class => class,
+        })
+        .collect();
+    // LB9, LB10: 結合文字は直前の文字のクラスを引き継ぐ。基底がなければ AL
+    let mut resolved = raw.clone();
+    for i in 0..resolved.len() {
+        if matches!(raw[i], CM | ZWJ) {
+            resolved[i] = match i.checked_sub(1).map(|j| resolved[j]) {
+                Some(BK | CR | LF | NL | SP | ZW) | None => AL,
+                Some(base) => base,
+            };
+        }
+    }
+
+    (0..text.len())
+        .map(|i| match i {
+            0 => Break::Prohibited,
+            _ => pair(&raw, &resolved, text, i),
+        })
+        .collect()
+}
+
+/// 文字 `i - 1` と `i` の間
+fn pair(raw: &[Class], resolved: &[Class], text: &[(char, Class)], i: usize) -> Break {
+    use self::Break::*;
+
+    // LB4, LB5
+    match raw[i - 1] {
+        BK | LF | NL => return Mandatory,
+        CR if raw[i] == LF => return Prohibited,
+        CR => return Mandatory,
+        _ => {}
+    }
+    // LB6, LB7
+    if matches!(raw[i], BK | CR | LF | NL | SP | ZW) {
+        return Prohibited;
+    }
+    // 空白を飛ばした直前のクラス
+    let before_spaces = resolved[..i].iter().rev().find(|&&class| class != SP);
+    // LB8
+    if before_spaces == Some(&ZW) {
+        return Allowed;
+    }
+    // LB8a
+    if raw[i - 1] == ZWJ {
+        return Prohibited;
+    }
+    // LB9
+    if matches!(raw[i], CM | ZWJ) && !matches!(resolved[i - 1], BK | CR | LF | NL | SP | ZW) {
+        return Prohibited;
+    }
+
+    let (b, a) = (resolved[i - 1], resolved[i]);
+    let prohibited = match (b, a) {
+        // LB11
+        (WJ, _) | (_, WJ) => true,
+        // LB12, LB12a
+        (GL, _) => true,
+        (SP | BA | HY, GL) => false,
+        (_, GL) => true,
+        // LB13
+        (_, CL | CP | EX | IS | SY) => true,
+        _ => false,
+    };
+    if prohibited {
+        return Prohibited;
+    }
+    // LB14 から LB17 は間の空白を越えて働く
+    match (before_spaces, a) {
+        (Some(OP), _) | (Some(QU), OP) | (Some(CL | CP), NS) | (Some(B2), B2) => return Prohibited,
+        _ => {}
+    }
+    // LB18
+    if b == SP {
+        return Allowed;
+    }
+    // LB19, LB20
+    match (b, a) {
+        (QU, _) | (_, QU) => return Prohibited,
+        (CB, _) | (_, CB) => return Allowed,
+        _ => {}
+    }
+    // LB21a
+    if matches!(b, HY | BA) && i >= 2 && resolved[i - 2] == HL {
+        return Prohibited;
+    }
+    // LB30a: 地域指示子は2つずつ組にする
+    if (b, a) == (RI, RI) {
+        let count = resolved[..i].iter().rev().take_while(|&&c| c == RI).count();
+        return if count % 2 == 1 { Prohibited } else { Allowed };
+    }
+    // LB30 の括弧は全角のものを除く
+    let wide = |c: char| c >= '\u{2e80}';
+    let prohibited = match (b, a) {
+        // LB21, LB21b, LB22
+        (_, BA | HY | NS) | (BB, _) | (SY, HL) | (_, IN) => true,
+        // LB23, LB23a, LB24
+        (AL | HL, NU) | (NU, AL | HL) => true,
+        (PR, ID | EB | EM) | (ID | EB | EM, PO) => true,
+        (PR | PO, AL | HL) | (AL | HL, PR | PO) => true,
+        // LB25
+        (CL | CP | NU, PO | PR) | (PO | PR, OP | NU) | (HY | IS | NU | SY, NU) => true,
+        // LB26, LB27
+        (JL, JL | JV | H2 | H3) | (JV | H2, JV | JT) | (JT | H3, JT) => true,
+        (JL | JV | JT | H2 | H3, PO) | (PR, JL | JV | JT | H2 | H3) => true,
+        // LB28, LB29
+        (AL | HL, AL | HL) | (IS, AL | HL) => true,
+        // LB30
+        (AL | HL | NU, OP) => !wide(text[i].0),
+        (CP, AL | HL | NU) => !wide(text[i - 1].0),
+        // LB30b
+        (EB, EM) => true,
+        _ => false,
+    };
+    if prohibited {
+        Prohibited
+    } else {
+        // LB31
+        Allowed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 折り返せる位置で分けた断片
+    fn segments(text: &str, word_break: WordBreak, line_break: LineBreak) -> Vec<String> {
+        let text: Vec<(char, Class)> = text
+            .chars()
+            .map(|c| (c, tailor(c, class(c), word_break, line_break)))
+            .collect();
+        let mut result = vec![String::new()];
+        for (&(c, _), opportunity) in text.iter().zip(opportunities(&text)) {
+            if opportunity != Break::Prohibited && !result.last().unwrap().is_empty() {
+                result.push(String::new());
+            }
+            result.last_mut().unwrap().push(c);
+        }
+        result
+    }
+
+    #[test]
+    fn test_opportunities() {
+        let cases: [(&str, &[&str]); 10] = [
+            ("Hello, world!", &["Hello, ", "world!"]),
+            (
+                "well-known (see p. 3)",
+                &["well-", "known ", "(see ", "p. ", "3)"],
+            ),
+            ("$1,000.50 or 20%", &["$1,000.50 ", "or ", "20%"]),
+            ("a\u{a0}b c", &["a\u{a0}b ", "c"]),
+            ("co\u{ad}operate", &["co\u{ad}", "operate"]),
+            ("x\u{200b}y", &["x\u{200b}", "y"]),
+            ("e\u{301}t\u{e9}", &["e\u{301}t\u{e9}"]),
+            ("「日本語」です。", &["「日", "本", "語」", "で", "す。"]),
+            ("ちょっと", &["ち", "ょ", "っ", "と"]),
+            ("한국어 텍스트", &["한", "국", "어 ", "텍", "스", "트"]),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(
+                segments(text, WordBreak::Normal, LineBreak::Auto),
+                expected,
+                "{:?}",
+                text
+            );
+        }
+
+        // 小書きの仮名の前では `strict` だと折り返さない
+        assert_eq!(
+            segments("ちょっと", WordBreak::Normal, LineBreak::Strict),
+            ["ちょっ", "と"]
+        );
+        assert_eq!(
+            segments("時々", WordBreak::Normal, LineBreak::Normal),
+            ["時々"]
+        );
+        assert_eq!(
+            segments("時々", WordBreak::Normal, LineBreak::Loose),
+            ["時", "々"]
+        );
+        assert_eq!(
+            segments("abc, 日本", WordBreak::BreakAll, LineBreak::Auto),
+            ["a", "b", "c, ", "日", "本"]
+        );
+        assert_eq!(
+            segments("日本語。한국어", WordBreak::KeepAll, LineBreak::Auto),
+            ["日本語。", "한국어"]
+        );
+    }
+}
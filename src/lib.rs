@@ -0,0 +1,7 @@
+pub mod css;
+pub mod dom;
+pub mod font;
+pub mod html;
+pub mod layout;
+pub mod painting;
+pub mod style;
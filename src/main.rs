@@ -1,19 +1,21 @@
-use std::{fs::File, io::Read};
+use std::{collections::HashMap, fs::File, io::Read};
 
 use iced::{
-    canvas::{Frame, Path, Program},
-    executor, Application, Color, Command, Length, Point, Settings, Size,
+    canvas::{event, Event, Frame, Path, Program},
+    executor, keyboard, mouse, Application, Color, Command, Length, Point, Rectangle, Settings,
+    Size,
+};
+use toy_browser::{
+    css::{import, media::Device, Origin, Stylesheet},
+    dom::{ElementData, ElementState, Node, NodeType},
+    html,
+    layout::{self, LayoutBox, Rect},
+    painting::{self, Canvas},
+    style::{self, StyleCache},
 };
-use painting::Canvas;
-
-mod css;
-mod dom;
-mod html;
-mod layout;
-mod painting;
-mod style;
 
 struct Gui {
+    document: Document,
     pixels: Vec<Color>,
     width: usize,
     height: usize,
@@ -26,26 +28,255 @@ struct RenderingCanvas<'a> {
 }
 
 struct GUIProps {
-    canvas: Canvas,
+    document: Document,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Message {
+    /// キャンバス上の位置。キャンバスの外なら `None`
+    CursorMoved(Option<Point>),
+    ButtonPressed,
+    ButtonReleased,
+    /// `true` なら逆順に移る
+    FocusNext(bool),
+}
+
+/// 表示している文書と、利用者の操作で変わる状態
+///
+/// 要素は根からの子の番号の並びで指す
+struct Document {
+    root: Node,
+    stylesheets: Vec<Stylesheet>,
+    device: Device,
+    viewport: layout::Dimensions,
+    /// 前回求めたスタイル
+    cache: Option<StyleCache>,
+    /// 前回描いたときの、ノードを持つボックスのボーダーボックスとそのノード。描いた順
+    hit_regions: Vec<(Rect, Vec<usize>)>,
+    /// ポインタの下にある最も深いノード
+    hovered: Option<Vec<usize>>,
+    /// ボタンを押したときにポインタの下にあったノード
+    active: Option<Vec<usize>>,
+    focused: Option<Vec<usize>>,
+}
+
+impl Document {
+    fn new(root: Node, stylesheets: Vec<Stylesheet>, viewport: layout::Dimensions) -> Document {
+        Document {
+            root,
+            stylesheets,
+            device: Device::new(viewport.content.width, viewport.content.height),
+            viewport,
+            cache: None,
+            hit_regions: Vec::new(),
+            hovered: None,
+            active: None,
+            focused: None,
+        }
+    }
+
+    /// スタイルを (前回からの変更があれば) 求め直してレイアウトし、その結果を `f` に渡す
+    fn with_layout<R>(&mut self, f: impl FnOnce(&LayoutBox, &Node) -> R) -> R {
+        let styled = match self.cache.take() {
+            Some(cache) => {
+                style::restyle(&self.root, &self.stylesheets, &self.device, cache, &[]).0
+            }
+            None => style::style_tree(&self.root, &self.stylesheets, &self.device),
+        };
+        let result = f(&layout::layout_tree(&styled, self.viewport), &self.root);
+        self.cache = Some(styled.into_cache());
+        self.root.clear_dirty();
+        result
+    }
+
+    /// 描いたレイアウトをヒットテストのために残す
+    fn paint(&mut self) -> Canvas {
+        let bounds = self.viewport.content;
+        let (canvas, hit_regions) = self.with_layout(|layout_root, root| {
+            let mut paths = HashMap::new();
+            node_paths(root, &mut Vec::new(), &mut paths);
+            let hit_regions = layout_root
+                .hit_regions()
+                .into_iter()
+                .map(|(rect, node)| (rect, paths[&(node as *const Node)].clone()))
+                .collect();
+            (painting::paint(layout_root, bounds), hit_regions)
+        });
+        self.hit_regions = hit_regions;
+        canvas
+    }
+
+    /// 前回描いたレイアウトで、点を含む最も手前のボックスのノード
+    fn hit_test(&self, point: Point) -> Option<Vec<usize>> {
+        self.hit_regions
+            .iter()
+            .rev()
+            .find(|(rect, _)| rect.contains(point.x, point.y))
+            .map(|(_, path)| path.clone())
+    }
+
+    /// `path` の指すノードか、その最も近い先祖のうちフォーカスできるもの
+    fn focus_target(&self, path: &[usize]) -> Option<Vec<usize>> {
+        (0..=path.len())
+            .rev()
+            .map(|len| path[..len].to_vec())
+            .find(|path| element(&self.root, path).is_some_and(|elem| elem.is_focusable()))
+    }
+
+    /// `path` の指すノードと、その先祖すべての状態を変更する
+    fn update_state_along(&mut self, path: &Option<Vec<usize>>, update: fn(&mut ElementState)) {
+        let Some(path) = path else {
+            return;
+        };
+        for len in 0..=path.len() {
+            self.root.descendant_mut(&path[..len]).update_state(update);
+        }
+    }
+
+    /// 状態が変わったら `true` を返す
+    fn update(&mut self, message: Message) -> bool {
+        match message {
+            Message::CursorMoved(point) => {
+                let hovered = point.and_then(|point| self.hit_test(point));
+                if hovered == self.hovered {
+                    return false;
+                }
+                let old = std::mem::replace(&mut self.hovered, hovered.clone());
+                self.update_state_along(&old, |state| state.hover = false);
+                self.update_state_along(&hovered, |state| state.hover = true);
+            }
+            Message::ButtonPressed => {
+                let hovered = self.hovered.clone();
+                self.active = hovered.clone();
+                self.update_state_along(&hovered, |state| state.active = true);
+                // フォーカスできる最も近い先祖か自身にフォーカスを移し、なければフォーカスを外す
+                let target = hovered.and_then(|path| self.focus_target(&path));
+                if let Some(ref path) = target {
+                    let elem = element(&self.root, path).unwrap();
+                    // 文字を入力する要素では、マウスで移したフォーカスも示す
+                    let is_text_field = elem.tag_name == "textarea"
+                        || elem.tag_name == "input"
+                            && elem.attributes.get("type").is_none_or(|ty| ty == "text");
+                    self.focus(target.clone(), is_text_field);
+                } else {
+                    self.focus(None, false);
+                }
+            }
+            Message::ButtonReleased => {
+                let active = self.active.take();
+                self.update_state_along(&active, |state| state.active = false);
+                // チェックボックスは、押したときと同じ要素の上で離したときに切り替える
+                let pressed = active.and_then(|path| self.focus_target(&path));
+                let released = self
+                    .hovered
+                    .as_ref()
+                    .and_then(|path| self.focus_target(path));
+                if let Some(path) = pressed.filter(|pressed| Some(pressed) == released.as_ref()) {
+                    let elem = element(&self.root, &path).unwrap();
+                    let is_checkbox = elem.tag_name == "input"
+                        && elem
+                            .attributes
+                            .get("type")
+                            .is_some_and(|ty| ty == "checkbox");
+                    if is_checkbox {
+                        self.root
+                            .descendant_mut(&path)
+                            .update_state(|state| state.checked = !state.checked);
+                    }
+                }
+            }
+            Message::FocusNext(backwards) => {
+                let mut focusable = Vec::new();
+                focusable_paths(&self.root, &mut Vec::new(), &mut focusable);
+                if backwards {
+                    focusable.reverse();
+                }
+                let next = match self.focused {
+                    Some(ref focused) => focusable
+                        .iter()
+                        .skip_while(|&path| path != focused)
+                        .nth(1)
+                        .or(focusable.first()),
+                    None => focusable.first(),
+                };
+                self.focus(next.cloned(), true);
+            }
+        }
+        true
+    }
+
+    fn focus(&mut self, path: Option<Vec<usize>>, visible: bool) {
+        if let Some(ref old) = self.focused {
+            self.root.descendant_mut(old).update_state(|state| {
+                state.focus = false;
+                state.focus_visible = false;
+            });
+        }
+        if let Some(ref new) = path {
+            self.root.descendant_mut(new).update_state(|state| {
+                state.focus = true;
+                state.focus_visible = visible;
+            });
+        }
+        self.focused = path;
+    }
+}
+
+fn element<'a>(root: &'a Node, path: &[usize]) -> Option<&'a ElementData> {
+    let node = path.iter().fold(root, |node, &index| &node.children[index]);
+    match node.node_type {
+        NodeType::Element(ref elem) => Some(elem),
+        NodeType::Text(_) => None,
+    }
+}
+
+/// すべてのノードについて、根からの子の番号の並びを求める
+fn node_paths(node: &Node, path: &mut Vec<usize>, result: &mut HashMap<*const Node, Vec<usize>>) {
+    result.insert(node, path.clone());
+    for (index, child) in node.children.iter().enumerate() {
+        path.push(index);
+        node_paths(child, path, result);
+        path.pop();
+    }
+}
+
+/// フォーカスできる要素を文書順に集める
+fn focusable_paths(node: &Node, path: &mut Vec<usize>, result: &mut Vec<Vec<usize>>) {
+    if let NodeType::Element(ref elem) = node.node_type {
+        if elem.is_focusable() {
+            result.push(path.clone());
+        }
+    }
+    for (index, child) in node.children.iter().enumerate() {
+        path.push(index);
+        focusable_paths(child, path, result);
+        path.pop();
+    }
+}
+
+fn to_pixels(canvas: &Canvas) -> Vec<Color> {
+    canvas
+        .pixels
+        .iter()
+        .map(|color| Color::from_rgba8(color.r, color.g, color.b, color.a as f32 / 255.0))
+        .collect()
 }
 
 impl Application for Gui {
     type Executor = executor::Default;
 
-    type Message = ();
+    type Message = Message;
 
     type Flags = GUIProps;
 
     fn new(flags: Self::Flags) -> (Self, iced::Command<Self::Message>) {
+        let mut document = flags.document;
+        let canvas = document.paint();
         let gui = Gui {
-            pixels: flags
-                .canvas
-                .pixels
-                .iter()
-                .map(|color| Color::from_rgba8(color.r, color.g, color.b, color.a as f32))
-                .collect(),
-            width: flags.canvas.width,
-            height: flags.canvas.height,
+            document,
+            pixels: to_pixels(&canvas),
+            width: canvas.width,
+            height: canvas.height,
         };
         (gui, Command::none())
     }
@@ -56,9 +287,12 @@ impl Application for Gui {
 
     fn update(
         &mut self,
-        _message: Self::Message,
+        message: Self::Message,
         _clipboard: &mut iced::Clipboard,
     ) -> Command<Self::Message> {
+        if self.document.update(message) {
+            self.pixels = to_pixels(&self.document.paint());
+        }
         Command::none()
     }
 
@@ -74,7 +308,32 @@ impl Application for Gui {
     }
 }
 
-impl<'a> Program<()> for RenderingCanvas<'a> {
+impl<'a> Program<Message> for RenderingCanvas<'a> {
+    fn update(
+        &mut self,
+        event: Event,
+        bounds: Rectangle,
+        cursor: iced::canvas::Cursor,
+    ) -> (event::Status, Option<Message>) {
+        let message = match event {
+            Event::Mouse(mouse::Event::CursorMoved { .. } | mouse::Event::CursorLeft) => {
+                Message::CursorMoved(cursor.position_in(&bounds))
+            }
+            Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left)) => {
+                Message::ButtonPressed
+            }
+            Event::Mouse(mouse::Event::ButtonReleased(mouse::Button::Left)) => {
+                Message::ButtonReleased
+            }
+            Event::Keyboard(keyboard::Event::KeyPressed {
+                key_code: keyboard::KeyCode::Tab,
+                modifiers,
+            }) => Message::FocusNext(modifiers.shift),
+            _ => return (event::Status::Ignored, None),
+        };
+        (event::Status::Captured, Some(message))
+    }
+
     fn draw(
         &self,
         _bounds: iced::Rectangle,
@@ -121,20 +380,24 @@ fn main() -> iced::Result {
     viewport.content.height = 600.0;
 
     let html = read_source("test/test.html".to_string());
-    let css = read_source("test/test.css".to_string());
 
     let root_node = html::parse(html);
-    let stylesheet = css::parse(css);
-    let style_root = style::style_tree(&root_node, &stylesheet);
-    let layout_root = layout::layout_tree(&style_root, viewport);
-
-    let canvas = painting::paint(&layout_root, viewport.content);
+    let mut stylesheets = Vec::new();
+    // 無視した宣言や規則は警告として報告する
+    let mut load = |path: &str, origin| {
+        let (stylesheet, errors) = import::load(path.as_ref(), origin).unwrap();
+        for error in errors {
+            eprintln!("{}", error);
+        }
+        stylesheets.push(stylesheet);
+    };
+    // 引数で指定されたファイルをユーザースタイルシートとして読み込む
+    if let Some(user_css) = std::env::args().nth(1) {
+        load(&user_css, Origin::User);
+    }
+    load("test/test.css", Origin::Author);
 
     Gui::run(Settings::with_flags(GUIProps {
-        canvas: Canvas {
-            pixels: canvas.pixels,
-            width: viewport.content.width as usize,
-            height: viewport.content.height as usize,
-        },
+        document: Document::new(root_node, stylesheets, viewport),
     }))
 }
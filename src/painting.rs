@@ -1,6 +1,10 @@
 use crate::{
-    css::{Color, Value},
-    layout::{AnonymousBlock, BlockNode, InlineNode, LayoutBox, Rect},
+    css::Color,
+    layout::{
+        AnonymousBlock, BlockNode, GeneratedText, InlineNode, LayoutBox, LineBox, Marker, Rect,
+        Text,
+    },
+    style::computed::ComputedStyle,
 };
 
 pub type DisplayList = Vec<DisplayCommand>;
@@ -24,7 +28,7 @@ fn render_layout_box(list: &mut DisplayList, layout_box: &LayoutBox) {
 }
 
 fn render_background(list: &mut DisplayList, layout_box: &LayoutBox) {
-    if let Some(color) = get_color(layout_box, "background") {
+    if let Some(color) = get_color(layout_box, |style| style.background_color) {
         list.push(DisplayCommand::SolidColor(
             color,
             layout_box.dimensions.border_box(),
@@ -33,67 +37,68 @@ fn render_background(list: &mut DisplayList, layout_box: &LayoutBox) {
 }
 
 fn render_borders(list: &mut DisplayList, layout_box: &LayoutBox) {
-    let color = match get_color(layout_box, "border-color") {
-        Some(color) => color,
-        _ => return,
-    };
-
     let d = &layout_box.dimensions;
     let border_box = d.border_box();
 
     // Left border
-    list.push(DisplayCommand::SolidColor(
-        color,
-        Rect {
-            x: border_box.x,
-            y: border_box.y,
-            width: d.border.left,
-            height: border_box.height,
-        },
-    ));
+    if let Some(color) = get_color(layout_box, |style| style.border_color.left) {
+        list.push(DisplayCommand::SolidColor(
+            color,
+            Rect {
+                x: border_box.x,
+                y: border_box.y,
+                width: d.border.left,
+                height: border_box.height,
+            },
+        ));
+    }
 
     // Right border
-    list.push(DisplayCommand::SolidColor(
-        color,
-        Rect {
-            x: border_box.x + border_box.width - d.border.right,
-            y: border_box.y,
-            width: d.border.right,
-            height: border_box.height,
-        },
-    ));
+    if let Some(color) = get_color(layout_box, |style| style.border_color.right) {
+        list.push(DisplayCommand::SolidColor(
+            color,
+            Rect {
+                x: border_box.x + border_box.width - d.border.right,
+                y: border_box.y,
+                width: d.border.right,
+                height: border_box.height,
+            },
+        ));
+    }
 
     // Top border
-    list.push(DisplayCommand::SolidColor(
-        color,
-        Rect {
-            x: border_box.x,
-            y: border_box.y,
-            width: border_box.width,
-            height: d.border.top,
-        },
-    ));
+    if let Some(color) = get_color(layout_box, |style| style.border_color.top) {
+        list.push(DisplayCommand::SolidColor(
+            color,
+            Rect {
+                x: border_box.x,
+                y: border_box.y,
+                width: border_box.width,
+                height: d.border.top,
+            },
+        ));
+    }
 
     // Bottom border
-    list.push(DisplayCommand::SolidColor(
-        color,
-        Rect {
-            x: border_box.x,
-            y: border_box.y + border_box.height + d.border.bottom,
-            width: border_box.width,
-            height: d.border.bottom,
-        },
-    ));
+    if let Some(color) = get_color(layout_box, |style| style.border_color.bottom) {
+        list.push(DisplayCommand::SolidColor(
+            color,
+            Rect {
+                x: border_box.x,
+                y: border_box.y + border_box.height + d.border.bottom,
+                width: border_box.width,
+                height: d.border.bottom,
+            },
+        ));
+    }
 }
 
-/// CSS プロパティ `name` に指定された色を返す
-fn get_color(layout_box: &LayoutBox, name: &str) -> Option<Color> {
+/// ボックスの計算値から `color` で選んだ色を返す
+fn get_color(layout_box: &LayoutBox, color: fn(&ComputedStyle) -> Color) -> Option<Color> {
     match layout_box.box_type {
-        BlockNode(style) | InlineNode(style) => match style.value(name) {
-            Some(Value::ColorValue(color)) => Some(color),
-            _ => None,
-        },
-        AnonymousBlock => None,
+        BlockNode(style) | InlineNode(style) => Some(color(&style.computed)),
+        // 生成した文字列の背景とボーダーは擬似要素のボックスが描く
+        AnonymousBlock | LineBox | Text(..) | GeneratedText(..) | Marker(..) => None,
     }
 }
 
@@ -130,7 +135,8 @@ impl Canvas {
 
                 for y in y0..y1 {
                     for x in x0..x1 {
-                        self.pixels[x + y * self.width] = color;
+                        let pixel = &mut self.pixels[x + y * self.width];
+                        *pixel = blend(color, *pixel);
                     }
                 }
             }
@@ -138,6 +144,28 @@ impl Canvas {
     }
 }
 
+/// `src` を `dst` の上にアルファ合成する (source-over)
+fn blend(src: Color, dst: Color) -> Color {
+    if src.a == 255 {
+        return src;
+    }
+    let src_a = src.a as f32 / 255.0;
+    let dst_a = dst.a as f32 / 255.0;
+    let out_a = src_a + dst_a * (1.0 - src_a);
+    if out_a == 0.0 {
+        return src;
+    }
+    let channel = |s: u8, d: u8| {
+        ((s as f32 * src_a + d as f32 * dst_a * (1.0 - src_a)) / out_a).round() as u8
+    };
+    Color {
+        r: channel(src.r, dst.r),
+        g: channel(src.g, dst.g),
+        b: channel(src.b, dst.b),
+        a: (out_a * 255.0).round() as u8,
+    }
+}
+
 /// LayoutBoxのツリーをピクセルの配列にペイントする
 pub fn paint(layout_root: &LayoutBox, bounds: Rect) -> Canvas {
     let display_list = build_display_list(layout_root);
@@ -147,3 +175,40 @@ pub fn paint(layout_root: &LayoutBox, bounds: Rect) -> Canvas {
     }
     canvas
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blend() {
+        let white = Color {
+            r: 255,
+            g: 255,
+            b: 255,
+            a: 255,
+        };
+        let transparent = Color {
+            r: 0,
+            g: 0,
+            b: 0,
+            a: 0,
+        };
+        let half_red = Color {
+            r: 255,
+            g: 0,
+            b: 0,
+            a: 128,
+        };
+        assert_eq!(blend(transparent, white), white);
+        assert_eq!(
+            blend(half_red, white),
+            Color {
+                r: 255,
+                g: 127,
+                b: 127,
+                a: 255,
+            }
+        );
+    }
+}
@@ -1,123 +1,1149 @@
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::OnceLock,
+};
 
 use crate::{
-    css::{Rule, Selector, SimpleSelector, Specificity, Stylesheet, Value},
+    css::{
+        self,
+        custom::{self as custom_property, Token, TokenStream, UnparsedValue},
+        media::Device,
+        properties::{PropertyId, PROPERTIES},
+        CssRule, Declaration, ImportLayer, LayerName, LengthContext, Origin, PseudoElement, Rule,
+        Selector, Specificity, Stylesheet, Unit, Value,
+    },
     dom::{ElementData, Node, NodeType},
+    font,
+};
+
+use self::{
+    computed::ComputedStyle,
+    invalidation::{InvalidationMap, RestyleScope},
+    matching::{Ancestors, SelectorMap},
+    sharing::StyleSharingCache,
 };
 
-pub type PropertyMap = HashMap<String, Value>;
+pub mod computed;
+mod invalidation;
+mod matching;
+mod sharing;
+pub mod trace;
+
+pub type PropertyMap = HashMap<PropertyId, Value>;
+
+/// カスタムプロパティの名前 (`--` から始まる) と `var()` を置換した後の値
+pub type CustomPropertyMap = HashMap<String, Value>;
 
 #[derive(Debug, PartialEq)]
 pub struct StyledNode<'a> {
+    /// 擬似要素では、それを生成した要素
     pub node: &'a Node,
+    pub pseudo_element: Option<PseudoElement>,
+    /// すべてのプロパティの値。指定されなかったものは継承値か初期値になる
+    ///
+    /// テキストノードは親要素の値を継承する
     pub specified_values: PropertyMap,
+    /// 親から継承したものを含む
+    pub custom_properties: CustomPropertyMap,
+    pub computed: ComputedStyle,
     pub children: Vec<StyledNode<'a>>,
+    /// ボックスを生成する `::before` と `::after` (この順)
+    pub pseudo_elements: Vec<StyledNode<'a>>,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Display {
     Inline,
     Block,
+    /// マーカーを生成するブロック
+    ListItem,
     None,
 }
 
 impl<'a> StyledNode<'a> {
-    pub fn value(&self, name: &str) -> Option<Value> {
-        self.specified_values.get(name).cloned()
+    pub fn value(&self, id: PropertyId) -> Value {
+        self.specified_values
+            .get(&id)
+            .cloned()
+            .unwrap_or_else(|| id.initial_value())
     }
 
-    pub fn lookup(&self, name: &str, fallback: &str, default: &Value) -> Value {
-        self.value(name)
-            .unwrap_or_else(|| self.value(fallback).unwrap_or_else(|| default.clone()))
+    pub fn custom_property(&self, name: &str) -> Option<Value> {
+        self.custom_properties.get(name).cloned()
     }
 
     pub fn display(&self) -> Display {
-        match self.value("display") {
-            Some(Value::Keyword(s)) => match &*s {
-                "block" => Display::Block,
-                "none" => Display::None,
-                _ => Display::Inline,
+        self.computed.display
+    }
+
+    pub fn pseudo_element(&self, pseudo_element: PseudoElement) -> Option<&StyledNode<'a>> {
+        self.pseudo_elements
+            .iter()
+            .find(|styled| styled.pseudo_element == Some(pseudo_element))
+    }
+
+    fn new(node: &'a Node, style: NodeStyle, children: Vec<StyledNode<'a>>) -> StyledNode<'a> {
+        StyledNode {
+            node,
+            pseudo_element: None,
+            specified_values: style.specified_values,
+            custom_properties: style.custom_properties,
+            computed: style.computed,
+            children,
+            pseudo_elements: style
+                .pseudo_elements
+                .into_iter()
+                .map(|(pseudo_element, style)| StyledNode {
+                    pseudo_element: Some(pseudo_element),
+                    ..StyledNode::new(node, style, Vec::new())
+                })
+                .collect(),
+        }
+    }
+}
+
+/// カスケードにおける宣言の出どころと重要度の優先順位 (後ろほど強い)
+///
+/// https://www.w3.org/TR/css-cascade-4/#cascade-origin
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum CascadeLevel {
+    UserAgentNormal,
+    UserNormal,
+    AuthorNormal,
+    AuthorImportant,
+    UserImportant,
+    UserAgentImportant,
+}
+
+impl CascadeLevel {
+    fn new(origin: Origin, important: bool) -> CascadeLevel {
+        match (origin, important) {
+            (Origin::UserAgent, false) => CascadeLevel::UserAgentNormal,
+            (Origin::User, false) => CascadeLevel::UserNormal,
+            (Origin::Author, false) => CascadeLevel::AuthorNormal,
+            (Origin::Author, true) => CascadeLevel::AuthorImportant,
+            (Origin::User, true) => CascadeLevel::UserImportant,
+            (Origin::UserAgent, true) => CascadeLevel::UserAgentImportant,
+        }
+    }
+}
+
+/// HTML 要素のデフォルトのスタイル
+fn user_agent_stylesheet() -> &'static Stylesheet {
+    static STYLESHEET: OnceLock<Stylesheet> = OnceLock::new();
+    STYLESHEET.get_or_init(|| {
+        css::parse_with_origin(include_str!("ua.css").to_string(), Origin::UserAgent)
+    })
+}
+
+/// カスケードに参加するスタイル規則
+#[derive(Clone, Copy)]
+struct CascadeRule<'a> {
+    rule: &'a Rule,
+    /// 規則を直接含むスタイルシート (`@import` で読み込んだものなら読み込み先)
+    stylesheet: &'a Stylesheet,
+    origin: Origin,
+    /// 出どころの中でのカスケードレイヤーの順位 (通常の宣言では大きいほど強い)
+    layer: usize,
+}
+
+/// カスケードに参加する規則と、それを要素から引く表
+struct RuleSet<'a> {
+    rules: Vec<CascadeRule<'a>>,
+    map: SelectorMap,
+    invalidation: InvalidationMap,
+    /// セレクタに現れる擬似要素
+    pseudo_elements: HashSet<PseudoElement>,
+}
+
+/// スタイルツリーを作る間の統計
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StyleStats {
+    /// 兄弟要素のスタイルを再利用した要素の数
+    pub sharing_hits: usize,
+    /// 再利用できず、規則を照合した要素の数
+    pub sharing_misses: usize,
+    /// `restyle` で前回のスタイルをそのまま使った要素の数
+    pub reused: usize,
+}
+
+impl std::ops::AddAssign for StyleStats {
+    fn add_assign(&mut self, other: StyleStats) {
+        self.sharing_hits += other.sharing_hits;
+        self.sharing_misses += other.sharing_misses;
+        self.reused += other.reused;
+    }
+}
+
+impl StyleStats {
+    /// 要素のうち、スタイルを再利用できたものの割合
+    pub fn sharing_hit_rate(&self) -> f32 {
+        let total = self.sharing_hits + self.sharing_misses;
+        if total == 0 {
+            return 0.0;
+        }
+        self.sharing_hits as f32 / total as f32
+    }
+}
+
+/// `stylesheets` は出現順 (ソース順) に並べる
+///
+/// ユーザーエージェントスタイルシートはこれらより前に適用される
+/// `@media` 規則は `device` に対して評価する
+pub fn style_tree<'a>(
+    root: &'a Node,
+    stylesheets: &'a [Stylesheet],
+    device: &Device,
+) -> StyledNode<'a> {
+    style_tree_with_stats(root, stylesheets, device).0
+}
+
+/// `style_tree` と同じだが、スタイル共有キャッシュの利用状況も返す
+pub fn style_tree_with_stats<'a>(
+    root: &'a Node,
+    stylesheets: &'a [Stylesheet],
+    device: &Device,
+) -> (StyledNode<'a>, StyleStats) {
+    style_document(root, stylesheets, device, None, &[], true)
+}
+
+/// `style_tree_with_stats` と同じだが、`parallel` 機能が有効でも兄弟を並列に処理しない
+///
+/// 並列に求めた結果や速さと比べるためのもの
+pub fn style_tree_sequential_with_stats<'a>(
+    root: &'a Node,
+    stylesheets: &'a [Stylesheet],
+    device: &Device,
+) -> (StyledNode<'a>, StyleStats) {
+    style_document(root, stylesheets, device, None, &[], false)
+}
+
+/// 前回のスタイル `previous` のうち、変更の影響を受けない部分を使ってスタイルツリーを作る
+///
+/// DOM の変更は `Node::dirty` から読む。`changed_stylesheets` は前回から追加または
+/// 削除されたスタイルシートで、その規則に一致する要素だけを求め直す
+/// `device` が前回と異なる場合は `style_tree` を使う
+pub fn restyle<'a>(
+    root: &'a Node,
+    stylesheets: &'a [Stylesheet],
+    device: &Device,
+    previous: StyleCache,
+    changed_stylesheets: &[&Stylesheet],
+) -> (StyledNode<'a>, StyleStats) {
+    style_document(
+        root,
+        stylesheets,
+        device,
+        Some(previous),
+        changed_stylesheets,
+        true,
+    )
+}
+
+fn style_document<'a>(
+    root: &'a Node,
+    stylesheets: &'a [Stylesheet],
+    device: &Device,
+    previous: Option<StyleCache>,
+    changed_stylesheets: &[&Stylesheet],
+    parallel: bool,
+) -> (StyledNode<'a>, StyleStats) {
+    let ctx = LengthContext {
+        font_size: MEDIUM_FONT_SIZE,
+        root_font_size: MEDIUM_FONT_SIZE,
+        percent_base: MEDIUM_FONT_SIZE,
+        viewport_width: device.width,
+        viewport_height: device.height,
+        ..LengthContext::default()
+    }
+    .with_font(font::database().default_font());
+    let rules = cascade_rules(stylesheets, device);
+    let changed_rules = collect_rules(changed_stylesheets.iter().copied(), device);
+    let mut traversal = Traversal {
+        rules: &rules,
+        changed_rules: (!changed_stylesheets.is_empty()).then_some(&changed_rules),
+        ancestors: Ancestors::default(),
+        stats: StyleStats::default(),
+        parallel,
+    };
+    let styled = style_subtree(
+        root,
+        None,
+        &HashMap::new(),
+        ctx,
+        &mut traversal,
+        &mut StyleSharingCache::default(),
+        previous,
+    );
+    (styled, traversal.stats)
+}
+
+/// 木をたどる間、すべてのノードで共有する状態
+struct Traversal<'a, 'r> {
+    rules: &'r RuleSet<'r>,
+    /// `restyle` で追加または削除されたスタイルシートの規則
+    changed_rules: Option<&'r RuleSet<'r>>,
+    /// いまスタイルを求めているノードの先祖
+    ancestors: Ancestors<'a>,
+    stats: StyleStats,
+    /// `false` なら `parallel` 機能が有効でも兄弟を順に処理する
+    #[cfg_attr(not(feature = "parallel"), allow(dead_code))]
+    parallel: bool,
+}
+
+/// 前回求めたスタイル。DOM を借用しないので、DOM を変更する間も持っておける
+pub struct StyleCache {
+    style: NodeStyle,
+    children: Vec<StyleCache>,
+}
+
+impl<'a> StyledNode<'a> {
+    pub fn into_cache(self) -> StyleCache {
+        StyleCache {
+            style: NodeStyle {
+                specified_values: self.specified_values,
+                custom_properties: self.custom_properties,
+                computed: self.computed,
+                pseudo_elements: self
+                    .pseudo_elements
+                    .into_iter()
+                    .map(|styled| (styled.pseudo_element.unwrap(), styled.into_cache().style))
+                    .collect(),
             },
-            _ => Display::Inline,
+            children: self
+                .children
+                .into_iter()
+                .map(StyledNode::into_cache)
+                .collect(),
         }
     }
 }
 
-pub fn style_tree<'a>(root: &'a Node, stylesheet: &'a Stylesheet) -> StyledNode<'a> {
-    StyledNode {
-        node: root,
-        specified_values: match root.node_type {
-            NodeType::Element(ref elem) => specified_values(elem, stylesheet),
-            NodeType::Text(_) => HashMap::new(),
-        },
-        children: root
-            .children
+/// ユーザーエージェントスタイルシートと `stylesheets` から、カスケードに参加する規則を集める
+fn cascade_rules<'a>(stylesheets: &'a [Stylesheet], device: &Device) -> RuleSet<'a> {
+    collect_rules(
+        std::iter::once(user_agent_stylesheet()).chain(stylesheets),
+        device,
+    )
+}
+
+fn collect_rules<'a>(
+    stylesheets: impl Iterator<Item = &'a Stylesheet>,
+    device: &Device,
+) -> RuleSet<'a> {
+    let mut collector = RuleCollector {
+        device,
+        layers: HashMap::new(),
+        rules: Vec::new(),
+    };
+    for stylesheet in stylesheets {
+        collector.collect(
+            stylesheet,
+            &stylesheet.rules,
+            stylesheet.origin,
+            LayerTree::ROOT,
+        );
+    }
+    let rules = collector.finish();
+    let selectors = rules.iter().flat_map(|rule| &rule.rule.selectors);
+    RuleSet {
+        map: SelectorMap::new(rules.iter().map(|rule| rule.rule)),
+        invalidation: InvalidationMap::new(selectors.clone()),
+        pseudo_elements: selectors.filter_map(Selector::pseudo_element).collect(),
+        rules,
+    }
+}
+
+/// 条件が一致する規則だけを、`@import` を展開しながらソース順に集める
+struct RuleCollector<'a, 'd> {
+    device: &'d Device,
+    layers: HashMap<Origin, LayerTree>,
+    rules: Vec<CascadeRule<'a>>,
+}
+
+impl<'a> RuleCollector<'a, '_> {
+    /// `rules` は `stylesheet` の中の規則で、`layer` は `rules` が属するレイヤーの節点
+    fn collect(
+        &mut self,
+        stylesheet: &'a Stylesheet,
+        rules: &'a [CssRule],
+        origin: Origin,
+        layer: usize,
+    ) {
+        for rule in rules {
+            match *rule {
+                CssRule::Style(ref rule) => self.rules.push(CascadeRule {
+                    rule,
+                    stylesheet,
+                    origin,
+                    layer,
+                }),
+                CssRule::Media(ref media) => {
+                    if media.queries.matches(self.device) {
+                        self.collect(stylesheet, &media.rules, origin, layer);
+                    }
+                }
+                // 読み込めなかったスタイルシートは無視する
+                CssRule::Import(ref import) => {
+                    if let Some(Ok(ref imported)) = import.stylesheet {
+                        if import.media.matches(self.device) {
+                            let tree = self.layers.entry(origin).or_default();
+                            let layer = match import.layer {
+                                None => layer,
+                                Some(ImportLayer::Anonymous) => tree.anonymous(layer),
+                                Some(ImportLayer::Named(ref name)) => tree.declare(layer, name),
+                            };
+                            self.collect(imported, &imported.rules, origin, layer);
+                        }
+                    }
+                }
+                CssRule::Layer(ref block) => {
+                    let tree = self.layers.entry(origin).or_default();
+                    let layer = match block.name {
+                        Some(ref name) => tree.declare(layer, name),
+                        None => tree.anonymous(layer),
+                    };
+                    self.collect(stylesheet, &block.rules, origin, layer);
+                }
+                CssRule::LayerStatement(ref names) => {
+                    let tree = self.layers.entry(origin).or_default();
+                    for name in names {
+                        tree.declare(layer, name);
+                    }
+                }
+                CssRule::Supports(ref supports) => {
+                    if supports.condition.matches() {
+                        self.collect(stylesheet, &supports.rules, origin, layer);
+                    }
+                }
+            }
+        }
+    }
+
+    /// レイヤーの節点を順位に置き換えて、集めた規則を返す
+    fn finish(self) -> Vec<CascadeRule<'a>> {
+        let ranks: HashMap<Origin, Vec<usize>> = self
+            .layers
             .iter()
-            .map(|child| style_tree(child, stylesheet))
-            .collect(),
+            .map(|(&origin, tree)| (origin, tree.ranks()))
+            .collect();
+        let mut rules = self.rules;
+        for rule in &mut rules {
+            if let Some(ranks) = ranks.get(&rule.origin) {
+                rule.layer = ranks[rule.layer];
+            }
+        }
+        rules
     }
 }
 
-fn specified_values(elem: &ElementData, stylesheet: &Stylesheet) -> PropertyMap {
-    let mut values = HashMap::new();
-    let mut rules = matching_rules(elem, stylesheet);
+/// 1つの出どころの中のカスケードレイヤーの木
+///
+/// 根はレイヤーに属さない規則を表す
+/// https://www.w3.org/TR/css-cascade-5/#layer-ordering
+struct LayerTree {
+    nodes: Vec<LayerNode>,
+}
+
+#[derive(Default)]
+struct LayerNode {
+    /// 無名レイヤーは `None`
+    name: Option<String>,
+    /// 宣言順に並んだ子レイヤー
+    children: Vec<usize>,
+}
 
-    rules.sort_by(|&(a, _), &(b, _)| a.cmp(&b));
-    for (_, rule) in rules {
-        for declaration in &rule.declarations {
-            values.insert(declaration.name.clone(), declaration.value.clone());
+impl Default for LayerTree {
+    fn default() -> LayerTree {
+        LayerTree {
+            nodes: vec![LayerNode::default()],
         }
     }
-    values
 }
 
-type MatchRule<'a> = (Specificity, &'a Rule);
+impl LayerTree {
+    const ROOT: usize = 0;
 
-fn matching_rules<'a>(elem: &ElementData, stylesheet: &'a Stylesheet) -> Vec<MatchRule<'a>> {
-    stylesheet
-        .rules
-        .iter()
-        .filter_map(|rule| match_rule(elem, rule))
-        .collect()
+    /// `parent` の中でレイヤー `name` を宣言する。宣言済みならその節点を返す
+    fn declare(&mut self, parent: usize, name: &LayerName) -> usize {
+        name.iter().fold(parent, |node, part| {
+            let existing = self.nodes[node]
+                .children
+                .iter()
+                .copied()
+                .find(|&child| self.nodes[child].name.as_deref() == Some(&**part));
+            existing.unwrap_or_else(|| self.push(node, Some(part.clone())))
+        })
+    }
+
+    /// 無名レイヤーは出現するたびに別のレイヤーになる
+    fn anonymous(&mut self, parent: usize) -> usize {
+        self.push(parent, None)
+    }
+
+    fn push(&mut self, parent: usize, name: Option<String>) -> usize {
+        let node = self.nodes.len();
+        self.nodes.push(LayerNode {
+            name,
+            children: Vec::new(),
+        });
+        self.nodes[parent].children.push(node);
+        node
+    }
+
+    /// 各節点の順位
+    ///
+    /// 後に宣言されたレイヤーほど大きく、レイヤー直下の規則はその子レイヤーより大きい
+    fn ranks(&self) -> Vec<usize> {
+        fn visit(tree: &LayerTree, node: usize, ranks: &mut Vec<usize>, next: &mut usize) {
+            for &child in &tree.nodes[node].children {
+                visit(tree, child, ranks, next);
+            }
+            ranks[node] = *next;
+            *next += 1;
+        }
+        let mut ranks = vec![0; self.nodes.len()];
+        visit(self, LayerTree::ROOT, &mut ranks, &mut 0);
+        ranks
+    }
 }
 
-fn match_rule<'a>(elem: &ElementData, rule: &'a Rule) -> Option<MatchRule<'a>> {
-    rule.selectors
-        .iter()
-        .find(|selector| matchs(elem, selector))
-        .map(|selector| (selector.specificity(), rule))
+/// `parent` は親要素の値で、ルート要素では `None`
+///
+/// `ctx` はフォントサイズの解決に使う。`ctx.font_size` は親要素のフォントサイズ
+/// `sharing` は兄弟要素のスタイルを覚えておくキャッシュ
+/// `previous` は前回のスタイルで、`None` なら部分木のすべてを求め直す
+fn style_subtree<'a>(
+    root: &'a Node,
+    parent: Option<&PropertyMap>,
+    parent_custom_properties: &CustomPropertyMap,
+    ctx: LengthContext,
+    traversal: &mut Traversal<'a, '_>,
+    sharing: &mut StyleSharingCache<'a>,
+    previous: Option<StyleCache>,
+) -> StyledNode<'a> {
+    let (style, previous_children) = match (previous, &root.node_type) {
+        (Some(previous), NodeType::Element(elem)) => {
+            match invalidation::restyle_scope(root, elem, traversal) {
+                RestyleScope::None => {
+                    traversal.stats.reused += 1;
+                    (previous.style, Some(previous.children))
+                }
+                RestyleScope::Element => {
+                    let style = element_style(
+                        elem,
+                        parent,
+                        parent_custom_properties,
+                        &ctx,
+                        traversal,
+                        sharing,
+                    );
+                    // スタイルが変わらなければ、子孫が継承する値も変わらない
+                    let children = (style == previous.style).then_some(previous.children);
+                    (style, children)
+                }
+                RestyleScope::Subtree => {
+                    let style = element_style(
+                        elem,
+                        parent,
+                        parent_custom_properties,
+                        &ctx,
+                        traversal,
+                        sharing,
+                    );
+                    (style, None)
+                }
+            }
+        }
+        (None, NodeType::Element(elem)) => {
+            let style = element_style(
+                elem,
+                parent,
+                parent_custom_properties,
+                &ctx,
+                traversal,
+                sharing,
+            );
+            (style, None)
+        }
+        (Some(previous), NodeType::Text(_)) => (previous.style, Some(previous.children)),
+        (None, NodeType::Text(_)) => {
+            let style = NodeStyle::new(
+                default_values(HashMap::new(), parent, &ctx),
+                parent_custom_properties.clone(),
+                &ctx,
+                parent.is_none(),
+            );
+            (style, None)
+        }
+    };
+    let previous_children = previous_children
+        .filter(|children| !root.dirty.children_changed && children.len() == root.children.len());
+
+    let ctx = element_length_context(ctx, &style.computed, parent.is_none());
+    if let NodeType::Element(ref elem) = root.node_type {
+        traversal.ancestors.push(elem);
+    }
+    let children = style_children(root, &style, ctx, traversal, previous_children);
+    if let NodeType::Element(_) = root.node_type {
+        traversal.ancestors.pop();
+    }
+    StyledNode::new(root, style, children)
 }
 
-fn matchs(elem: &ElementData, selector: &Selector) -> bool {
-    match *selector {
-        Selector::Simple(ref simple_selector) => matchs_simple_selector(elem, simple_selector),
+/// 子と擬似要素の長さを解決する基準。`ctx` は親要素のもの
+///
+/// フォント相対長さは、計算値が `computed` の要素のフォントサイズとフォントを基準にする
+fn element_length_context(
+    ctx: LengthContext,
+    computed: &ComputedStyle,
+    is_root: bool,
+) -> LengthContext {
+    let mut ctx = ctx.with_font(computed.font());
+    ctx.font_size = computed.font_size;
+    if is_root {
+        ctx.root_font_size = ctx.font_size;
     }
+    ctx
 }
 
-fn matchs_simple_selector(elem: &ElementData, selector: &SimpleSelector) -> bool {
-    if selector.tag_name.iter().any(|name| elem.tag_name != *name) {
-        return false;
+/// 規則を照合して要素のスタイルを求める。同じスタイルになる兄弟があればそれを使う
+fn element_style<'a>(
+    elem: &'a ElementData,
+    parent: Option<&PropertyMap>,
+    parent_custom_properties: &CustomPropertyMap,
+    ctx: &LengthContext,
+    traversal: &mut Traversal<'a, '_>,
+    sharing: &mut StyleSharingCache<'a>,
+) -> NodeStyle {
+    if let Some(style) = sharing.get(elem) {
+        traversal.stats.sharing_hits += 1;
+        return style;
     }
+    traversal.stats.sharing_misses += 1;
+    let (mut values, custom_properties) =
+        specified_values(elem, &traversal.ancestors, traversal.rules, None);
+    let custom_properties =
+        substitute_variables(&mut values, custom_properties, parent_custom_properties);
+    let values = default_values(values, parent, ctx);
+    let mut style = NodeStyle::new(values, custom_properties, ctx, parent.is_none());
 
-    if selector.id.iter().any(|id| elem.id() != Some(id)) {
-        return false;
+    let ctx = element_length_context(*ctx, &style.computed, parent.is_none());
+    style.pseudo_elements = [PseudoElement::Before, PseudoElement::After]
+        .into_iter()
+        .filter(|pseudo_element| traversal.rules.pseudo_elements.contains(pseudo_element))
+        .filter_map(|pseudo_element| {
+            let style = pseudo_element_style(elem, pseudo_element, &style, &ctx, traversal)?;
+            Some((pseudo_element, style))
+        })
+        .collect();
+    sharing.insert(elem, &style);
+    style
+}
+
+/// 要素 `elem` のスタイル `parent` を継承する擬似要素のスタイル。ボックスを生成しなければ `None`
+///
+/// `ctx.font_size` は `elem` のフォントサイズ
+fn pseudo_element_style(
+    elem: &ElementData,
+    pseudo_element: PseudoElement,
+    parent: &NodeStyle,
+    ctx: &LengthContext,
+    traversal: &Traversal,
+) -> Option<NodeStyle> {
+    let (mut values, custom_properties) = specified_values(
+        elem,
+        &traversal.ancestors,
+        traversal.rules,
+        Some(pseudo_element),
+    );
+    if values.is_empty() {
+        return None;
+    }
+    let custom_properties =
+        substitute_variables(&mut values, custom_properties, &parent.custom_properties);
+    let values = default_values(values, Some(&parent.specified_values), ctx);
+    let style = NodeStyle::new(values, custom_properties, ctx, false);
+    // `content` が `normal` か `none` ならボックスを生成しない
+    (!style.computed.content.is_empty()).then_some(style)
+}
+
+/// `parent` は `root` のスタイルで、`previous` は子の前回のスタイル
+fn style_children<'a>(
+    root: &'a Node,
+    parent: &NodeStyle,
+    ctx: LengthContext,
+    traversal: &mut Traversal<'a, '_>,
+    previous: Option<Vec<StyleCache>>,
+) -> Vec<StyledNode<'a>> {
+    #[cfg(feature = "parallel")]
+    if traversal.parallel {
+        return style_children_parallel(root, parent, ctx, traversal, previous);
     }
+    style_children_sequential(root, parent, ctx, traversal, previous)
+}
 
-    let elem_classes = elem.classes();
-    if selector
-        .class
+/// 子を順に処理し、兄弟全体でスタイル共有キャッシュを使う
+fn style_children_sequential<'a>(
+    root: &'a Node,
+    parent: &NodeStyle,
+    ctx: LengthContext,
+    traversal: &mut Traversal<'a, '_>,
+    previous: Option<Vec<StyleCache>>,
+) -> Vec<StyledNode<'a>> {
+    let mut sharing = StyleSharingCache::default();
+    let mut previous = previous.map(Vec::into_iter);
+    root.children
         .iter()
-        .any(|class| !elem_classes.contains(&**class))
+        .map(|child| {
+            style_subtree(
+                child,
+                Some(&parent.specified_values),
+                &parent.custom_properties,
+                ctx,
+                traversal,
+                &mut sharing,
+                previous.as_mut().and_then(Iterator::next),
+            )
+        })
+        .collect()
+}
+
+/// 並列に処理する兄弟の単位。この中ではスタイル共有キャッシュを使う
+#[cfg(feature = "parallel")]
+const PARALLEL_CHUNK_SIZE: usize = 32;
+
+/// 子を `PARALLEL_CHUNK_SIZE` 個ずつに分け、それぞれの部分木を別のタスクで求める
+#[cfg(feature = "parallel")]
+fn style_children_parallel<'a>(
+    root: &'a Node,
+    parent: &NodeStyle,
+    ctx: LengthContext,
+    traversal: &mut Traversal<'a, '_>,
+    previous: Option<Vec<StyleCache>>,
+) -> Vec<StyledNode<'a>> {
+    use rayon::prelude::*;
+
+    let mut previous: Vec<Option<StyleCache>> = match previous {
+        Some(previous) => previous.into_iter().map(Some).collect(),
+        None => root.children.iter().map(|_| None).collect(),
+    };
+    let rules = traversal.rules;
+    let changed_rules = traversal.changed_rules;
+    let ancestors = &traversal.ancestors;
+    let chunks: Vec<(Vec<StyledNode>, StyleStats)> = root
+        .children
+        .par_chunks(PARALLEL_CHUNK_SIZE)
+        .zip(previous.par_chunks_mut(PARALLEL_CHUNK_SIZE))
+        .map(|(chunk, previous)| {
+            let mut traversal = Traversal {
+                rules,
+                changed_rules,
+                ancestors: ancestors.clone(),
+                stats: StyleStats::default(),
+                parallel: true,
+            };
+            let mut sharing = StyleSharingCache::default();
+            let children = chunk
+                .iter()
+                .zip(previous)
+                .map(|(child, previous)| {
+                    style_subtree(
+                        child,
+                        Some(&parent.specified_values),
+                        &parent.custom_properties,
+                        ctx,
+                        &mut traversal,
+                        &mut sharing,
+                        previous.take(),
+                    )
+                })
+                .collect();
+            (children, traversal.stats)
+        })
+        .collect();
+
+    let mut children = Vec::with_capacity(root.children.len());
+    for (chunk, stats) in chunks {
+        children.extend(chunk);
+        traversal.stats += stats;
+    }
+    children
+}
+
+/// 1つのノードのスタイル。子孫を除いた `StyledNode` の中身
+#[derive(Clone, PartialEq)]
+struct NodeStyle {
+    specified_values: PropertyMap,
+    custom_properties: CustomPropertyMap,
+    computed: ComputedStyle,
+    pseudo_elements: Vec<(PseudoElement, NodeStyle)>,
+}
+
+impl NodeStyle {
+    /// `ctx.font_size` は親要素のフォントサイズ
+    fn new(
+        specified_values: PropertyMap,
+        custom_properties: CustomPropertyMap,
+        ctx: &LengthContext,
+        is_root: bool,
+    ) -> NodeStyle {
+        let mut ctx = *ctx;
+        ctx.font_size = specified_values[&PropertyId::FontSize].to_px(&ctx);
+        if is_root {
+            ctx.root_font_size = ctx.font_size;
+        }
+        NodeStyle {
+            computed: ComputedStyle::compute(&specified_values, &ctx),
+            specified_values,
+            custom_properties,
+            pseudo_elements: Vec::new(),
+        }
+    }
+}
+
+/// 指定されなかったプロパティと CSS 全体のキーワードの値を、継承値か初期値で置き換える
+///
+/// 継承されるフォントサイズと太さは親の値を基準にするので、ここで計算値に解決する
+/// https://www.w3.org/TR/css-cascade-4/#defaulting
+fn default_values(
+    mut values: PropertyMap,
+    parent: Option<&PropertyMap>,
+    ctx: &LengthContext,
+) -> PropertyMap {
+    let inherit = |id: PropertyId| match parent {
+        Some(parent) => parent[&id].clone(),
+        None => id.initial_value(),
+    };
+    let mut complete = HashMap::with_capacity(PROPERTIES.len());
+    for def in &PROPERTIES {
+        let id = def.id;
+        let value = match values.remove(&id) {
+            Some(Value::Keyword(ref k)) if k == "initial" => id.initial_value(),
+            Some(Value::Keyword(ref k)) if k == "inherit" => inherit(id),
+            // `unset` と、ロールバックする先のない `revert`
+            Some(Value::Keyword(ref k)) if k == "unset" || k == "revert" => {
+                if def.inherited {
+                    inherit(id)
+                } else {
+                    id.initial_value()
+                }
+            }
+            // 負の値を受け付けないプロパティの式は、負になれば0に切り詰める
+            Some(Value::Calc(calc)) if def.grammar.non_negative => {
+                Value::Calc(calc.clamp_non_negative())
+            }
+            Some(value) => value,
+            None if def.inherited => inherit(id),
+            None => id.initial_value(),
+        };
+        complete.insert(id, value);
+    }
+
+    let font_size = font_size(&complete[&PropertyId::FontSize], ctx);
+    complete.insert(PropertyId::FontSize, Value::Length(font_size, Unit::Px));
+    // 長さとパーセンテージの `line-height` はこの要素のフォントサイズで解決してから継承する
+    if let Value::Length(..) | Value::Percentage(_) | Value::Calc(_) =
+        complete[&PropertyId::LineHeight]
     {
-        return false;
+        let ctx = LengthContext {
+            font_size,
+            percent_base: font_size,
+            ..*ctx
+        };
+        let line_height = complete[&PropertyId::LineHeight].to_px(&ctx);
+        complete.insert(PropertyId::LineHeight, Value::Length(line_height, Unit::Px));
+    }
+    let parent_weight = match parent.map(|parent| &parent[&PropertyId::FontWeight]) {
+        Some(&Value::Number(weight)) => weight,
+        _ => computed::NORMAL_FONT_WEIGHT,
+    };
+    let font_weight = match complete[&PropertyId::FontWeight] {
+        Value::Keyword(ref k) if k == "bold" => 700.0,
+        Value::Keyword(ref k) if k == "normal" => computed::NORMAL_FONT_WEIGHT,
+        Value::Keyword(ref k) => computed::relative_font_weight(k, parent_weight),
+        Value::Number(weight) => weight,
+        _ => parent_weight,
+    };
+    complete.insert(PropertyId::FontWeight, Value::Number(font_weight));
+    // `color` プロパティの `currentcolor` は親の色を表す
+    if let Value::Keyword(ref k) = complete[&PropertyId::Color] {
+        if k == "currentcolor" {
+            complete.insert(PropertyId::Color, inherit(PropertyId::Color));
+        }
+    }
+    complete
+}
+
+/// `font-size: medium` の大きさ
+pub const MEDIUM_FONT_SIZE: f32 = 16.0;
+
+/// 要素のフォントサイズをpx単位で求める
+///
+/// `em` とパーセンテージは親のフォントサイズ `parent.font_size` を基準にする
+///
+/// https://www.w3.org/TR/css-fonts-4/#font-size-prop
+fn font_size(value: &Value, parent: &LengthContext) -> f32 {
+    let ctx = LengthContext {
+        percent_base: parent.font_size,
+        ..*parent
+    };
+    match *value {
+        Value::Keyword(ref k) => match &**k {
+            "xx-small" => MEDIUM_FONT_SIZE * 3.0 / 5.0,
+            "x-small" => MEDIUM_FONT_SIZE * 3.0 / 4.0,
+            "small" => MEDIUM_FONT_SIZE * 8.0 / 9.0,
+            "medium" => MEDIUM_FONT_SIZE,
+            "large" => MEDIUM_FONT_SIZE * 6.0 / 5.0,
+            "x-large" => MEDIUM_FONT_SIZE * 3.0 / 2.0,
+            "xx-large" => MEDIUM_FONT_SIZE * 2.0,
+            "xxx-large" => MEDIUM_FONT_SIZE * 3.0,
+            "larger" => parent.font_size * 1.2,
+            "smaller" => parent.font_size / 1.2,
+            _ => parent.font_size,
+        },
+        Value::Length(..) | Value::Percentage(_) | Value::Calc(_) => value.to_px(&ctx),
+        _ => parent.font_size,
+    }
+}
+
+/// カスタムプロパティを親から継承し、すべての `var()` を置換する
+///
+/// 置換できなかった宣言と、置換した結果がプロパティの文法に合わない宣言は
+/// 計算値の時点で無効となり、指定されなかったものとして扱う
+/// https://www.w3.org/TR/css-variables-1/#substitute-a-var
+fn substitute_variables(
+    values: &mut PropertyMap,
+    custom_properties: CustomPropertyMap,
+    parent_custom_properties: &CustomPropertyMap,
+) -> CustomPropertyMap {
+    let mut resolver = VariableResolver::default();
+    // 親の値は置換済みで、要素自身の宣言がそれを上書きする
+    for (name, value) in parent_custom_properties.iter().chain(&custom_properties) {
+        if let Value::Unparsed(ref unparsed) = *value {
+            resolver.raw.insert(name.clone(), unparsed.tokens.clone());
+        }
     }
 
-    true
+    let mut resolved = HashMap::new();
+    let names: Vec<String> = resolver.raw.keys().cloned().collect();
+    for name in names {
+        if let Some(text) = resolver.resolve(&name) {
+            let value = Value::Unparsed(UnparsedValue {
+                tokens: TokenStream(vec![Token::Text(text)]),
+                shorthand: None,
+            });
+            resolved.insert(name, value);
+        }
+    }
+
+    let mut invalid = Vec::new();
+    for (&id, value) in values.iter_mut() {
+        let Value::Unparsed(ref unparsed) = *value else {
+            continue;
+        };
+        let substituted = unparsed
+            .tokens
+            .substitute(&mut |name| resolver.resolve(name))
+            .and_then(|text| unparsed.parse_substituted(id.name(), &text))
+            .filter(|value| id.def().accepts(value));
+        match substituted {
+            Some(substituted) => *value = substituted,
+            None => invalid.push(id),
+        }
+    }
+    for id in invalid {
+        values.remove(&id);
+    }
+    resolved
+}
+
+/// カスタムプロパティの値の中の `var()` を、循環を検出しながら置換する
+#[derive(Default)]
+struct VariableResolver {
+    /// 置換前の値
+    raw: HashMap<String, TokenStream>,
+    /// 置換後の値。無効なものは `None`
+    resolved: HashMap<String, Option<String>>,
+    /// 置換中のプロパティ
+    stack: Vec<String>,
+}
+
+impl VariableResolver {
+    fn resolve(&mut self, name: &str) -> Option<String> {
+        if let Some(resolved) = self.resolved.get(name) {
+            return resolved.clone();
+        }
+        if let Some(start) = self.stack.iter().position(|n| n == name) {
+            // 循環に含まれるプロパティはフォールバックの有無にかかわらずすべて無効
+            for name in &self.stack[start..] {
+                self.resolved.insert(name.clone(), None);
+            }
+            return None;
+        }
+
+        let tokens = self.raw.get(name)?.clone();
+        self.stack.push(name.to_string());
+        let result = tokens.substitute(&mut |name| self.resolve(name));
+        self.stack.pop();
+
+        let result = if self.resolved.contains_key(name) {
+            None
+        } else {
+            result
+        };
+        self.resolved.insert(name.to_string(), result.clone());
+        result
+    }
+}
+
+/// 置換前のカスタムプロパティは別の表に分けて返す
+///
+/// `pseudo_element` が `Some` なら、要素 `elem` のその擬似要素の値
+fn specified_values(
+    elem: &ElementData,
+    ancestors: &Ancestors,
+    rules: &RuleSet,
+    pseudo_element: Option<PseudoElement>,
+) -> (PropertyMap, CustomPropertyMap) {
+    let declarations = cascade_declarations(elem, ancestors, rules, pseudo_element);
+    cascaded_values(&group_by_property(declarations))
+}
+
+/// プロパティごとに分けた宣言から、勝った宣言の値を集める
+fn cascaded_values(
+    groups: &HashMap<&str, Vec<MatchedDeclaration>>,
+) -> (PropertyMap, CustomPropertyMap) {
+    let mut values = HashMap::new();
+    let mut custom_properties = HashMap::new();
+    for (&name, candidates) in groups {
+        let Some(winner) = cascaded_value(candidates) else {
+            continue;
+        };
+        let value = candidates[winner].declaration.value.clone();
+        if custom_property::is_custom_property(name) {
+            custom_properties.insert(name.to_string(), value);
+        } else if let Some(id) = PropertyId::from_name(name) {
+            values.insert(id, value);
+        }
+    }
+    (values, custom_properties)
+}
+
+/// 要素に一致した規則の宣言
+#[derive(Clone, Copy)]
+struct MatchedDeclaration<'a> {
+    declaration: &'a Declaration,
+    rule: CascadeRule<'a>,
+    specificity: Specificity,
+}
+
+/// 要素に一致した規則の宣言を、カスケードの優先順位の昇順に並べる
+///
+/// https://www.w3.org/TR/css-cascade-4/#cascade-sort
+fn cascade_declarations<'a>(
+    elem: &ElementData,
+    ancestors: &Ancestors,
+    rules: &RuleSet<'a>,
+    pseudo_element: Option<PseudoElement>,
+) -> Vec<MatchedDeclaration<'a>> {
+    let mut declarations: Vec<((CascadeLevel, isize, Specificity), MatchedDeclaration)> =
+        Vec::new();
+    for (specificity, rule) in matching_rules(elem, ancestors, rules, pseudo_element) {
+        for declaration in &rule.rule.declarations {
+            let level = CascadeLevel::new(rule.origin, declaration.important);
+            // `!important` の宣言では、先に宣言されたレイヤーほど強い
+            let layer = if declaration.important {
+                -(rule.layer as isize)
+            } else {
+                rule.layer as isize
+            };
+            let matched = MatchedDeclaration {
+                declaration,
+                rule,
+                specificity,
+            };
+            declarations.push(((level, layer, specificity), matched));
+        }
+    }
+
+    // 安定ソートなので、優先順位が等しい宣言はソース順に並んだまま残り、後のものが勝つ
+    declarations.sort_by_key(|&(priority, _)| priority);
+    declarations
+        .into_iter()
+        .map(|(_, declaration)| declaration)
+        .collect()
+}
+
+/// 宣言をプロパティ名ごとに、順序を保ったまま分ける
+fn group_by_property<'a>(
+    declarations: Vec<MatchedDeclaration<'a>>,
+) -> HashMap<&'a str, Vec<MatchedDeclaration<'a>>> {
+    let mut groups: HashMap<&str, Vec<MatchedDeclaration>> = HashMap::new();
+    for declaration in declarations {
+        groups
+            .entry(&declaration.declaration.name)
+            .or_default()
+            .push(declaration);
+    }
+    groups
+}
+
+/// 優先順位の昇順に並んだ同じプロパティの宣言から、勝った宣言の位置を返す
+///
+/// `revert` が勝った場合は、その出どころより弱い出どころの宣言だけでカスケードをやり直す
+/// ロールバックする先がなければ `None` (指定されなかったもの) になる
+/// https://www.w3.org/TR/css-cascade-4/#default
+fn cascaded_value(candidates: &[MatchedDeclaration]) -> Option<usize> {
+    let mut limit = None;
+    for (i, candidate) in candidates.iter().enumerate().rev() {
+        let origin = candidate.rule.origin;
+        if limit.is_some_and(|limit| origin >= limit) {
+            continue;
+        }
+        match candidate.declaration.value {
+            Value::Keyword(ref k) if k == "revert" => limit = Some(origin),
+            _ => return Some(i),
+        }
+    }
+    None
+}
+
+type MatchRule<'a> = (Specificity, CascadeRule<'a>);
+
+/// 要素 (`pseudo_element` が `Some` ならその擬似要素) に一致した規則をソース順に返す
+///
+/// 規則の複数のセレクタが一致した場合は、最も詳細度の高いものを使う
+fn matching_rules<'a>(
+    elem: &ElementData,
+    ancestors: &Ancestors,
+    rules: &RuleSet<'a>,
+    pseudo_element: Option<PseudoElement>,
+) -> Vec<MatchRule<'a>> {
+    let mut matched: Vec<MatchRule> = Vec::new();
+    let mut last_rule = None;
+    for (index, selector) in rules.map.candidates(elem, ancestors.filter()) {
+        // セレクタは詳細度の降順に並んでいるので、最初に一致したものを使う
+        if last_rule == Some(index) {
+            continue;
+        }
+        let rule = rules.rules[index];
+        let selector = &rule.rule.selectors[selector];
+        if selector.pseudo_element() == pseudo_element
+            && matching::matches(elem, ancestors, selector)
+        {
+            matched.push((selector.specificity(), rule));
+            last_rule = Some(index);
+        }
+    }
+    matched
 }
 
 #[cfg(test)]
 mod test {
     use crate::{
         css::{self, Color},
+        dom::{Dirty, ElementState},
         html,
     };
 
@@ -125,49 +1151,407 @@ mod test {
 
     #[test]
     fn test_style() {
-        let html_node = html::parse(r#"<h1 class="test">head line</h1>"#.to_string());
+        let html_node = html::parse(r#"<span class="test">head line</span>"#.to_string());
         let stylesheet = css::parse(r#".test { color: #000000; }"#.to_string());
-        let actual = style_tree(&html_node, &stylesheet);
+        let stylesheets = [stylesheet];
+        let actual = style_tree(&html_node, &stylesheets, &Device::new(800.0, 600.0));
 
+        // 指定されなかったプロパティは初期値になり、テキストノードは `color` を継承する
+        let mut values: PropertyMap = PROPERTIES
+            .iter()
+            .map(|def| (def.id, def.id.initial_value()))
+            .collect();
+        values.insert(PropertyId::FontSize, Value::Length(16.0, css::Unit::Px));
+        values.insert(PropertyId::FontWeight, Value::Number(400.0));
+        values.insert(
+            PropertyId::Color,
+            Value::ColorValue(Color {
+                r: 0,
+                g: 0,
+                b: 0,
+                a: 255,
+            }),
+        );
         assert_eq!(
             actual,
             StyledNode {
                 node: &Node {
                     children: vec![Node {
                         children: vec![],
-                        node_type: NodeType::Text("head line".to_string())
+                        node_type: NodeType::Text("head line".to_string()),
+                        dirty: Dirty::default(),
                     }],
                     node_type: NodeType::Element(ElementData {
-                        tag_name: "h1".to_string(),
+                        tag_name: "span".to_string(),
                         attributes: {
                             let mut ret = HashMap::new();
                             ret.insert("class".to_string(), "test".to_string());
                             ret
-                        }
-                    })
-                },
-                specified_values: {
-                    let mut ret = HashMap::new();
-                    ret.insert(
-                        "color".to_string(),
-                        Value::ColorValue(Color {
-                            r: 0,
-                            g: 0,
-                            b: 0,
-                            a: 255,
-                        }),
-                    );
-                    ret
+                        },
+                        state: ElementState::default(),
+                    }),
+                    dirty: Dirty::default(),
                 },
+                pseudo_element: None,
+                specified_values: values.clone(),
+                custom_properties: HashMap::new(),
+                computed: ComputedStyle::default(),
                 children: vec![StyledNode {
                     node: &Node {
                         children: vec![],
                         node_type: NodeType::Text("head line".to_string()),
+                        dirty: Dirty::default(),
                     },
-                    specified_values: HashMap::new(),
-                    children: vec![]
-                }]
+                    pseudo_element: None,
+                    specified_values: values,
+                    custom_properties: HashMap::new(),
+                    computed: ComputedStyle::default(),
+                    children: vec![],
+                    pseudo_elements: vec![],
+                }],
+                pseudo_elements: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn test_inheritance() {
+        let html_node = html::parse(
+            r#"<div><p>text</p><span class="a"></span><span class="b"></span></div>"#.to_string(),
+        );
+        let stylesheets = [css::parse(
+            r#"
+            div { color: red; font-size: 20px; margin-top: 3px; }
+            p { font-size: 1.5em; margin-top: inherit; margin-bottom: 7px; }
+            p { margin-bottom: revert; }
+            .a { color: initial; font-size: unset; margin-top: unset; }
+            .b { color: currentcolor; font-size: larger; display: revert; }
+            "#
+            .to_string(),
+        )];
+        let styled = style_tree(&html_node, &stylesheets, &Device::new(800.0, 600.0));
+        let px = |f| Value::Length(f, css::Unit::Px);
+        let red = Value::ColorValue(Color {
+            r: 255,
+            g: 0,
+            b: 0,
+            a: 255,
+        });
+
+        let [p, a, b] = &styled.children[..] else {
+            panic!("unexpected children");
+        };
+        assert_eq!(p.value(PropertyId::Color), red);
+        // 継承されるのは解決したフォントサイズ
+        assert_eq!(p.value(PropertyId::FontSize), px(30.0));
+        assert_eq!(p.value(PropertyId::MarginTop), px(3.0));
+        // 作者の `revert` はユーザーエージェントの値に戻す
+        assert_eq!(
+            p.value(PropertyId::MarginBottom),
+            Value::Length(1.0, css::Unit::Em)
+        );
+        let text = &p.children[0];
+        assert_eq!(text.value(PropertyId::FontSize), px(30.0));
+        assert_eq!(text.value(PropertyId::MarginTop), px(0.0));
+
+        assert_eq!(
+            a.value(PropertyId::Color),
+            PropertyId::Color.initial_value()
+        );
+        assert_eq!(a.value(PropertyId::FontSize), px(20.0));
+        assert_eq!(a.value(PropertyId::MarginTop), px(0.0));
+
+        assert_eq!(b.value(PropertyId::Color), red);
+        assert_eq!(b.value(PropertyId::FontSize), px(24.0));
+        assert_eq!(
+            b.value(PropertyId::Display),
+            Value::Keyword("inline".to_string())
+        );
+    }
+
+    #[test]
+    fn test_non_negative_calc() {
+        let html_node = html::parse("<p></p>".to_string());
+        let stylesheets = [css::parse(
+            "p { font-size: calc(10px - 1em); padding-left: calc(1em - 20px); margin-left: calc(1em - 20px); }"
+                .to_string(),
+        )];
+        let styled = style_tree(&html_node, &stylesheets, &Device::new(800.0, 600.0));
+        let ctx = LengthContext {
+            font_size: 16.0,
+            ..LengthContext::default()
+        };
+
+        assert_eq!(
+            styled.value(PropertyId::FontSize),
+            Value::Length(0.0, css::Unit::Px)
+        );
+        assert_eq!(styled.value(PropertyId::PaddingLeft).to_px(&ctx), 0.0);
+        assert_eq!(styled.value(PropertyId::MarginLeft).to_px(&ctx), -4.0);
+    }
+
+    #[test]
+    fn test_font_relative_units() {
+        let html_node = html::parse("<div><p></p></div>".to_string());
+        let stylesheets = [css::parse(
+            "div { font-size: 20px; } p { font-size: 2ex; padding-left: 1ex; padding-right: 3ch; }"
+                .to_string(),
+        )];
+        let styled = style_tree(&html_node, &stylesheets, &Device::new(800.0, 600.0));
+        let font = font::database().default_font();
+        let (x_height, zero) = (font.x_height_ratio(), font.zero_advance_ratio());
+        assert_ne!(x_height, 0.5);
+
+        // `font-size` の `ex` は親のフォントサイズを基準にする
+        let p = &styled.children[0].computed;
+        assert_eq!(p.font_size, 2.0 * x_height * 20.0);
+        assert_eq!(
+            p.padding.left,
+            computed::LengthPercentage::Length(x_height * p.font_size)
+        );
+        assert_eq!(
+            p.padding.right,
+            computed::LengthPercentage::Length(3.0 * zero * p.font_size)
+        );
+    }
+
+    #[test]
+    fn test_cascade_order() {
+        let html_node = html::parse(r#"<p id="x" class="a"></p>"#.to_string());
+        let stylesheets = [
+            css::parse_with_origin(
+                r#"p { color: #000001 !important; margin-top: 1px; }"#.to_string(),
+                Origin::UserAgent,
+            ),
+            css::parse_with_origin(
+                r#"p { width: 2px !important; margin-top: 2px; padding-top: 2px; }"#.to_string(),
+                Origin::User,
+            ),
+            css::parse(
+                r#"
+                #x { color: #000003 !important; width: 3px !important; padding-top: 3px; }
+                p { margin-top: 3px !important; padding-top: 4px !important; }
+                .a { margin-top: 5px; }
+                "#
+                .to_string(),
+            ),
+        ];
+        let styled = style_tree(&html_node, &stylesheets, &Device::new(800.0, 600.0));
+
+        let px = |f| Value::Length(f, css::Unit::Px);
+        // UA の !important は作者の !important より強い
+        assert_eq!(
+            styled.value(PropertyId::Color),
+            Value::ColorValue(Color {
+                r: 0,
+                g: 0,
+                b: 1,
+                a: 255,
+            })
+        );
+        // ユーザーの !important は作者の !important より強い
+        assert_eq!(styled.value(PropertyId::Width), px(2.0));
+        // 作者の !important は詳細度の高い通常の宣言より強い
+        assert_eq!(styled.value(PropertyId::MarginTop), px(3.0));
+        assert_eq!(styled.value(PropertyId::PaddingTop), px(4.0));
+    }
+
+    #[test]
+    fn test_user_agent_stylesheet() {
+        let html_node = html::parse(r#"<div><p></p><head></head><em></em></div>"#.to_string());
+        let stylesheets = [css::parse(r#"p { margin-top: 0; }"#.to_string())];
+        let styled = style_tree(&html_node, &stylesheets, &Device::new(800.0, 600.0));
+
+        let keyword = |k: &str| Value::Keyword(k.to_string());
+        assert_eq!(styled.value(PropertyId::Display), keyword("block"));
+
+        let [p, head, em] = &styled.children[..] else {
+            panic!("unexpected children");
+        };
+        assert_eq!(p.value(PropertyId::MarginTop), Value::Number(0.0));
+        assert_eq!(
+            p.value(PropertyId::MarginBottom),
+            Value::Length(1.0, css::Unit::Em)
+        );
+        assert_eq!(head.value(PropertyId::Display), keyword("none"));
+        assert_eq!(em.value(PropertyId::Display), keyword("inline"));
+        assert_eq!(em.value(PropertyId::FontStyle), keyword("italic"));
+    }
+
+    #[test]
+    fn test_media_rules() {
+        let html_node = html::parse(r#"<div></div>"#.to_string());
+        let stylesheets = [css::parse(
+            r#"
+            div { width: 1px; }
+            @media (min-width: 600px) { div { width: 2px; } }
+            @media print { div { width: 3px; } }
+            @media screen and (orientation: landscape) {
+                @media (width < 1000px) { div { height: 4px; } }
             }
+            "#
+            .to_string(),
+        )];
+
+        let px = |f| Value::Length(f, css::Unit::Px);
+        let styled = style_tree(&html_node, &stylesheets, &Device::new(800.0, 600.0));
+        assert_eq!(styled.value(PropertyId::Width), px(2.0));
+        assert_eq!(styled.value(PropertyId::Height), px(4.0));
+
+        let styled = style_tree(&html_node, &stylesheets, &Device::new(400.0, 600.0));
+        assert_eq!(styled.value(PropertyId::Width), px(1.0));
+        assert_eq!(
+            styled.value(PropertyId::Height),
+            PropertyId::Height.initial_value()
+        );
+    }
+
+    #[test]
+    fn test_imported_rules() {
+        let html_node = html::parse(r#"<p></p>"#.to_string());
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/test/import/main.css");
+        let stylesheets = [css::import::load(path.as_ref(), Origin::Author).unwrap().0];
+
+        let styled = style_tree(&html_node, &stylesheets, &Device::new(800.0, 600.0));
+        let red = Color {
+            r: 255,
+            g: 0,
+            b: 0,
+            a: 255,
+        };
+        assert_eq!(styled.value(PropertyId::Color), Value::ColorValue(red));
+        assert_ne!(
+            styled.value(PropertyId::BackgroundColor),
+            PropertyId::BackgroundColor.initial_value()
+        );
+
+        let styled = style_tree(&html_node, &stylesheets, &Device::new(300.0, 600.0));
+        assert_eq!(
+            styled.value(PropertyId::BackgroundColor),
+            PropertyId::BackgroundColor.initial_value()
+        );
+    }
+
+    #[test]
+    fn test_custom_properties() {
+        let html_node = html::parse(r#"<div><p></p><span></span></div>"#.to_string());
+        let stylesheets = [css::parse(
+            r#"
+            div { --brand-color: #00ccff; --gap: 2px; --a: var(--b); --b: var(--a, red); }
+            p { background: var(--brand-color, #fff); margin: var(--gap) 0; color: var(--a, red); }
+            span {
+                --brand-color: var(--missing);
+                background-color: var(--brand-color, #fff);
+                border-color: var(--a);
+            }
+            "#
+            .to_string(),
+        )];
+        let styled = style_tree(&html_node, &stylesheets, &Device::new(800.0, 600.0));
+        let color = |r, g, b| Value::ColorValue(Color { r, g, b, a: 255 });
+
+        let div = &styled;
+        assert_eq!(div.custom_property("--a"), None);
+        assert_eq!(div.custom_property("--b"), None);
+
+        let p = &div.children[0];
+        assert_eq!(
+            p.value(PropertyId::BackgroundColor),
+            color(0x00, 0xcc, 0xff)
+        );
+        assert_eq!(
+            p.value(PropertyId::BackgroundRepeat),
+            Value::Keyword("repeat".to_string())
+        );
+        assert_eq!(
+            p.value(PropertyId::MarginTop),
+            Value::Length(2.0, css::Unit::Px)
+        );
+        assert_eq!(p.value(PropertyId::MarginLeft), Value::Number(0.0));
+        assert_eq!(p.value(PropertyId::Color), color(0xff, 0x00, 0x00));
+
+        let span = &div.children[1];
+        assert_eq!(span.custom_property("--brand-color"), None);
+        assert_eq!(span.custom_property("--gap"), p.custom_property("--gap"));
+        assert_eq!(
+            span.value(PropertyId::BackgroundColor),
+            color(0xff, 0xff, 0xff)
+        );
+        assert_eq!(
+            span.value(PropertyId::BorderTopColor),
+            Value::Keyword("currentcolor".to_string())
+        );
+    }
+
+    #[test]
+    fn test_cascade_layers() {
+        let html_node = html::parse(r#"<p class="a"></p>"#.to_string());
+        let stylesheets = [css::parse(
+            r#"
+            @layer base, components;
+            @layer components { p { color: blue; width: 1px !important; } }
+            @layer base {
+                p { color: red; height: 1px; width: 2px !important; }
+                .a { margin-top: 1px; }
+            }
+            p { height: 2px; }
+            @layer { p { margin-top: 3px; } }
+            @layer base.reset { p { color: green; border-top-width: 1px; } }
+            @supports (display: grid) { p { padding-top: 1px; } }
+            @supports not (display: grid) { p { padding-left: 1px; } }
+            "#
+            .to_string(),
+        )];
+        let styled = style_tree(&html_node, &stylesheets, &Device::new(800.0, 600.0));
+        let px = |f| Value::Length(f, css::Unit::Px);
+
+        // 後に宣言されたレイヤーが勝ち、レイヤー直下の規則はその子レイヤーに勝つ
+        assert_eq!(
+            styled.value(PropertyId::Color),
+            Value::ColorValue(Color {
+                r: 0,
+                g: 0,
+                b: 255,
+                a: 255
+            })
+        );
+        // `!important` では先に宣言されたレイヤーが勝つ
+        assert_eq!(styled.value(PropertyId::Width), px(2.0));
+        // レイヤーに属さない規則はどのレイヤーにも勝つ
+        assert_eq!(styled.value(PropertyId::Height), px(2.0));
+        // レイヤーの順序は詳細度より優先される
+        assert_eq!(styled.value(PropertyId::MarginTop), px(3.0));
+        assert_eq!(styled.value(PropertyId::BorderTopWidth), px(1.0));
+
+        assert_eq!(styled.value(PropertyId::PaddingTop), px(0.0));
+        assert_eq!(styled.value(PropertyId::PaddingLeft), px(1.0));
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_parallel_style_tree() {
+        let items = "<li class=\"a\"><p>x</p></li><li class=\"b\"></li>".repeat(50);
+        let root_node = html::parse(format!("<div><ul>{}</ul><ul>{}</ul></div>", items, items));
+        let stylesheets = [css::parse(
+            "ul > .a { margin-left: 1px; } div .b { color: red; } .a p { width: 50%; }".to_string(),
+        )];
+        let device = Device::new(800.0, 600.0);
+
+        let (sequential, sequential_stats) =
+            style_tree_sequential_with_stats(&root_node, &stylesheets, &device);
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(4)
+            .build()
+            .unwrap();
+        let (parallel, stats) =
+            pool.install(|| style_tree_with_stats(&root_node, &stylesheets, &device));
+        assert_eq!(parallel, sequential);
+        assert_eq!(stats.sharing_hits + stats.sharing_misses, 1 + 2 + 2 * 150);
+        // 並列の走査では `PARALLEL_CHUNK_SIZE` 個の兄弟ごとにキャッシュが分かれる
+        assert_eq!(
+            sequential_stats.sharing_hits + sequential_stats.sharing_misses,
+            1 + 2 + 2 * 150
         );
+        assert!(sequential_stats.sharing_hits >= stats.sharing_hits);
     }
 }
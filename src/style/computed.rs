@@ -0,0 +1,667 @@
+//! 指定値から計算値を求める
+//!
+//! https://www.w3.org/TR/css-cascade-4/#computed
+
+use crate::{
+    css::{
+        calc::CalcNode, content::ContentFunction, properties::PropertyId, Color, LengthContext,
+        Value,
+    },
+    font::{self, Font},
+};
+
+use super::{Display, PropertyMap};
+
+/// レイアウトとペイントが読む、要素の計算値
+///
+/// 長さはすべてpx単位に解決してある。パーセンテージはコンテナブロックの大きさが
+/// 決まるレイアウトの時点で解決する
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComputedStyle {
+    pub display: Display,
+    pub width: LengthPercentageOrAuto,
+    pub height: LengthPercentageOrAuto,
+    pub margin: Sides<LengthPercentageOrAuto>,
+    pub padding: Sides<LengthPercentage>,
+    /// `border-style` が `none` か `hidden` の辺は0
+    pub border_width: Sides<f32>,
+    pub border_color: Sides<Color>,
+    pub color: Color,
+    pub background_color: Color,
+    /// 優先する順
+    pub font_family: Vec<FontFamily>,
+    pub font_size: f32,
+    /// 1から1000までの数値
+    pub font_weight: f32,
+    pub font_style: FontStyle,
+    pub line_height: LineHeight,
+    pub text_align: TextAlign,
+    pub white_space: WhiteSpace,
+    /// `word-break: break-word` は `normal` にして、`overflow_wrap` を `anywhere` にする
+    pub word_break: WordBreak,
+    pub line_break: LineBreak,
+    pub overflow_wrap: OverflowWrap,
+    pub hyphens: Hyphens,
+    /// `normal` と `none` は空
+    pub content: Vec<ContentItem>,
+    /// カウンタ名と値の組。`none` は空
+    pub counter_reset: Vec<(String, i32)>,
+    pub counter_increment: Vec<(String, i32)>,
+    pub list_style_type: ListStyleType,
+    pub list_style_position: ListStylePosition,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Sides<T> {
+    pub top: T,
+    pub right: T,
+    pub bottom: T,
+    pub left: T,
+}
+
+/// `content` の1つの成分値
+///
+/// https://www.w3.org/TR/css-content-3/#content-property
+#[derive(Debug, Clone, PartialEq)]
+pub enum ContentItem {
+    String(String),
+    Function(ContentFunction),
+    OpenQuote,
+    CloseQuote,
+    /// 引用符を表示せずに入れ子の深さだけを変える
+    NoOpenQuote,
+    NoCloseQuote,
+}
+
+/// https://www.w3.org/TR/css-fonts-4/#font-family-prop
+#[derive(Debug, Clone, PartialEq)]
+pub enum FontFamily {
+    Named(String),
+    /// `serif` や `sans-serif` などの総称ファミリー
+    Generic(String),
+}
+
+/// https://www.w3.org/TR/css-fonts-4/#font-style-prop
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FontStyle {
+    Normal,
+    Italic,
+    Oblique,
+}
+
+/// https://www.w3.org/TR/css-inline-3/#line-height-property
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineHeight {
+    /// フォントのメトリクスから決める
+    Normal,
+    /// px。数値の `line-height` もこの要素のフォントサイズを掛けてpxにしてある
+    Length(f32),
+}
+
+/// https://www.w3.org/TR/css-text-3/#text-align-property
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextAlign {
+    Start,
+    End,
+    Left,
+    Right,
+    Center,
+    Justify,
+}
+
+/// https://www.w3.org/TR/css-text-3/#white-space-property
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WhiteSpace {
+    Normal,
+    Pre,
+    Nowrap,
+    PreWrap,
+    PreLine,
+}
+
+impl WhiteSpace {
+    /// 連続する空白とタブを1つの空白にまとめるかどうか
+    pub fn collapses_spaces(self) -> bool {
+        matches!(
+            self,
+            WhiteSpace::Normal | WhiteSpace::Nowrap | WhiteSpace::PreLine
+        )
+    }
+
+    /// 改行文字で行を改めるかどうか。そうでなければ改行は空白として扱う
+    pub fn preserves_newlines(self) -> bool {
+        !matches!(self, WhiteSpace::Normal | WhiteSpace::Nowrap)
+    }
+
+    /// 行の幅に合わせて折り返すかどうか
+    pub fn wraps(self) -> bool {
+        !matches!(self, WhiteSpace::Pre | WhiteSpace::Nowrap)
+    }
+}
+
+/// https://www.w3.org/TR/css-text-3/#word-break-property
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WordBreak {
+    Normal,
+    /// 単語の中で折り返さない。CJK の文字の間でも折り返さない
+    KeepAll,
+    /// 文字の間ならどこでも折り返す
+    BreakAll,
+}
+
+/// https://www.w3.org/TR/css-text-3/#line-break-property
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineBreak {
+    /// `normal` と同じ
+    Auto,
+    Loose,
+    Normal,
+    Strict,
+    /// 禁則を無視して、文字の間ならどこでも折り返す
+    Anywhere,
+}
+
+/// https://www.w3.org/TR/css-text-3/#overflow-wrap-property
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OverflowWrap {
+    Normal,
+    /// 行に収まらない単語を任意の位置で折り返す
+    Anywhere,
+    /// `anywhere` と違い最小内容寸法に影響しないが、それを計算しないので同じになる
+    BreakWord,
+}
+
+/// https://www.w3.org/TR/css-text-3/#hyphens-property
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Hyphens {
+    /// ソフトハイフンでも折り返さない
+    None,
+    /// ソフトハイフンの位置でだけ折り返す
+    Manual,
+    /// 辞書を持たないので `manual` と同じ
+    Auto,
+}
+
+/// https://www.w3.org/TR/css-lists-3/#text-markers
+#[derive(Debug, Clone, PartialEq)]
+pub enum ListStyleType {
+    /// カウンタスタイルの名前
+    CounterStyle(String),
+    /// 文字列をそのままマーカーにする
+    String(String),
+    None,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ListStylePosition {
+    /// マーカーを内容の最初のインラインボックスにする
+    Inside,
+    /// マーカーを主ボックスの外に置く
+    Outside,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LengthPercentage {
+    /// px
+    Length(f32),
+    Percentage(f32),
+    /// パーセンテージを含む式。相対長さはpxに変換してある
+    Calc(CalcNode),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LengthPercentageOrAuto {
+    LengthPercentage(LengthPercentage),
+    Auto,
+}
+
+impl LengthPercentage {
+    /// パーセンテージを `percent_base` に対して解決したpx数
+    pub fn resolve(&self, percent_base: f32) -> f32 {
+        match *self {
+            LengthPercentage::Length(px) => px,
+            LengthPercentage::Percentage(f) => f / 100.0 * percent_base,
+            LengthPercentage::Calc(ref calc) => calc.to_px(&LengthContext {
+                percent_base,
+                ..LengthContext::default()
+            }),
+        }
+    }
+
+    pub fn has_percentage(&self) -> bool {
+        match *self {
+            LengthPercentage::Length(_) => false,
+            LengthPercentage::Percentage(_) => true,
+            LengthPercentage::Calc(ref calc) => calc.has_percentage(),
+        }
+    }
+}
+
+impl LengthPercentageOrAuto {
+    /// `auto` なら `None`
+    pub fn resolve(&self, percent_base: f32) -> Option<f32> {
+        match *self {
+            LengthPercentageOrAuto::LengthPercentage(ref length) => {
+                Some(length.resolve(percent_base))
+            }
+            LengthPercentageOrAuto::Auto => None,
+        }
+    }
+}
+
+/// `font-weight: normal` の太さ
+pub const NORMAL_FONT_WEIGHT: f32 = 400.0;
+
+impl Default for ComputedStyle {
+    /// どのプロパティも指定されていないルート要素の計算値
+    fn default() -> ComputedStyle {
+        let black = Color {
+            r: 0,
+            g: 0,
+            b: 0,
+            a: 255,
+        };
+        let zero = LengthPercentage::Length(0.0);
+        ComputedStyle {
+            display: Display::Inline,
+            width: LengthPercentageOrAuto::Auto,
+            height: LengthPercentageOrAuto::Auto,
+            margin: Sides {
+                top: LengthPercentageOrAuto::LengthPercentage(zero.clone()),
+                right: LengthPercentageOrAuto::LengthPercentage(zero.clone()),
+                bottom: LengthPercentageOrAuto::LengthPercentage(zero.clone()),
+                left: LengthPercentageOrAuto::LengthPercentage(zero.clone()),
+            },
+            padding: Sides {
+                top: zero.clone(),
+                right: zero.clone(),
+                bottom: zero.clone(),
+                left: zero,
+            },
+            border_width: Sides::default(),
+            border_color: Sides {
+                top: black,
+                right: black,
+                bottom: black,
+                left: black,
+            },
+            color: black,
+            background_color: Color {
+                r: 0,
+                g: 0,
+                b: 0,
+                a: 0,
+            },
+            font_family: vec![FontFamily::Generic("serif".to_string())],
+            font_size: super::MEDIUM_FONT_SIZE,
+            font_weight: NORMAL_FONT_WEIGHT,
+            font_style: FontStyle::Normal,
+            line_height: LineHeight::Normal,
+            text_align: TextAlign::Start,
+            white_space: WhiteSpace::Normal,
+            word_break: WordBreak::Normal,
+            line_break: LineBreak::Auto,
+            overflow_wrap: OverflowWrap::Normal,
+            hyphens: Hyphens::Manual,
+            content: Vec::new(),
+            counter_reset: Vec::new(),
+            counter_increment: Vec::new(),
+            list_style_type: ListStyleType::CounterStyle("disc".to_string()),
+            list_style_position: ListStylePosition::Outside,
+        }
+    }
+}
+
+impl ComputedStyle {
+    /// `font-family`、`font-weight`、`font-style` に最も合うフォント
+    pub fn font(&self) -> &'static Font {
+        font::database().query(&self.font_family, self.font_weight, self.font_style)
+    }
+
+    /// 継承と初期値を適用した後の値 `values` から計算値を求める
+    ///
+    /// `values` の `font-size` と `font-weight` は解決済みで、`ctx.font_size` は
+    /// この要素のフォントサイズ。`ex` と `ch` はこの要素のフォントを基準にする
+    pub fn compute(values: &PropertyMap, ctx: &LengthContext) -> ComputedStyle {
+        let value = |id: PropertyId| &values[&id];
+        let font_family = font_family(value(PropertyId::FontFamily));
+        let font_style = match *value(PropertyId::FontStyle) {
+            Value::Keyword(ref k) if k == "italic" => FontStyle::Italic,
+            Value::Keyword(ref k) if k == "oblique" => FontStyle::Oblique,
+            _ => FontStyle::Normal,
+        };
+        let font_weight = match *value(PropertyId::FontWeight) {
+            Value::Number(weight) => weight,
+            _ => NORMAL_FONT_WEIGHT,
+        };
+        let ctx = &ctx.with_font(font::database().query(&font_family, font_weight, font_style));
+        let padding = |id| length_percentage(value(id), ctx).unwrap_or_default();
+        let size = |id| match length_percentage(value(id), ctx) {
+            Some(length) => LengthPercentageOrAuto::LengthPercentage(length),
+            None => LengthPercentageOrAuto::Auto,
+        };
+        let color = match *value(PropertyId::Color) {
+            Value::ColorValue(color) => color,
+            _ => ComputedStyle::default().color,
+        };
+        let color_of = |id| match *value(id) {
+            Value::ColorValue(color) => color,
+            // `currentcolor`
+            _ => color,
+        };
+        let border_width = |width, style| match *value(style) {
+            Value::Keyword(ref k) if k == "none" || k == "hidden" => 0.0,
+            _ => border_width(value(width), ctx),
+        };
+
+        ComputedStyle {
+            display: display(value(PropertyId::Display)),
+            width: size(PropertyId::Width),
+            height: size(PropertyId::Height),
+            margin: Sides {
+                top: size(PropertyId::MarginTop),
+                right: size(PropertyId::MarginRight),
+                bottom: size(PropertyId::MarginBottom),
+                left: size(PropertyId::MarginLeft),
+            },
+            padding: Sides {
+                top: padding(PropertyId::PaddingTop),
+                right: padding(PropertyId::PaddingRight),
+                bottom: padding(PropertyId::PaddingBottom),
+                left: padding(PropertyId::PaddingLeft),
+            },
+            border_width: Sides {
+                top: border_width(PropertyId::BorderTopWidth, PropertyId::BorderTopStyle),
+                right: border_width(PropertyId::BorderRightWidth, PropertyId::BorderRightStyle),
+                bottom: border_width(PropertyId::BorderBottomWidth, PropertyId::BorderBottomStyle),
+                left: border_width(PropertyId::BorderLeftWidth, PropertyId::BorderLeftStyle),
+            },
+            border_color: Sides {
+                top: color_of(PropertyId::BorderTopColor),
+                right: color_of(PropertyId::BorderRightColor),
+                bottom: color_of(PropertyId::BorderBottomColor),
+                left: color_of(PropertyId::BorderLeftColor),
+            },
+            color,
+            background_color: color_of(PropertyId::BackgroundColor),
+            font_family,
+            font_size: ctx.font_size,
+            font_style,
+            font_weight,
+            line_height: match *value(PropertyId::LineHeight) {
+                Value::Number(factor) => LineHeight::Length(factor * ctx.font_size),
+                Value::Length(..) => LineHeight::Length(value(PropertyId::LineHeight).to_px(ctx)),
+                _ => LineHeight::Normal,
+            },
+            text_align: match *value(PropertyId::TextAlign) {
+                Value::Keyword(ref k) => match &**k {
+                    "end" => TextAlign::End,
+                    "left" => TextAlign::Left,
+                    "right" => TextAlign::Right,
+                    "center" => TextAlign::Center,
+                    "justify" => TextAlign::Justify,
+                    _ => TextAlign::Start,
+                },
+                _ => TextAlign::Start,
+            },
+            white_space: match *value(PropertyId::WhiteSpace) {
+                Value::Keyword(ref k) => match &**k {
+                    "pre" => WhiteSpace::Pre,
+                    "nowrap" => WhiteSpace::Nowrap,
+                    "pre-wrap" => WhiteSpace::PreWrap,
+                    "pre-line" => WhiteSpace::PreLine,
+                    _ => WhiteSpace::Normal,
+                },
+                _ => WhiteSpace::Normal,
+            },
+            word_break: match *value(PropertyId::WordBreak) {
+                Value::Keyword(ref k) if k == "keep-all" => WordBreak::KeepAll,
+                Value::Keyword(ref k) if k == "break-all" => WordBreak::BreakAll,
+                _ => WordBreak::Normal,
+            },
+            line_break: match *value(PropertyId::LineBreak) {
+                Value::Keyword(ref k) => match &**k {
+                    "loose" => LineBreak::Loose,
+                    "normal" => LineBreak::Normal,
+                    "strict" => LineBreak::Strict,
+                    "anywhere" => LineBreak::Anywhere,
+                    _ => LineBreak::Auto,
+                },
+                _ => LineBreak::Auto,
+            },
+            overflow_wrap: match (
+                value(PropertyId::OverflowWrap),
+                value(PropertyId::WordBreak),
+            ) {
+                (_, Value::Keyword(k)) if k == "break-word" => OverflowWrap::Anywhere,
+                (Value::Keyword(k), _) if k == "anywhere" => OverflowWrap::Anywhere,
+                (Value::Keyword(k), _) if k == "break-word" => OverflowWrap::BreakWord,
+                _ => OverflowWrap::Normal,
+            },
+            hyphens: match *value(PropertyId::Hyphens) {
+                Value::Keyword(ref k) if k == "none" => Hyphens::None,
+                Value::Keyword(ref k) if k == "auto" => Hyphens::Auto,
+                _ => Hyphens::Manual,
+            },
+            content: content(value(PropertyId::Content)),
+            counter_reset: counters(value(PropertyId::CounterReset), 0),
+            counter_increment: counters(value(PropertyId::CounterIncrement), 1),
+            list_style_type: match *value(PropertyId::ListStyleType) {
+                Value::String(ref s) => ListStyleType::String(s.clone()),
+                Value::Keyword(ref k) if k == "none" => ListStyleType::None,
+                Value::Keyword(ref k) => ListStyleType::CounterStyle(k.clone()),
+                _ => ListStyleType::None,
+            },
+            list_style_position: match *value(PropertyId::ListStylePosition) {
+                Value::Keyword(ref k) if k == "inside" => ListStylePosition::Inside,
+                _ => ListStylePosition::Outside,
+            },
+        }
+    }
+}
+
+impl Default for LengthPercentage {
+    fn default() -> LengthPercentage {
+        LengthPercentage::Length(0.0)
+    }
+}
+
+/// 長さとパーセンテージ以外 (`auto` など) は `None`
+fn length_percentage(value: &Value, ctx: &LengthContext) -> Option<LengthPercentage> {
+    Some(match *value {
+        Value::Length(..) => LengthPercentage::Length(value.to_px(ctx)),
+        Value::Number(0.0) => LengthPercentage::Length(0.0),
+        Value::Percentage(f) => LengthPercentage::Percentage(f),
+        Value::Calc(ref calc) if calc.has_percentage() => {
+            LengthPercentage::Calc(calc.absolutize(ctx))
+        }
+        Value::Calc(ref calc) => LengthPercentage::Length(calc.to_px(ctx)),
+        _ => return None,
+    })
+}
+
+/// https://www.w3.org/TR/css-backgrounds-3/#border-width
+fn border_width(value: &Value, ctx: &LengthContext) -> f32 {
+    match *value {
+        Value::Keyword(ref k) => match &**k {
+            "thin" => 1.0,
+            "thick" => 5.0,
+            _ => 3.0,
+        },
+        _ => value.to_px(ctx),
+    }
+}
+
+fn content(value: &Value) -> Vec<ContentItem> {
+    let values = match *value {
+        Value::List(ref values) => &values[..],
+        _ => std::slice::from_ref(value),
+    };
+    values
+        .iter()
+        .filter_map(|value| match *value {
+            Value::String(ref s) => Some(ContentItem::String(s.clone())),
+            Value::Content(ref function) => Some(ContentItem::Function(function.clone())),
+            Value::Keyword(ref k) => match &**k {
+                "open-quote" => Some(ContentItem::OpenQuote),
+                "close-quote" => Some(ContentItem::CloseQuote),
+                "no-open-quote" => Some(ContentItem::NoOpenQuote),
+                "no-close-quote" => Some(ContentItem::NoCloseQuote),
+                // `normal` と `none`
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect()
+}
+
+/// https://www.w3.org/TR/css-fonts-4/#generic-font-families
+const GENERIC_FAMILIES: [&str; 9] = [
+    "serif",
+    "sans-serif",
+    "cursive",
+    "fantasy",
+    "monospace",
+    "system-ui",
+    "emoji",
+    "math",
+    "fangsong",
+];
+
+/// 引用符のない名前は空白で区切られた識別子をつなげたもの
+fn font_family(value: &Value) -> Vec<FontFamily> {
+    let items = match *value {
+        Value::CommaList(ref items) => &items[..],
+        _ => std::slice::from_ref(value),
+    };
+    items
+        .iter()
+        .filter_map(|item| match *item {
+            Value::String(ref name) => Some(FontFamily::Named(name.clone())),
+            Value::Keyword(ref k) if GENERIC_FAMILIES.contains(&&*k.to_ascii_lowercase()) => {
+                Some(FontFamily::Generic(k.to_ascii_lowercase()))
+            }
+            Value::Keyword(ref name) => Some(FontFamily::Named(name.clone())),
+            Value::List(ref words) => {
+                let words: Option<Vec<&str>> = words
+                    .iter()
+                    .map(|word| match *word {
+                        Value::Keyword(ref k) => Some(&**k),
+                        _ => None,
+                    })
+                    .collect();
+                Some(FontFamily::Named(words?.join(" ")))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// `counter-reset` と `counter-increment` の値。整数を省略したカウンタは `default`
+fn counters(value: &Value, default: i32) -> Vec<(String, i32)> {
+    let values = match *value {
+        Value::List(ref values) => &values[..],
+        _ => std::slice::from_ref(value),
+    };
+    let mut counters: Vec<(String, i32)> = Vec::new();
+    for value in values {
+        match *value {
+            Value::Keyword(ref name) if name == "none" => {}
+            Value::Keyword(ref name) => counters.push((name.clone(), default)),
+            Value::Number(n) => {
+                if let Some(last) = counters.last_mut() {
+                    last.1 = n as i32;
+                }
+            }
+            _ => {}
+        }
+    }
+    counters
+}
+
+fn display(value: &Value) -> Display {
+    match *value {
+        Value::Keyword(ref s) => match &**s {
+            "list-item" => Display::ListItem,
+            // 表のレイアウトは未実装なので、ブロックとして扱う
+            "block" | "flow-root" | "table" | "table-caption" | "table-header-group"
+            | "table-row-group" | "table-footer-group" | "table-row" | "table-cell"
+            | "table-column-group" | "table-column" => Display::Block,
+            "none" => Display::None,
+            _ => Display::Inline,
+        },
+        _ => Display::Inline,
+    }
+}
+
+/// 親の太さ `parent` に対する `bolder` と `lighter` の太さ
+///
+/// https://www.w3.org/TR/css-fonts-4/#relative-weights
+pub fn relative_font_weight(keyword: &str, parent: f32) -> f32 {
+    match keyword {
+        "bolder" if parent < 350.0 => 400.0,
+        "bolder" if parent < 550.0 => 700.0,
+        "bolder" if parent < 900.0 => 900.0,
+        "lighter" if parent < 100.0 => parent,
+        "lighter" if parent < 550.0 => 100.0,
+        "lighter" if parent < 750.0 => 400.0,
+        "lighter" => 700.0,
+        _ => parent,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        css::{self, media::Device},
+        html,
+        style::style_tree,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_computed_style() {
+        let root_node = html::parse(r#"<div class="a"><p class="b"></p></div>"#.to_string());
+        let stylesheets = [css::parse(
+            r#"
+            .a { font-size: 10px; font-weight: bold; color: red; border: medium solid; }
+            .b {
+                font-size: larger;
+                font-weight: bolder;
+                margin: 1em auto 5% calc(50% - 2em);
+                border-top: thick none blue;
+                background-color: currentcolor;
+            }
+            "#
+            .to_string(),
+        )];
+        let styled = style_tree(&root_node, &stylesheets, &Device::new(800.0, 600.0));
+        let red = Color {
+            r: 255,
+            g: 0,
+            b: 0,
+            a: 255,
+        };
+
+        let a = &styled.computed;
+        assert_eq!(a.display, Display::Block);
+        assert_eq!(a.border_width.left, 3.0);
+        assert_eq!(a.border_color.left, red);
+        assert_eq!(a.font_weight, 700.0);
+
+        let b = &styled.children[0].computed;
+        assert_eq!(b.font_size, 12.0);
+        assert_eq!(b.font_weight, 900.0);
+        assert_eq!(b.color, red);
+        assert_eq!(b.background_color, red);
+        assert_eq!(b.border_width.top, 0.0);
+        assert_eq!(
+            b.margin.top,
+            LengthPercentageOrAuto::LengthPercentage(LengthPercentage::Length(12.0))
+        );
+        assert_eq!(b.margin.right, LengthPercentageOrAuto::Auto);
+        assert_eq!(b.margin.bottom.resolve(200.0), Some(10.0));
+        assert_eq!(b.margin.left.resolve(200.0), Some(76.0));
+    }
+}
@@ -0,0 +1,339 @@
+//! DOM とスタイルシートの変更から、スタイルを求め直す範囲を決める
+//!
+//! セレクタが依存する ID、クラス、擬似クラスを、主語の複合セレクタに現れるものと、それより左の
+//! 複合セレクタに現れるものに分けて覚えておく。前者が変わった要素はその要素だけを、
+//! 後者が変わった要素は子孫も含めて求め直す。追加または削除されたスタイルシートは、
+//! その規則に一致する要素だけを求め直す
+//!
+//! 求め直した要素のスタイルが前回と変わった場合は、継承する値が変わるので子孫も求め直す
+
+use std::collections::HashSet;
+
+use crate::{
+    css::{PseudoClass, Selector},
+    dom::{ElementData, Node},
+};
+
+use super::matching::matches_pseudo_class;
+
+use super::{matching_rules, Traversal};
+
+/// スタイルを求め直す範囲 (後ろほど広い)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(super) enum RestyleScope {
+    /// 前回のスタイルをそのまま使う
+    None,
+    /// この要素だけ
+    Element,
+    /// この要素と、すべての子孫
+    Subtree,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash)]
+enum Dependency {
+    Id(String),
+    Class(String),
+    PseudoClass(PseudoClass),
+}
+
+const PSEUDO_CLASSES: [PseudoClass; 7] = [
+    PseudoClass::Hover,
+    PseudoClass::Active,
+    PseudoClass::Focus,
+    PseudoClass::FocusVisible,
+    PseudoClass::Checked,
+    PseudoClass::Disabled,
+    PseudoClass::Enabled,
+];
+
+/// セレクタが依存する ID、クラス、擬似クラス
+#[derive(Default)]
+pub(super) struct InvalidationMap {
+    /// 主語の複合セレクタに現れるもの
+    subject: HashSet<Dependency>,
+    /// 主語より左の複合セレクタに現れるもの
+    ancestor: HashSet<Dependency>,
+}
+
+impl InvalidationMap {
+    pub(super) fn new<'a>(selectors: impl Iterator<Item = &'a Selector>) -> InvalidationMap {
+        let mut map = InvalidationMap::default();
+        for selector in selectors {
+            for (i, compound) in selector.compounds().enumerate() {
+                let dependencies = if i == 0 {
+                    &mut map.subject
+                } else {
+                    &mut map.ancestor
+                };
+                dependencies.extend(compound.id.iter().cloned().map(Dependency::Id));
+                dependencies.extend(compound.class.iter().cloned().map(Dependency::Class));
+                dependencies.extend(
+                    compound
+                        .pseudo_classes
+                        .iter()
+                        .copied()
+                        .map(Dependency::PseudoClass),
+                );
+            }
+        }
+        map
+    }
+
+    /// `changed` が変わった要素で、求め直す範囲
+    fn scope(&self, changed: &[Dependency]) -> RestyleScope {
+        if changed.iter().any(|key| self.ancestor.contains(key)) {
+            RestyleScope::Subtree
+        } else if changed.iter().any(|key| self.subject.contains(key)) {
+            RestyleScope::Element
+        } else {
+            RestyleScope::None
+        }
+    }
+}
+
+/// 前回スタイルを求めたときから変わった、要素の ID、クラス、擬似クラス
+///
+/// `:disabled` などは属性から決まるので、擬似クラスは属性と状態の両方の変更から求める
+fn changed_dependencies(node: &Node, elem: &ElementData) -> Vec<Dependency> {
+    let mut changed = Vec::new();
+    if node.dirty.old_attributes.is_none() && node.dirty.old_state.is_none() {
+        return changed;
+    }
+    let old_elem = ElementData {
+        tag_name: elem.tag_name.clone(),
+        attributes: node
+            .dirty
+            .old_attributes
+            .clone()
+            .unwrap_or_else(|| elem.attributes.clone()),
+        state: node.dirty.old_state.unwrap_or(elem.state),
+    };
+    if node.dirty.old_attributes.is_some() {
+        if old_elem.id() != elem.id() {
+            changed.extend(old_elem.id().cloned().map(Dependency::Id));
+            changed.extend(elem.id().cloned().map(Dependency::Id));
+        }
+        let (old_classes, classes) = (old_elem.classes(), elem.classes());
+        changed.extend(
+            old_classes
+                .symmetric_difference(&classes)
+                .map(|class| Dependency::Class(class.to_string())),
+        );
+    }
+    changed.extend(
+        PSEUDO_CLASSES
+            .into_iter()
+            .filter(|&pseudo_class| {
+                matches_pseudo_class(&old_elem, pseudo_class)
+                    != matches_pseudo_class(elem, pseudo_class)
+            })
+            .map(Dependency::PseudoClass),
+    );
+    changed
+}
+
+/// 前回スタイルを求めた要素 `node` で、求め直す範囲
+pub(super) fn restyle_scope(
+    node: &Node,
+    elem: &ElementData,
+    traversal: &Traversal,
+) -> RestyleScope {
+    let mut scope = traversal
+        .rules
+        .invalidation
+        .scope(&changed_dependencies(node, elem));
+    if let Some(changed_rules) = traversal.changed_rules {
+        let pseudo_elements = changed_rules.pseudo_elements.iter().copied().map(Some);
+        if std::iter::once(None)
+            .chain(pseudo_elements)
+            .any(|pseudo_element| {
+                !matching_rules(elem, &traversal.ancestors, changed_rules, pseudo_element)
+                    .is_empty()
+            })
+        {
+            scope = scope.max(RestyleScope::Element);
+        }
+    }
+    scope
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        css::{self, media::Device, properties::PropertyId, Color, Value},
+        html,
+        style::{restyle, style_tree_with_stats, StyleStats},
+    };
+
+    fn color(r: u8, g: u8, b: u8) -> Value {
+        Value::ColorValue(Color { r, g, b, a: 255 })
+    }
+
+    #[test]
+    fn test_restyle_attribute_change() {
+        let mut root_node = html::parse(
+            r#"<div><ul class="list"><li><p></p></li><li></li></ul><p class="x"></p><p id="y"></p></div>"#
+                .to_string(),
+        );
+        let stylesheets = [css::parse(
+            ".open li { color: red; } .x { color: blue; } #z { color: lime; }".to_string(),
+        )];
+        let device = Device::new(800.0, 600.0);
+        let (styled, stats) = style_tree_with_stats(&root_node, &stylesheets, &device);
+        assert_eq!(stats.sharing_hits + stats.sharing_misses, 7);
+        let mut cache = styled.into_cache();
+
+        // 先祖の位置に現れるクラスが変わると、子孫も求め直す
+        root_node.children[0].set_attribute("class", "list open");
+        let (styled, stats) = restyle(&root_node, &stylesheets, &device, cache, &[]);
+        assert_eq!(stats.reused, 3);
+        assert_eq!(stats.sharing_hits + stats.sharing_misses, 4);
+        let ul = &styled.children[0];
+        assert_eq!(ul.children[0].value(PropertyId::Color), color(255, 0, 0));
+        assert_eq!(
+            ul.children[0].children[0].value(PropertyId::Color),
+            color(255, 0, 0)
+        );
+        cache = styled.into_cache();
+        root_node.clear_dirty();
+
+        // 主語に現れるクラスと ID だけを求め直し、どのセレクタも使わない属性は無視する
+        root_node.children[1].set_attribute("class", "");
+        root_node.children[2].set_attribute("id", "z");
+        root_node.children[2].set_attribute("title", "z");
+        let (styled, stats) = restyle(&root_node, &stylesheets, &device, cache, &[]);
+        assert_eq!(
+            stats,
+            StyleStats {
+                sharing_hits: 0,
+                sharing_misses: 2,
+                reused: 5,
+            }
+        );
+        assert_eq!(styled.children[1].value(PropertyId::Color), color(0, 0, 0));
+        assert_eq!(
+            styled.children[2].value(PropertyId::Color),
+            color(0, 255, 0)
+        );
+        assert_eq!(
+            styled,
+            style_tree_with_stats(&root_node, &stylesheets, &device).0
+        );
+    }
+
+    #[test]
+    fn test_restyle_state_change() {
+        let mut root_node = html::parse(
+            r#"<div><p class="a"><span></span></p><p></p><input></input></div>"#.to_string(),
+        );
+        let stylesheets = [css::parse(
+            "p:hover { color: red; } .a:active span { color: blue; } :focus-visible { color: lime; }"
+                .to_string(),
+        )];
+        let device = Device::new(800.0, 600.0);
+        let cache = style_tree_with_stats(&root_node, &stylesheets, &device)
+            .0
+            .into_cache();
+
+        // 主語に現れる擬似クラスはその要素だけ、先祖の位置に現れるものは子孫も求め直す
+        root_node.children[0].update_state(|state| state.active = true);
+        root_node.children[1].update_state(|state| state.hover = true);
+        root_node.children[2].update_state(|state| state.focus_visible = true);
+        let (styled, stats) = restyle(&root_node, &stylesheets, &device, cache, &[]);
+        assert_eq!(stats.reused, 2);
+        assert_eq!(styled.children[0].value(PropertyId::Color), color(0, 0, 0));
+        assert_eq!(
+            styled.children[0].children[0].value(PropertyId::Color),
+            color(0, 0, 255)
+        );
+        assert_eq!(
+            styled.children[1].value(PropertyId::Color),
+            color(255, 0, 0)
+        );
+        // `:focus-visible` には `:focus` も要る
+        assert_eq!(styled.children[2].value(PropertyId::Color), color(0, 0, 0));
+
+        let cache = styled.into_cache();
+        root_node.clear_dirty();
+        root_node.children[2].update_state(|state| state.focus = true);
+        let (styled, stats) = restyle(&root_node, &stylesheets, &device, cache, &[]);
+        assert_eq!(stats.reused, 4);
+        assert_eq!(
+            styled.children[2].value(PropertyId::Color),
+            color(0, 255, 0)
+        );
+        assert_eq!(
+            styled,
+            style_tree_with_stats(&root_node, &stylesheets, &device).0
+        );
+    }
+
+    #[test]
+    fn test_restyle_disabled_attribute() {
+        let mut root_node = html::parse(
+            r#"<div><div disabled=""></div><input></input><button disabled=""></button></div>"#
+                .to_string(),
+        );
+        let stylesheets = [css::parse(
+            ":disabled { color: red; } :enabled { color: blue; }".to_string(),
+        )];
+        let device = Device::new(800.0, 600.0);
+        let (styled, _) = style_tree_with_stats(&root_node, &stylesheets, &device);
+        let colors = |styled: &crate::style::StyledNode| -> Vec<Value> {
+            styled
+                .children
+                .iter()
+                .map(|child| child.value(PropertyId::Color))
+                .collect()
+        };
+        // フォームの要素でなければどちらにも一致しない
+        assert_eq!(
+            colors(&styled),
+            vec![color(0, 0, 0), color(0, 0, 255), color(255, 0, 0)]
+        );
+
+        let cache = styled.into_cache();
+        root_node.clear_dirty();
+        root_node.children[1].set_attribute("disabled", "");
+        root_node.children[2].remove_attribute("disabled");
+        let (styled, stats) = restyle(&root_node, &stylesheets, &device, cache, &[]);
+        assert_eq!(stats.reused, 2);
+        assert_eq!(
+            colors(&styled),
+            vec![color(0, 0, 0), color(255, 0, 0), color(0, 0, 255)]
+        );
+    }
+
+    #[test]
+    fn test_restyle_stylesheet_change() {
+        let mut root_node =
+            html::parse(r#"<div><p class="a"><span></span></p><p></p><em></em></div>"#.to_string());
+        let mut stylesheets = vec![css::parse("p { width: 10px; }".to_string())];
+        let device = Device::new(800.0, 600.0);
+        let cache = style_tree_with_stats(&root_node, &stylesheets, &device)
+            .0
+            .into_cache();
+
+        stylesheets.push(css::parse("div .a { color: red; }".to_string()));
+        let added = css::parse("div .a { color: red; }".to_string());
+        let (styled, stats) = restyle(&root_node, &stylesheets, &device, cache, &[&added]);
+        assert_eq!(stats.reused, 3);
+        assert_eq!(stats.sharing_hits + stats.sharing_misses, 2);
+        assert_eq!(
+            styled.children[0].children[0].value(PropertyId::Color),
+            color(255, 0, 0)
+        );
+        assert_eq!(styled.children[1].value(PropertyId::Color), color(0, 0, 0));
+
+        // 子の追加
+        let cache = styled.into_cache();
+        root_node.children[1].append_child(html::parse("<b></b>".to_string()));
+        let (styled, stats) = restyle(&root_node, &stylesheets, &device, cache, &[]);
+        assert_eq!(stats.reused, 5);
+        assert_eq!(stats.sharing_hits + stats.sharing_misses, 1);
+        assert_eq!(
+            styled,
+            style_tree_with_stats(&root_node, &stylesheets, &device).0
+        );
+    }
+}
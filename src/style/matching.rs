@@ -0,0 +1,362 @@
+//! セレクタの照合と、要素ごとに照合する規則の絞り込み
+//!
+//! 規則は最も右の複合セレクタのID、クラス、タグ名で表に分けておき、要素は自身の
+//! ID、クラス、タグ名の表と全称の表にある規則だけを照合する。先祖を要求するセレクタは、
+//! 先祖のID、クラス、タグ名を入れたブルームフィルタで照合の前に大半を弾く
+//!
+//! https://www.w3.org/TR/selectors-4/#match-against-element
+
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+};
+
+use crate::{
+    css::{Combinator, PseudoClass, Rule, Selector, SimpleSelector},
+    dom::ElementData,
+};
+
+/// 規則の番号と、その規則の中のセレクタの番号を、セレクタのキーで引く表
+#[derive(Default)]
+pub(super) struct SelectorMap {
+    by_id: HashMap<String, Vec<SelectorEntry>>,
+    by_class: HashMap<String, Vec<SelectorEntry>>,
+    by_tag: HashMap<String, Vec<SelectorEntry>>,
+    universal: Vec<SelectorEntry>,
+}
+
+struct SelectorEntry {
+    rule: usize,
+    selector: usize,
+    /// 先祖が持っていなければならないID、クラス、タグ名のハッシュ値 (一部)
+    ancestor_hashes: Vec<u32>,
+}
+
+/// ブルームフィルタに入れるハッシュ値の、セレクタごとの上限
+const MAX_ANCESTOR_HASHES: usize = 4;
+
+impl SelectorMap {
+    pub(super) fn new<'a>(rules: impl Iterator<Item = &'a Rule>) -> SelectorMap {
+        let mut map = SelectorMap::default();
+        for (rule, rule_data) in rules.enumerate() {
+            for (selector, selector_data) in rule_data.selectors.iter().enumerate() {
+                let entry = SelectorEntry {
+                    rule,
+                    selector,
+                    ancestor_hashes: selector_data
+                        .compounds()
+                        .skip(1)
+                        .flat_map(compound_hashes)
+                        .take(MAX_ANCESTOR_HASHES)
+                        .collect(),
+                };
+                // 最も右の複合セレクタの中で、一致する要素が少なそうなキーを選ぶ
+                let subject = selector_data.subject();
+                let bucket = if let Some(ref id) = subject.id {
+                    map.by_id.entry(id.clone()).or_default()
+                } else if let Some(class) = subject.class.first() {
+                    map.by_class.entry(class.clone()).or_default()
+                } else if let Some(ref tag_name) = subject.tag_name {
+                    map.by_tag.entry(tag_name.clone()).or_default()
+                } else {
+                    &mut map.universal
+                };
+                bucket.push(entry);
+            }
+        }
+        map
+    }
+
+    /// `elem` に一致するかもしれない `(規則の番号, セレクタの番号)` を昇順で返す
+    pub(super) fn candidates(
+        &self,
+        elem: &ElementData,
+        filter: &AncestorFilter,
+    ) -> Vec<(usize, usize)> {
+        let mut buckets = vec![&self.universal];
+        buckets.extend(elem.id().and_then(|id| self.by_id.get(id)));
+        buckets.extend(
+            elem.classes()
+                .iter()
+                .filter_map(|&class| self.by_class.get(class)),
+        );
+        buckets.extend(self.by_tag.get(&elem.tag_name));
+
+        let mut candidates: Vec<_> = buckets
+            .into_iter()
+            .flatten()
+            .filter(|entry| {
+                entry
+                    .ancestor_hashes
+                    .iter()
+                    .all(|&hash| filter.might_contain(hash))
+            })
+            .map(|entry| (entry.rule, entry.selector))
+            .collect();
+        // 表ごとに分かれているので、規則の順に並べ直す
+        candidates.sort_unstable();
+        candidates
+    }
+}
+
+/// スタイルを求めている要素の先祖
+#[derive(Clone, Default)]
+pub(super) struct Ancestors<'a> {
+    /// ルート要素から親要素への順
+    elements: Vec<&'a ElementData>,
+    filter: AncestorFilter,
+}
+
+impl<'a> Ancestors<'a> {
+    pub(super) fn push(&mut self, elem: &'a ElementData) {
+        for hash in element_hashes(elem) {
+            self.filter.insert(hash);
+        }
+        self.elements.push(elem);
+    }
+
+    pub(super) fn pop(&mut self) {
+        let elem = self.elements.pop().expect("no ancestor to pop");
+        for hash in element_hashes(elem) {
+            self.filter.remove(hash);
+        }
+    }
+
+    pub(super) fn filter(&self) -> &AncestorFilter {
+        &self.filter
+    }
+}
+
+/// フィルタの大きさ (2の累乗)
+const FILTER_SIZE: usize = 1 << 12;
+const FILTER_MASK: u32 = FILTER_SIZE as u32 - 1;
+
+/// 要素を取り除けるように、ビットの代わりに数を数えるブルームフィルタ
+///
+/// 1つのハッシュ値の下位12ビットと次の12ビットを2つの位置として使う
+#[derive(Clone)]
+pub(super) struct AncestorFilter {
+    counters: Box<[u8; FILTER_SIZE]>,
+}
+
+impl Default for AncestorFilter {
+    fn default() -> AncestorFilter {
+        AncestorFilter {
+            counters: Box::new([0; FILTER_SIZE]),
+        }
+    }
+}
+
+impl AncestorFilter {
+    fn indices(hash: u32) -> [usize; 2] {
+        [
+            (hash & FILTER_MASK) as usize,
+            ((hash >> 12) & FILTER_MASK) as usize,
+        ]
+    }
+
+    fn insert(&mut self, hash: u32) {
+        for index in AncestorFilter::indices(hash) {
+            self.counters[index] = self.counters[index].saturating_add(1);
+        }
+    }
+
+    fn remove(&mut self, hash: u32) {
+        for index in AncestorFilter::indices(hash) {
+            // 溢れたカウンタは正しい数が分からないので、減らさずに残す
+            if self.counters[index] != u8::MAX {
+                self.counters[index] -= 1;
+            }
+        }
+    }
+
+    /// `false` なら、どの先祖もこのハッシュ値のキーを持たない
+    fn might_contain(&self, hash: u32) -> bool {
+        AncestorFilter::indices(hash)
+            .iter()
+            .all(|&index| self.counters[index] != 0)
+    }
+}
+
+#[derive(Hash)]
+enum Key<'a> {
+    Id(&'a str),
+    Class(&'a str),
+    Tag(&'a str),
+}
+
+fn key_hash(key: Key) -> u32 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish() as u32
+}
+
+fn element_hashes(elem: &ElementData) -> Vec<u32> {
+    let mut keys = vec![Key::Tag(&elem.tag_name)];
+    keys.extend(elem.id().map(|id| Key::Id(id)));
+    keys.extend(elem.classes().into_iter().map(Key::Class));
+    keys.into_iter().map(key_hash).collect()
+}
+
+fn compound_hashes(selector: &SimpleSelector) -> Vec<u32> {
+    let mut keys: Vec<_> = selector.id.iter().map(|id| Key::Id(id)).collect();
+    keys.extend(selector.class.iter().map(|class| Key::Class(class)));
+    keys.extend(selector.tag_name.iter().map(|tag_name| Key::Tag(tag_name)));
+    keys.into_iter().map(key_hash).collect()
+}
+
+pub(super) fn matches(elem: &ElementData, ancestors: &Ancestors, selector: &Selector) -> bool {
+    match *selector {
+        Selector::Simple(ref simple_selector) => matches_simple_selector(elem, simple_selector),
+        Selector::Complex(ref complex) => {
+            matches_simple_selector(elem, &complex.subject)
+                && matches_ancestors(&complex.ancestors, &ancestors.elements)
+        }
+    }
+}
+
+/// 主語から左へ順に並んだ `compounds` が、ルートから順に並んだ `ancestors` に一致するか
+fn matches_ancestors(
+    compounds: &[(Combinator, SimpleSelector)],
+    ancestors: &[&ElementData],
+) -> bool {
+    let Some(((combinator, compound), rest)) = compounds.split_first() else {
+        return true;
+    };
+    match combinator {
+        Combinator::Child => ancestors.split_last().is_some_and(|(parent, ancestors)| {
+            matches_simple_selector(parent, compound) && matches_ancestors(rest, ancestors)
+        }),
+        Combinator::Descendant => (0..ancestors.len()).rev().any(|i| {
+            matches_simple_selector(ancestors[i], compound)
+                && matches_ancestors(rest, &ancestors[..i])
+        }),
+    }
+}
+
+fn matches_simple_selector(elem: &ElementData, selector: &SimpleSelector) -> bool {
+    if selector.tag_name.iter().any(|name| elem.tag_name != *name) {
+        return false;
+    }
+
+    if selector.id.iter().any(|id| elem.id() != Some(id)) {
+        return false;
+    }
+
+    if selector
+        .pseudo_classes
+        .iter()
+        .any(|&pseudo_class| !matches_pseudo_class(elem, pseudo_class))
+    {
+        return false;
+    }
+
+    let elem_classes = elem.classes();
+    if selector
+        .class
+        .iter()
+        .any(|class| !elem_classes.contains(&**class))
+    {
+        return false;
+    }
+
+    true
+}
+
+/// https://www.w3.org/TR/selectors-4/#useraction-pseudos
+///
+/// `:enabled` と `:disabled` はフォームの要素だけに一致する
+/// https://html.spec.whatwg.org/multipage/semantics-other.html#selector-enabled
+pub(super) fn matches_pseudo_class(elem: &ElementData, pseudo_class: PseudoClass) -> bool {
+    let state = elem.state;
+    match pseudo_class {
+        PseudoClass::Hover => state.hover,
+        PseudoClass::Active => state.active,
+        PseudoClass::Focus => state.focus,
+        PseudoClass::FocusVisible => state.focus && state.focus_visible,
+        PseudoClass::Checked => state.checked,
+        PseudoClass::Disabled => elem.is_disabled(),
+        PseudoClass::Enabled => elem.is_form_control() && !elem.is_disabled(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{css, dom};
+
+    use super::*;
+
+    fn element(tag_name: &str, class: &str) -> ElementData {
+        let attributes = [("class".to_string(), class.to_string())].into();
+        match dom::elem(tag_name.to_string(), attributes, Vec::new()).node_type {
+            dom::NodeType::Element(elem) => elem,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_selector_map() {
+        let stylesheet =
+            css::parse("* {} #x {} .a, p {} .b .a {} ul > .a {} div {} section .c {}".to_string());
+        let rules: Vec<_> = stylesheet
+            .rules
+            .iter()
+            .map(|rule| match rule {
+                css::CssRule::Style(rule) => rule,
+                _ => unreachable!(),
+            })
+            .collect();
+        let map = SelectorMap::new(rules.iter().copied());
+
+        let ul = element("ul", "b");
+        let p = element("p", "a");
+        let mut ancestors = Ancestors::default();
+        assert_eq!(
+            map.candidates(&ul, ancestors.filter()),
+            vec![(0, 0)],
+            "rules for other keys and rules requiring ancestors are skipped"
+        );
+
+        ancestors.push(&ul);
+        let candidates = map.candidates(&p, ancestors.filter());
+        assert_eq!(candidates, vec![(0, 0), (2, 0), (2, 1), (3, 0), (4, 0)]);
+        let matched: Vec<_> = candidates
+            .iter()
+            .filter(|&&(rule, selector)| matches(&p, &ancestors, &rules[rule].selectors[selector]))
+            .collect();
+        assert_eq!(matched, vec![&(0, 0), &(2, 0), &(2, 1), &(3, 0), &(4, 0)]);
+
+        ancestors.pop();
+        assert_eq!(
+            map.candidates(&p, ancestors.filter()),
+            vec![(0, 0), (2, 0), (2, 1)]
+        );
+        assert!(ancestors.filter.counters.iter().all(|&count| count == 0));
+    }
+
+    #[test]
+    fn test_combinators() {
+        let html = element("html", "");
+        let div = element("div", "a");
+        let p = element("p", "");
+        let mut ancestors = Ancestors::default();
+        ancestors.push(&html);
+        ancestors.push(&div);
+
+        let matches_p = |source: &str, ancestors: &Ancestors| {
+            let stylesheet = css::parse(format!("{} {{}}", source));
+            let css::CssRule::Style(ref rule) = stylesheet.rules[0] else {
+                unreachable!()
+            };
+            matches(&p, ancestors, &rule.selectors[0])
+        };
+        assert!(matches_p("html p", &ancestors));
+        assert!(matches_p("html .a > p", &ancestors));
+        assert!(matches_p("html > div > p", &ancestors));
+        assert!(!matches_p("html > p", &ancestors));
+        assert!(!matches_p(".a html p", &ancestors));
+
+        ancestors.pop();
+        assert!(!matches_p(".a p", &ancestors));
+    }
+}
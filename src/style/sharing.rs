@@ -0,0 +1,145 @@
+//! 兄弟要素どうしのスタイル共有
+//!
+//! 同じ親を持つ要素は先祖も同じなので、タグ名とクラスと状態と無効かどうかが同じで
+//! どちらもIDを持たなければ、一致する規則も、継承する値も同じになる。そのような兄弟には、先に求めたスタイルを
+//! そのまま使う。属性や兄弟の位置で一致が変わるセレクタを追加するときは、ここの
+//! 条件にもそれを加える
+//!
+//! https://www.w3.org/TR/css-cascade-4/#cascade
+
+use crate::dom::ElementData;
+
+use super::NodeStyle;
+
+/// 覚えておく兄弟要素の数
+const CACHE_SIZE: usize = 8;
+
+/// 同じ親を持つ要素の間で使うキャッシュ
+#[derive(Default)]
+pub(super) struct StyleSharingCache<'a> {
+    /// 新しいものほど後ろ
+    entries: Vec<(&'a ElementData, NodeStyle)>,
+}
+
+impl<'a> StyleSharingCache<'a> {
+    /// `elem` と同じスタイルになる兄弟要素があれば、そのスタイルを返す
+    pub(super) fn get(&mut self, elem: &ElementData) -> Option<NodeStyle> {
+        if !can_share(elem) {
+            return None;
+        }
+        let index = self
+            .entries
+            .iter()
+            .rposition(|&(candidate, _)| equivalent(candidate, elem))?;
+        // 使われたものを最も新しい位置に移す
+        let entry = self.entries.remove(index);
+        let style = entry.1.clone();
+        self.entries.push(entry);
+        Some(style)
+    }
+
+    pub(super) fn insert(&mut self, elem: &'a ElementData, style: &NodeStyle) {
+        if !can_share(elem) {
+            return;
+        }
+        if self.entries.len() == CACHE_SIZE {
+            self.entries.remove(0);
+        }
+        self.entries.push((elem, style.clone()));
+    }
+}
+
+/// ID セレクタはその要素にしか一致しないので、ID を持つ要素は共有しない
+fn can_share(elem: &ElementData) -> bool {
+    elem.id().is_none()
+}
+
+/// `:disabled` と `:enabled` は `disabled` 属性で決まる
+fn equivalent(a: &ElementData, b: &ElementData) -> bool {
+    a.tag_name == b.tag_name
+        && a.state == b.state
+        && a.is_disabled() == b.is_disabled()
+        && a.classes() == b.classes()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        css::{self, media::Device, properties::PropertyId, Color, Value},
+        html,
+        style::{style_tree_with_stats, StyleStats},
+    };
+
+    #[test]
+    fn test_style_sharing() {
+        let root_node = html::parse(
+            r#"<ul><li class="a b"><p></p></li><li class="b a"></li><li id="x" class="a b"></li><li class="c"></li><li class="a b"></li></ul>"#
+                .to_string(),
+        );
+        let stylesheets = [css::parse(
+            "#x { color: red; } .c { color: blue; } li p { width: 10px; }".to_string(),
+        )];
+        let device = Device::new(800.0, 600.0);
+        let (styled, stats) = style_tree_with_stats(&root_node, &stylesheets, &device);
+        assert_eq!(
+            stats,
+            StyleStats {
+                sharing_hits: 2,
+                sharing_misses: 5,
+                reused: 0,
+            }
+        );
+        assert_eq!(stats.sharing_hit_rate(), 2.0 / 7.0);
+
+        assert_eq!(
+            styled.children[1].specified_values,
+            styled.children[0].specified_values
+        );
+        let red = Value::ColorValue(Color {
+            r: 255,
+            g: 0,
+            b: 0,
+            a: 255,
+        });
+        assert_eq!(styled.children[2].value(PropertyId::Color), red);
+        assert_ne!(styled.children[4].value(PropertyId::Color), red);
+        assert_eq!(
+            styled.children[0].children[0].value(PropertyId::Width),
+            Value::Length(10.0, css::Unit::Px)
+        );
+    }
+
+    #[test]
+    fn test_disabled_siblings_are_not_shared() {
+        let stylesheets = [css::parse("input:disabled { color: red; }".to_string())];
+        let device = Device::new(800.0, 600.0);
+        let red = Value::ColorValue(Color {
+            r: 255,
+            g: 0,
+            b: 0,
+            a: 255,
+        });
+        for (html, disabled) in [
+            (
+                r#"<p><input class="a"></input><input class="a" disabled=""></input></p>"#,
+                1,
+            ),
+            (
+                r#"<p><input class="a" disabled=""></input><input class="a"></input></p>"#,
+                0,
+            ),
+        ] {
+            let root_node = html::parse(html.to_string());
+            let (styled, stats) = style_tree_with_stats(&root_node, &stylesheets, &device);
+            assert_eq!(stats.sharing_hits, 0, "{}", html);
+            for (i, child) in styled.children.iter().enumerate() {
+                assert_eq!(
+                    child.value(PropertyId::Color) == red,
+                    i == disabled,
+                    "{}",
+                    html
+                );
+            }
+        }
+    }
+}
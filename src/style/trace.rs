@@ -0,0 +1,345 @@
+//! 要素のスタイルがどの規則から来たかを調べる (開発者ツールのスタイル表示用)
+//!
+//! `getComputedStyle()` の結果に、一致した規則と競合した宣言の情報を加えたもの
+
+use std::{collections::HashMap, path::Path};
+
+use crate::{
+    css::{
+        media::Device,
+        properties::{PropertyId, PROPERTIES},
+        Declaration, Origin, Rule, Specificity, Stylesheet, Value,
+    },
+    dom::{Node, NodeType},
+};
+
+use super::{
+    cascade_declarations, cascade_rules, cascaded_value, cascaded_values, group_by_property,
+    matching::Ancestors, matching_rules, substitute_variables, user_agent_stylesheet, CascadeLevel,
+    CascadeRule, StyledNode,
+};
+
+#[derive(Debug)]
+pub struct StyleTrace<'a> {
+    /// 要素に一致した規則。カスケードの優先順位の昇順
+    pub matched_rules: Vec<MatchedRule<'a>>,
+    /// 登録されたすべてのプロパティ (`PROPERTIES` の順)
+    pub properties: Vec<PropertyTrace<'a>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MatchedRule<'a> {
+    pub rule: &'a Rule,
+    /// `rule.location` が指すスタイルシート
+    pub stylesheet: StylesheetSource<'a>,
+    pub origin: Origin,
+    /// 要素に一致したセレクタの詳細度
+    pub specificity: Specificity,
+}
+
+/// 規則を含むスタイルシート
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StylesheetSource<'a> {
+    UserAgent,
+    /// `trace` に渡した `stylesheets` のこの位置のもの
+    Index(usize),
+    /// `@import` で読み込んだもの。ファイルから読み込んでいなければパスは `None`
+    Import(Option<&'a Path>),
+}
+
+#[derive(Debug)]
+pub struct PropertyTrace<'a> {
+    pub id: PropertyId,
+    /// 継承と初期値を適用した後の値
+    pub value: Value,
+    /// このプロパティを指定した宣言。カスケードの優先順位の昇順
+    pub declarations: Vec<TracedDeclaration<'a>>,
+    pub source: ValueSource,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TracedDeclaration<'a> {
+    pub declaration: &'a Declaration,
+    pub rule: MatchedRule<'a>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueSource {
+    /// `declarations` のこの位置の宣言が勝った
+    Declaration(usize),
+    /// 勝った宣言がないか、その `var()` を置換できず、親から継承した
+    /// (ルート要素では初期値と同じ)
+    Inherited,
+    /// 勝った宣言がないか、その `var()` を置換できず、初期値になった
+    Initial,
+}
+
+impl<'a> StyledNode<'a> {
+    /// スタイルツリーの根 `self` から `node` を探し、その各プロパティの値と、
+    /// 値を決めた宣言を調べる。`node` がツリーになければ `None`
+    ///
+    /// `stylesheets` と `device` は `style_tree` に渡したものと同じにする
+    /// テキストノードには規則が一致しないので、値はすべて継承値か初期値になる
+    pub fn trace<'s>(
+        &self,
+        node: &Node,
+        stylesheets: &'s [Stylesheet],
+        device: &Device,
+    ) -> Option<StyleTrace<'s>> {
+        let path = self.path_to(node)?;
+        let (&styled, ancestor_nodes) = path.split_last().unwrap();
+        let mut ancestors = Ancestors::default();
+        for ancestor in ancestor_nodes {
+            if let NodeType::Element(ref elem) = ancestor.node.node_type {
+                ancestors.push(elem);
+            }
+        }
+
+        let rules = cascade_rules(stylesheets, device);
+        let (mut matched, declarations) = match styled.node.node_type {
+            NodeType::Element(ref elem) => (
+                matching_rules(elem, &ancestors, &rules, None),
+                cascade_declarations(elem, &ancestors, &rules, None),
+            ),
+            NodeType::Text(_) => (Vec::new(), Vec::new()),
+        };
+        // 安定ソートなので、優先順位が等しい規則はソース順に並ぶ
+        matched.sort_by_key(|&(specificity, rule)| {
+            (
+                CascadeLevel::new(rule.origin, false),
+                rule.layer,
+                specificity,
+            )
+        });
+        let mut groups = group_by_property(declarations);
+        // `var()` を置換できなかった宣言は指定されなかったものとして扱われる
+        let (mut values, custom_properties) = cascaded_values(&groups);
+        let no_custom_properties = HashMap::new();
+        let parent_custom_properties = match ancestor_nodes.last() {
+            Some(parent) => &parent.custom_properties,
+            None => &no_custom_properties,
+        };
+        substitute_variables(&mut values, custom_properties, parent_custom_properties);
+
+        let properties = PROPERTIES
+            .iter()
+            .map(|def| {
+                let candidates = groups.remove(def.name).unwrap_or_default();
+                let source = match cascaded_value(&candidates) {
+                    Some(winner) if values.contains_key(&def.id) => {
+                        ValueSource::Declaration(winner)
+                    }
+                    _ if def.inherited => ValueSource::Inherited,
+                    _ => ValueSource::Initial,
+                };
+                PropertyTrace {
+                    id: def.id,
+                    value: styled.value(def.id),
+                    declarations: candidates
+                        .into_iter()
+                        .map(|candidate| TracedDeclaration {
+                            declaration: candidate.declaration,
+                            rule: MatchedRule::new(
+                                candidate.rule,
+                                candidate.specificity,
+                                stylesheets,
+                            ),
+                        })
+                        .collect(),
+                    source,
+                }
+            })
+            .collect();
+
+        Some(StyleTrace {
+            matched_rules: matched
+                .into_iter()
+                .map(|(specificity, rule)| MatchedRule::new(rule, specificity, stylesheets))
+                .collect(),
+            properties,
+        })
+    }
+
+    /// 根から `node` までのスタイルノード
+    fn path_to(&self, node: &Node) -> Option<Vec<&StyledNode<'a>>> {
+        if std::ptr::eq(self.node, node) {
+            return Some(vec![self]);
+        }
+        self.children.iter().find_map(|child| {
+            let mut path = child.path_to(node)?;
+            path.insert(0, self);
+            Some(path)
+        })
+    }
+}
+
+impl<'a> MatchedRule<'a> {
+    fn new(rule: CascadeRule<'a>, specificity: Specificity, stylesheets: &'a [Stylesheet]) -> Self {
+        let stylesheet = if std::ptr::eq(rule.stylesheet, user_agent_stylesheet()) {
+            StylesheetSource::UserAgent
+        } else if let Some(i) = stylesheets
+            .iter()
+            .position(|stylesheet| std::ptr::eq(stylesheet, rule.stylesheet))
+        {
+            StylesheetSource::Index(i)
+        } else {
+            StylesheetSource::Import(rule.stylesheet.path.as_deref())
+        };
+        MatchedRule {
+            rule: rule.rule,
+            stylesheet,
+            origin: rule.origin,
+            specificity,
+        }
+    }
+}
+
+impl<'a> StyleTrace<'a> {
+    pub fn property(&self, id: PropertyId) -> &PropertyTrace<'a> {
+        self.properties
+            .iter()
+            .find(|property| property.id == id)
+            .expect("every registered property is traced")
+    }
+}
+
+impl<'a> PropertyTrace<'a> {
+    /// 勝った宣言
+    pub fn winner(&self) -> Option<&TracedDeclaration<'a>> {
+        match self.source {
+            ValueSource::Declaration(i) => Some(&self.declarations[i]),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        css::{self, SourceLocation, Unit},
+        html,
+        style::style_tree,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_trace() {
+        let root_node =
+            html::parse(r#"<div id="a" class="b"><p></p><span></span></div>"#.to_string());
+        let stylesheets = [css::parse(
+            r#"
+            #a { width: 10px; }
+            .b { width: 20px; color: red; }
+            div { width: 30px !important; }
+            .b > p { color: blue; }
+            "#
+            .to_string(),
+        )];
+        let device = Device::new(800.0, 600.0);
+        let styled = style_tree(&root_node, &stylesheets, &device);
+
+        let trace = styled.trace(&root_node, &stylesheets, &device).unwrap();
+        let matched: Vec<_> = trace
+            .matched_rules
+            .iter()
+            .filter(|matched| matched.origin == Origin::Author)
+            .map(|matched| (matched.rule.location.line, matched.specificity))
+            .collect();
+        assert_eq!(
+            matched,
+            vec![(4, (0, 0, 1)), (3, (0, 1, 0)), (2, (1, 0, 0))]
+        );
+
+        let width = trace.property(PropertyId::Width);
+        assert_eq!(width.value, Value::Length(30.0, Unit::Px));
+        let locations: Vec<_> = width
+            .declarations
+            .iter()
+            .map(|declaration| declaration.rule.rule.location)
+            .collect();
+        assert_eq!(
+            locations,
+            vec![
+                SourceLocation {
+                    line: 3,
+                    column: 13
+                },
+                SourceLocation {
+                    line: 2,
+                    column: 13
+                },
+                SourceLocation {
+                    line: 4,
+                    column: 13
+                },
+            ]
+        );
+        assert_eq!(width.source, ValueSource::Declaration(2));
+        assert!(width.winner().unwrap().declaration.important);
+
+        assert_eq!(
+            trace.property(PropertyId::Height).source,
+            ValueSource::Initial
+        );
+
+        let p = styled
+            .trace(&root_node.children[0], &stylesheets, &device)
+            .unwrap();
+        let color = p.property(PropertyId::Color);
+        assert_eq!(color.source, ValueSource::Declaration(0));
+        assert_eq!(color.winner().unwrap().rule.rule.location.line, 5);
+
+        let span = styled
+            .trace(&root_node.children[1], &stylesheets, &device)
+            .unwrap();
+        let color = span.property(PropertyId::Color);
+        assert_eq!(color.source, ValueSource::Inherited);
+        assert_eq!(color.value, trace.property(PropertyId::Color).value);
+
+        let other = html::parse("<p></p>".to_string());
+        assert!(styled.trace(&other, &stylesheets, &device).is_none());
+    }
+
+    #[test]
+    fn test_trace_sources() {
+        let root_node = html::parse("<p></p>".to_string());
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/test/import/main.css");
+        let stylesheets = [
+            css::import::load(path.as_ref(), Origin::Author).unwrap().0,
+            css::parse("p { color: var(--missing); width: var(--missing); }".to_string()),
+        ];
+        let device = Device::new(800.0, 600.0);
+        let styled = style_tree(&root_node, &stylesheets, &device);
+        let trace = styled.trace(&root_node, &stylesheets, &device).unwrap();
+
+        let display = trace.property(PropertyId::Display).winner().unwrap();
+        assert_eq!(display.rule.stylesheet, StylesheetSource::UserAgent);
+        let background = trace
+            .property(PropertyId::BackgroundColor)
+            .winner()
+            .unwrap();
+        match background.rule.stylesheet {
+            StylesheetSource::Import(Some(path)) => assert!(path.ends_with("parts/colors.css")),
+            source => panic!("not imported: {:?}", source),
+        }
+
+        // 置換できなかった `var()` は宣言が勝っても使われない
+        let color = trace.property(PropertyId::Color);
+        let sources: Vec<_> = color
+            .declarations
+            .iter()
+            .map(|declaration| declaration.rule.stylesheet)
+            .collect();
+        assert_eq!(
+            sources,
+            vec![StylesheetSource::Index(0), StylesheetSource::Index(1)]
+        );
+        assert_eq!(color.source, ValueSource::Inherited);
+        assert!(color.winner().is_none());
+        assert_eq!(
+            trace.property(PropertyId::Width).source,
+            ValueSource::Initial
+        );
+    }
+}
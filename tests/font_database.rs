@@ -0,0 +1,37 @@
+//! `font::set_database` で登録したフォントをスタイルの計算が使うことを確かめる
+//!
+//! フォントの一覧はプロセス全体で1つなので、ほかのテストと別のプロセスで実行する
+
+use toy_browser::{
+    css::{self, media::Device},
+    font::{self, Font, FontDatabase},
+    html,
+    style::style_tree,
+};
+
+#[test]
+fn test_registered_fonts() {
+    let mut mono = Font::load(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/fonts/Lato-Regular.ttf"
+    ))
+    .unwrap();
+    mono.family = "Mono".to_string();
+    mono.weight = 700.0;
+    let mut database = FontDatabase::new();
+    database.add(mono);
+    assert!(font::set_database(database).is_ok());
+
+    let root_node = html::parse("<div><p></p><b></b></div>".to_string());
+    let stylesheets = [css::parse(
+        "p { font-family: Missing, Mono; font-weight: bold; } b { font-family: serif; }"
+            .to_string(),
+    )];
+    let styled = style_tree(&root_node, &stylesheets, &Device::new(800.0, 600.0));
+    let p = styled.children[0].computed.font();
+    assert_eq!((&*p.family, p.weight), ("Mono", 700.0));
+    assert_eq!(styled.children[1].computed.font().family, "Lato");
+
+    // 使い始めた後は変えられない
+    assert!(font::set_database(FontDatabase::new()).is_err());
+}